@@ -14,7 +14,9 @@
 // limitations under the License.
 
 use dynamo_llm::kv_router::{
-    protocols::ForwardPassMetrics, scheduler::KVHitRateEvent, KV_HIT_RATE_SUBJECT,
+    protocols::{ForwardPassMetrics, WorkerRole},
+    scheduler::KVHitRateEvent,
+    KV_HIT_RATE_SUBJECT,
 };
 use dynamo_runtime::{
     component::{service::EndpointStats, Namespace},
@@ -78,7 +80,7 @@ async fn mock_event_publisher(namespace: Namespace) {
     // be reflective of the KV Router's performance.
     // 3. The data in these events aren't in sync with the stats handler's
     // ForwardPassMetrics data, so they may not correlate well.
-    let worker_id = rand::rng().random_range(1..=1000);
+    let worker_id: i64 = rand::rng().random_range(1..=1000);
 
     let mut interval = interval(Duration::from_secs(1));
     loop {
@@ -89,7 +91,7 @@ async fn mock_event_publisher(namespace: Namespace) {
         let overlap_blocks = rand::rng().random_range(0..=isl_blocks);
 
         let event = KVHitRateEvent {
-            worker_id,
+            worker_id: worker_id.into(),
             isl_blocks,
             overlap_blocks,
         };
@@ -123,6 +125,10 @@ fn mock_stats_handler(_stats: EndpointStats) -> serde_json::Value {
         num_requests_waiting,
         gpu_cache_usage_perc,
         gpu_prefix_cache_hit_rate,
+        role: WorkerRole::Unified,
+        kv_block_size: None,
+        max_concurrent: None,
+        oldest_waiting_age_ms: None,
     };
     tracing::info!("Stats: {stats:?}");
     serde_json::to_value(stats).unwrap()
@@ -197,7 +197,7 @@ async fn app(runtime: Runtime) -> Result<()> {
                             let mut metrics = metrics_collector_clone.lock().await;
                             metrics.update_kv_hit_rate(
                                 &config_clone,
-                                event.worker_id,
+                                event.worker_id.into(),
                                 event.isl_blocks,
                                 event.overlap_blocks,
                             );
@@ -62,7 +62,7 @@ impl KvRouter {
                 .schedule(&token_ids, lora_id)
                 .await
                 .map_err(to_pyerr)?;
-            Ok(worker_id)
+            Ok(worker_id.0)
         })
     }
 }
@@ -135,6 +135,10 @@ impl WorkerMetricsPublisher {
                     num_requests_waiting,
                     gpu_cache_usage_perc,
                     gpu_prefix_cache_hit_rate,
+                    role: llm_rs::kv_router::protocols::WorkerRole::Unified,
+                    kv_block_size: None,
+                    max_concurrent: None,
+                    oldest_waiting_age_ms: None,
                 }
                 .into(),
             )
@@ -358,8 +362,12 @@ pub(crate) struct OverlapScores {
 #[pymethods]
 impl OverlapScores {
     #[getter]
-    fn scores(&self) -> HashMap<llm_rs::kv_router::indexer::WorkerId, u32> {
-        self.inner.scores.clone()
+    fn scores(&self) -> HashMap<i64, f64> {
+        self.inner
+            .scores
+            .iter()
+            .map(|(worker_id, score)| (worker_id.0, *score))
+            .collect()
     }
 
     #[getter]
@@ -422,12 +430,12 @@ impl RadixTree {
     }
 
     fn remove_worker(&mut self, _py: Python, worker_id: i64) -> PyResult<()> {
-        self.inner.remove_worker(worker_id);
+        self.inner.remove_worker(worker_id.into());
         Ok(())
     }
 
     fn clear_all_blocks(&mut self, _py: Python, worker_id: i64) -> PyResult<()> {
-        self.inner.clear_all_blocks(worker_id);
+        self.inner.clear_all_blocks(worker_id.into());
         Ok(())
     }
 }
@@ -560,7 +568,7 @@ impl KvMetricsAggregator {
             .endpoints
             .iter()
             .map(|(worker_id, x)| EndpointKvMetrics {
-                worker_id: *worker_id,
+                worker_id: worker_id.0,
                 request_active_slots: x.data.request_active_slots,
                 request_total_slots: x.data.request_total_slots,
                 kv_active_blocks: x.data.kv_active_blocks,
@@ -0,0 +1,104 @@
+// SPDX-FileCopyrightText: Copyright (c) 2024-2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cost of [`DefaultWorkerSelector::score_and_select`] as the worker pool grows, and of
+//! encoding the hit rate event each selection publishes.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use dynamo_llm::kv_router::indexer::OverlapScores;
+use dynamo_llm::kv_router::protocols::WorkerId;
+use dynamo_llm::kv_router::scheduler::{
+    DefaultWorkerSelector, Endpoint, EventEncoding, ForwardPassMetrics, KVHitRateEvent,
+};
+use dynamo_llm::kv_router::scoring::ProcessedEndpoints;
+
+const BLOCK_SIZE: usize = 16;
+const ISL_TOKENS: usize = 2048;
+
+fn workers(count: i64) -> ProcessedEndpoints {
+    ProcessedEndpoints::new(
+        (1..=count)
+            .map(|id| Endpoint {
+                name: format!("worker-{id}"),
+                subject: format!("worker-subject-{id:x}"),
+                data: ForwardPassMetrics {
+                    kv_active_blocks: (id as u64 * 37) % 1000,
+                    kv_total_blocks: 1000,
+                    num_requests_waiting: id as u64 % 8,
+                    gpu_cache_usage_perc: ((id * 37) % 1000) as f32 / 1000.0,
+                    ..Default::default()
+                },
+            })
+            .collect(),
+    )
+}
+
+/// Overlap on every other worker, deepest on the lowest ids.
+fn overlap(count: i64) -> OverlapScores {
+    let blocks = (ISL_TOKENS / BLOCK_SIZE) as i64;
+    OverlapScores {
+        scores: (1..=count)
+            .step_by(2)
+            .map(|id| (WorkerId(id), (blocks - id).max(0) as f64))
+            .collect(),
+        ..Default::default()
+    }
+}
+
+fn bench_score_and_select(c: &mut Criterion) {
+    let selector = DefaultWorkerSelector::with_seed(None, 0);
+    let mut group = c.benchmark_group("score_and_select");
+    for count in [4, 16, 64, 256] {
+        let (workers, overlap) = (workers(count), overlap(count));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, _| {
+            b.iter(|| {
+                selector
+                    .score_and_select(
+                        black_box(&workers),
+                        black_box(&overlap),
+                        ISL_TOKENS,
+                        BLOCK_SIZE,
+                    )
+                    .expect("Should select a worker")
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_event_encoding(c: &mut Criterion) {
+    let event = KVHitRateEvent {
+        worker_id: WorkerId(0x7f3a_12c4),
+        isl_blocks: ISL_TOKENS / BLOCK_SIZE,
+        overlap_blocks: 96,
+    };
+    let mut group = c.benchmark_group("encode_hit_rate_event");
+    for (name, encoding) in [
+        ("json", EventEncoding::Json),
+        ("msgpack", EventEncoding::MessagePack),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                encoding
+                    .encode(black_box(&event))
+                    .expect("Should encode the event")
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_score_and_select, bench_event_encoding);
+criterion_main!(benches);
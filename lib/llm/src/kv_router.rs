@@ -1,6 +1,7 @@
 // SPDX-FileCopyrightText: Copyright (c) 2024-2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use anyhow::Result;
@@ -14,21 +15,32 @@ use dynamo_runtime::{
     protocols::annotated::Annotated,
 };
 use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
 
 pub mod indexer;
 pub mod metrics_aggregator;
+#[cfg(feature = "otel")]
+mod otel;
+pub mod pool;
 pub mod protocols;
 pub mod publisher;
 pub mod recorder;
 pub mod scheduler;
 pub mod scoring;
+#[cfg(feature = "tower")]
+pub mod service;
+pub mod simulation;
 
 use crate::{
     kv_router::{
         indexer::{KvIndexer, KvIndexerInterface, RouterEvent},
         metrics_aggregator::KvMetricsAggregator,
-        protocols::{LocalBlockHash, RouterRequest, RouterResponse, WorkerSelectionResult},
-        scheduler::{KvScheduler, KvSchedulerError, SchedulingRequest},
+        protocols::{
+            LocalBlockHash, RouterRequest, RouterResponse, WorkerId, WorkerSelectionResult,
+        },
+        scheduler::{
+            KvScheduler, KvSchedulerError, Priority, SchedulingRequest, SelectionExplanation,
+        },
         scoring::ProcessedEndpoints,
     },
     preprocessor::PreprocessedRequest,
@@ -42,21 +54,168 @@ use dynamo_runtime::traits::events::EventSubscriber;
 // this should be discovered from the component
 pub const KV_EVENT_SUBJECT: &str = "kv_events";
 pub const KV_HIT_RATE_SUBJECT: &str = "kv-hit-rate";
+pub const KV_WORKER_DRAINED_SUBJECT: &str = "kv-worker-drained";
+pub const KV_SCHEDULING_REJECTED_SUBJECT: &str = "kv-scheduling-rejected";
 pub const KV_METRICS_ENDPOINT: &str = "load_metrics";
+pub const KV_SCHEDULER_COMPONENT: &str = "kv-scheduler";
+pub const KV_SCHEDULER_STATE_ENDPOINT: &str = "scheduler_state";
 
 /// A trait that users can implement to define custom selection logic
 pub trait WorkerSelector {
+    /// The routing configuration this selector was built with, if any.
+    /// The scheduler falls back to it when it isn't given a configuration of its own.
+    fn kv_router_config(&self) -> Option<KvRouterConfig> {
+        None
+    }
+
+    /// Apply a configuration passed to [`KvScheduler::update_config`], from the next selection
+    /// on. Selectors that aren't configured by a [`KvRouterConfig`] ignore it.
+    fn set_kv_router_config(&mut self, _config: KvRouterConfig) {}
+
+    /// Select a worker for `request`. `block_size` is the default KV block size, for workers
+    /// that don't report their own in [`protocols::ForwardPassMetrics::kv_block_size`]. The
+    /// scheduler passes the request's own [`scheduler::SchedulingRequest::block_size`] here
+    /// when it has one.
     fn select_worker(
         &self,
         workers: &ProcessedEndpoints,
         request: &SchedulingRequest,
         block_size: usize,
     ) -> Result<WorkerSelectionResult, KvSchedulerError>;
+
+    /// Candidate workers for `request`, best first, with the logits they were ranked by.
+    /// Selectors that don't score workers rank only the worker they would select.
+    fn rank_workers(
+        &self,
+        workers: &ProcessedEndpoints,
+        request: &SchedulingRequest,
+        block_size: usize,
+    ) -> Result<Vec<(WorkerId, f64)>, KvSchedulerError> {
+        let selection = self.select_worker(workers, request, block_size)?;
+        Ok(vec![(selection.worker_id, 0.0)])
+    }
+
+    /// Select a worker as [`WorkerSelector::select_worker`] does, explaining how each candidate
+    /// was scored. Selectors that don't score workers explain nothing.
+    fn explain_selection(
+        &self,
+        workers: &ProcessedEndpoints,
+        request: &SchedulingRequest,
+        block_size: usize,
+    ) -> Result<(WorkerSelectionResult, SelectionExplanation), KvSchedulerError> {
+        let selection = self.select_worker(workers, request, block_size)?;
+        Ok((selection, SelectionExplanation::default()))
+    }
+}
+
+/// Selection logic that has to wait on something, such as a lookup in an external service.
+///
+/// Set through [`scheduler::KvSchedulerBuilder::async_selector`], it is awaited in place of
+/// [`WorkerSelector::select_worker`] for plain selections, bounded by
+/// [`KvRouterConfig::selection_timeout_ms`]. Speculative and explained requests still go through
+/// the scheduler's [`WorkerSelector`].
+#[async_trait]
+pub trait AsyncWorkerSelector: Send + Sync {
+    /// Select a worker for `request`, as [`WorkerSelector::select_worker`] does.
+    async fn select_worker(
+        &self,
+        workers: &ProcessedEndpoints,
+        request: &SchedulingRequest,
+        block_size: usize,
+    ) -> Result<WorkerSelectionResult, KvSchedulerError>;
+}
+
+/// How [`scheduler::DefaultWorkerSelector`] chooses among workers tied for the best logit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TieBreakPolicy {
+    /// At random, weighted by each worker's logit within the tie band
+    #[default]
+    Random,
+    /// The lowest worker id, so selections are reproducible
+    LowestId,
+    /// The worker with the fewest waiting requests, then the fewest active KV blocks, then the
+    /// lowest id
+    LeastLoaded,
+    /// The next tied worker id after the one last picked this way, wrapping around
+    RoundRobin,
+}
+
+/// Preset trade-off between cache locality and load balance, applied by
+/// [`KvRouterConfig::for_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutingMode {
+    /// Favor lightly loaded workers, even at the cost of cache reuse
+    LatencyOptimized,
+    /// Favor workers with the request's prefix cached, even if somewhat busier
+    ThroughputOptimized,
+    /// The default weights
+    #[default]
+    Balanced,
+}
+
+impl RoutingMode {
+    /// Overlap, GPU cache usage and waiting requests weights of the preset.
+    pub fn weights(self) -> (f64, f64, f64) {
+        match self {
+            RoutingMode::LatencyOptimized => (1.0, 1.5, 2.0),
+            RoutingMode::ThroughputOptimized => (4.0, 0.5, 0.5),
+            RoutingMode::Balanced => (2.0, 1.0, 1.0),
+        }
+    }
+}
+
+/// Curve applied to a worker's GPU cache usage before it is weighted into the logit. Every curve
+/// maps empty to 0 and full to 1, so `gpu_cache_usage_weight` keeps its scale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsagePenaltyCurve {
+    /// The usage itself
+    #[default]
+    Linear,
+    /// The square of the usage
+    Quadratic,
+    /// `(e^(k * usage) - 1) / (e^k - 1)` with k = [`UsagePenaltyCurve::EXPONENTIAL_STEEPNESS`],
+    /// nearly flat until usage gets high
+    Exponential,
+}
+
+impl UsagePenaltyCurve {
+    /// Steepness of [`UsagePenaltyCurve::Exponential`]
+    pub const EXPONENTIAL_STEEPNESS: f64 = 5.0;
+
+    /// The penalty for `usage`, a fraction from 0 to 1.
+    pub fn apply(self, usage: f64) -> f64 {
+        match self {
+            UsagePenaltyCurve::Linear => usage,
+            UsagePenaltyCurve::Quadratic => usage * usage,
+            UsagePenaltyCurve::Exponential => {
+                let k = Self::EXPONENTIAL_STEEPNESS;
+                (k * usage).exp_m1() / k.exp_m1()
+            }
+        }
+    }
+}
+
+/// Token bucket capping how fast one tenant can submit requests.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TenantRateLimit {
+    /// Rate the bucket refills at, in requests per second
+    pub requests_per_second: f64,
+    /// Capacity of the bucket: requests admitted at once after the tenant has been idle
+    pub burst: u32,
 }
 
 /// KV Router configuration parameters
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct KvRouterConfig {
+    /// Preset the overlap, GPU cache usage and waiting requests weights were taken from. When
+    /// parsing, the preset's weights stand in for any of the three left unspecified.
+    /// Default: None (the individual defaults)
+    pub routing_mode: Option<RoutingMode>,
+
     /// Weight for overlap score in worker selection.
     /// Higher values prioritize KV cache reuse. Default: 2.0
     pub overlap_score_weight: f64,
@@ -65,17 +224,349 @@ pub struct KvRouterConfig {
     /// Higher values avoid workers with nearly full KV caches. Default: 1.0
     pub gpu_cache_usage_weight: f64,
 
+    /// Curve the GPU cache usage is passed through before `gpu_cache_usage_weight` applies.
+    /// The steeper curves penalize workers near full disproportionately. Default: linear
+    pub gpu_cache_usage_curve: UsagePenaltyCurve,
+
     /// Weight for waiting requests in worker selection.
     /// Higher values avoid workers with queued requests. Default: 1.0
     pub waiting_requests_weight: f64,
+
+    /// Weight for observed throughput in worker selection.
+    /// The term is added to the logit, so higher values favor workers that complete requests
+    /// faster. Throughput is measured in completions per second and normalized by the fastest
+    /// worker, so the term ranges from 0 to the weight. Default: 0.0 (disabled)
+    pub throughput_weight: f64,
+
+    /// Weight for absolute free KV blocks (`kv_total_blocks - kv_active_blocks`).
+    /// The term is added to the logit and normalized by the worker with the most free blocks,
+    /// so it ranges from 0 to the weight. `gpu_cache_usage_weight` penalizes how full a cache is
+    /// relative to its size; this term instead favors workers with more raw headroom, which
+    /// separates workers at the same usage but with different capacities. Default: 0.0 (disabled)
+    pub free_blocks_weight: f64,
+
+    /// Weight for how long a worker's oldest waiting request has been queued
+    /// (`oldest_waiting_age_ms`). The term is subtracted from the logit and normalized by the
+    /// stalest queue among the candidates, so it ranges from 0 to the weight. Of two workers
+    /// with the same number of waiting requests, it steers away from one whose queue isn't
+    /// draining, such as a stuck worker. Workers that don't report an age aren't penalized.
+    /// Default: 0.0 (disabled)
+    pub queue_age_weight: f64,
+
+    /// Workers whose logit is within this margin of the best are treated as tied and chosen
+    /// between by `tie_break_policy`.
+    /// Default: 0.0 (only exact ties)
+    pub tie_break_epsilon: f64,
+
+    /// How a worker is chosen among tied ones. Ignored while `deterministic` is set, which
+    /// always picks the lowest worker id.
+    /// Default: random
+    pub tie_break_policy: TieBreakPolicy,
+
+    /// Route to draining workers when no other worker is available.
+    /// Default: false (fail the request instead)
+    pub allow_draining_fallback: bool,
+
+    /// Requests with more input tokens than this are rejected instead of routed.
+    /// Default: None (no limit)
+    pub max_isl_tokens: Option<usize>,
+
+    /// Break ties by lowest worker id, so selections are reproducible. Overrides
+    /// `tie_break_policy`, whatever it is set to.
+    /// Default: false
+    pub deterministic: bool,
+
+    /// Workers whose GPU cache usage exceeds this fraction are never selected, however high
+    /// their overlap. Unlike `gpu_cache_usage_weight` this is an absolute exclusion.
+    /// Default: 1.0 (no cap)
+    pub gpu_cache_usage_hard_cap: f64,
+
+    /// Requests carrying the same prefix hash within this many milliseconds of the first one are
+    /// steered to the worker it was routed to, so they share its cache.
+    /// Default: 0 (disabled)
+    pub prefix_coalesce_window_ms: u64,
+
+    /// Requests are rejected once worker metrics haven't been updated for this many
+    /// milliseconds, rather than routed on stale data.
+    /// Default: None (never stale)
+    pub max_metrics_staleness_ms: Option<u64>,
+
+    /// High priority requests are served first, but after this many in a row a waiting low
+    /// priority request is served so it isn't starved.
+    /// Default: 8
+    pub high_priority_burst: usize,
+
+    /// Number of overlap scores the scheduler caches by prefix hash. Requests whose prefix hash
+    /// is cached are scored with the cached overlap instead of the one they carry.
+    /// Default: 0 (disabled)
+    pub overlap_cache_capacity: usize,
+
+    /// How long cached overlap scores stay valid, in milliseconds. Default: 10000
+    pub overlap_cache_ttl_ms: u64,
+
+    /// Workers that join after the scheduler starts are penalized for this many milliseconds,
+    /// so they aren't flooded before their caches are warm.
+    /// Default: 0 (disabled)
+    pub warmup_duration_ms: u64,
+
+    /// Logit penalty for a worker that has just joined. It decays linearly to zero over
+    /// `warmup_duration_ms`. Default: 1.0
+    pub warmup_penalty: f64,
+
+    /// Logit penalty for a worker that was just selected. It decays exponentially, with a time
+    /// constant of `selection_cooldown_ms`, from the weight at the moment of selection, so a
+    /// burst of similar requests arriving faster than the metrics are polled spreads over the
+    /// next best workers instead of all landing on the first. Default: 0.0 (disabled)
+    pub selection_cooldown_weight: f64,
+
+    /// Time constant of the `selection_cooldown_weight` decay, in milliseconds. Default: 5
+    pub selection_cooldown_ms: u64,
+
+    /// Weight of the fairness penalty. A tenant with more than its fair share of recent
+    /// selections is steered away from the most selected workers in proportion to its excess.
+    /// Default: 0.0 (disabled)
+    pub fairness_weight: f64,
+
+    /// Window over which per-tenant selections are counted, in milliseconds. Default: 60000
+    pub fairness_window_ms: u64,
+
+    /// Speculative requests are also dispatched to the runner-up when its logit is within this
+    /// margin of the best worker's. Default: 0.1
+    pub speculative_margin: f64,
+
+    /// Half-life, in milliseconds, over which the predicted KV block load added since the last
+    /// metrics poll decays back toward the polled value. Polls still replace it outright.
+    /// Default: None (predictions persist until the next poll)
+    pub predictive_decay_half_life_ms: Option<u64>,
+
+    /// Requests of a session seen within this many milliseconds of its last request are routed
+    /// straight to the worker that served it, while that worker can still take them, so later
+    /// turns reuse the cache built by earlier ones.
+    /// Default: 0 (disabled)
+    pub session_ttl_ms: u64,
+
+    /// A session's worker is passed over for the session's next request while its predicted
+    /// KV block load exceeds this multiple of the pool average, and the session follows the
+    /// worker selected instead. Must be at least 1.
+    /// Default: None (sessions stay put however loaded their worker gets)
+    pub session_rebalance_factor: Option<f64>,
+
+    /// Target KV cache hit rate for adaptive overlap weighting. Every `adaptive_interval_ms`
+    /// the overlap weight is raised by `adaptive_overlap_step` while the hit rate over the
+    /// scheduler's hit rate window is below the target, and lowered by it while mean GPU cache
+    /// usage is above `adaptive_overload_usage`, staying within `adaptive_overlap_weight_min`
+    /// and `adaptive_overlap_weight_max`. A config update restarts tuning from its
+    /// `overlap_score_weight`.
+    /// Default: None (weights stay fixed)
+    pub adaptive_target_hit_rate: Option<f64>,
+
+    /// Amount the overlap weight moves per adjustment. Default: 0.1
+    pub adaptive_overlap_step: f64,
+
+    /// Lowest overlap weight adaptive tuning may set. Default: 0.5
+    pub adaptive_overlap_weight_min: f64,
+
+    /// Highest overlap weight adaptive tuning may set. Default: 8.0
+    pub adaptive_overlap_weight_max: f64,
+
+    /// Mean GPU cache usage above which workers count as overloaded. Default: 0.9
+    pub adaptive_overload_usage: f64,
+
+    /// Interval between adaptive adjustments, in milliseconds. Default: 10000
+    pub adaptive_interval_ms: u64,
+
+    /// Requests of a `load_shed_priorities` class are rejected with
+    /// [`KvSchedulerError::LoadShed`] while this many requests are queued or predicted in
+    /// flight, so overload sheds bulk work instead of growing every request's latency.
+    /// Default: None (never shed)
+    pub load_shed_high_water_mark: Option<usize>,
+
+    /// Priority classes shed above the high-water mark. Default: [low]
+    pub load_shed_priorities: Vec<Priority>,
+
+    /// How much an overlap's recency discounts it, from 0 to 1. At 0 every overlapping block
+    /// counts fully; at 1 a worker's overlap is its recency-weighted score, so blocks it hasn't
+    /// used lately count for little since they may have been evicted. Workers whose overlap
+    /// carries no recency data keep the flat block count.
+    /// Default: 0.0 (recency ignored)
+    pub recency_weight: f64,
+
+    /// Requests spanning fewer blocks than this (`isl_tokens / block_size`) ignore overlap and
+    /// route on load alone, since a match on a few tokens says little about cache reuse and
+    /// only makes routing sticky.
+    /// Default: 0 (overlap always counts)
+    pub min_blocks_for_kv_routing: usize,
+
+    /// Weight of queue fill in [`KvScheduler::pressure`]: requests queued or held, over the
+    /// capacity of a scheduling queue. Default: 1.0
+    pub pressure_queue_weight: f64,
+
+    /// Weight of mean GPU cache usage in [`KvScheduler::pressure`]. Default: 1.0
+    pub pressure_usage_weight: f64,
+
+    /// Weight of mean waiting requests in [`KvScheduler::pressure`], each worker's count
+    /// normalized by `pressure_waiting_saturation`. Default: 1.0
+    pub pressure_waiting_weight: f64,
+
+    /// Waiting requests at which a worker counts as fully loaded for
+    /// [`KvScheduler::pressure`]. Default: 8
+    pub pressure_waiting_saturation: u64,
+
+    /// Lower bound on the waiting requests count that `num_requests_waiting` is divided by to
+    /// normalize the waiting term, which is otherwise the most any worker has waiting. While the
+    /// busiest worker stays below the floor each worker's term depends only on its own count, so
+    /// a spike on one worker no longer rescales everyone else's, and a handful of waiting
+    /// requests doesn't read as a full queue.
+    /// Default: 0.0 (normalize by the busiest worker)
+    pub waiting_normalization_floor: f64,
+
+    /// Requests carrying an idempotency key already scheduled within this many milliseconds
+    /// get the same worker again, without another selection or predictive update, so a
+    /// client's retry isn't counted twice. Default: 5000
+    pub idempotency_window_ms: u64,
+
+    /// Requests are failed with [`KvSchedulerError::SelectionTimeout`] when an
+    /// [`AsyncWorkerSelector`] takes longer than this many milliseconds to select a worker, so a
+    /// hung lookup can't stall the scheduler. Selection by a [`WorkerSelector`] isn't bounded.
+    /// Default: None (no timeout)
+    pub selection_timeout_ms: Option<u64>,
+
+    /// Caps how far predictive increments can raise a worker's `num_requests_waiting` above its
+    /// last polled value, so a burst of selections between polls can't make a worker look busy
+    /// for the rest of the poll interval. Default: None (uncapped)
+    pub max_predictive_waiting_delta: Option<u64>,
+
+    /// Request rate limit per tenant id. Requests of a tenant over its limit are rejected with
+    /// [`KvSchedulerError::RateLimited`] before they are queued; tenants without a limit, and
+    /// requests without a tenant, are unrestricted. Default: {} (no limits)
+    pub tenant_rate_limits: HashMap<String, TenantRateLimit>,
+
+    /// Soft quota per tenant id, in selections per `quota_window_ms`. A tenant over its quota is
+    /// still served, but its preferred workers are penalized in proportion to its overage, so it
+    /// yields them to tenants within theirs. Default: {} (no quotas)
+    pub tenant_soft_quotas: HashMap<String, u64>,
+
+    /// Weight of the soft quota penalty. Default: 0.0 (disabled)
+    pub quota_penalty_weight: f64,
+
+    /// Window over which selections are counted against soft quotas, in milliseconds.
+    /// Default: 60000
+    pub quota_window_ms: u64,
+
+    /// Fewest KV blocks a request is charged, however short it is. Engines allocate at least a
+    /// minimum number of blocks per sequence, so shorter requests are scored and predictively
+    /// accounted as if they spanned this many. Default: 1
+    pub min_blocks_per_request: u64,
+
+    /// While no worker has registered, hold requests until one does instead of rejecting them
+    /// with [`KvSchedulerError::NoEndpoints`], as is done when every worker is busy.
+    /// Default: false (fail fast)
+    pub wait_for_first_worker: bool,
+
+    /// Percentile, between 0 and 1, of each worker's last `waiting_percentile_window` polled
+    /// `num_requests_waiting` samples to score the waiting term on, so one spiky poll doesn't
+    /// swing routing. Default: None (latest sample only)
+    pub waiting_percentile: Option<f64>,
+
+    /// Polled samples per worker [`KvRouterConfig::waiting_percentile`] is taken over.
+    /// Default: 10
+    pub waiting_percentile_window: usize,
+
+    /// Times selection is retried when there are no eligible workers, as while a rolling restart
+    /// briefly empties the pool, before the request fails with
+    /// [`KvSchedulerError::NoEndpoints`]. Unlike the wait for a busy worker this is bounded.
+    /// Default: 0 (fail on the first attempt)
+    pub max_no_endpoints_retries: u32,
+
+    /// Wait before the first [`KvRouterConfig::max_no_endpoints_retries`] retry, doubling with
+    /// each one after. A change of the endpoints cuts the wait short. Default: 10
+    pub no_endpoints_retry_backoff_ms: u64,
+
+    /// Least time between reads of the endpoints, in milliseconds. Updates arriving sooner are
+    /// coalesced and read once the interval has passed, so a chatty metrics publisher doesn't
+    /// cost a copy of every worker's state per update. Updates are still read as they arrive
+    /// while requests are waiting for a busy worker.
+    /// Default: None (read every update)
+    pub endpoints_debounce_ms: Option<u64>,
+
+    /// Recent pairs of consecutive selections within a request stream that
+    /// [`scheduler::KvScheduler::oscillation_rate`] is measured over. Default: 100
+    pub oscillation_window: usize,
+
+    /// Oscillation rate above which a warning is logged, once the window is full. Weights that
+    /// ping-pong a steady workload between workers show up as a rate near 1. Default: 0.5
+    pub oscillation_warn_threshold: f64,
+
+    /// Weight of the penalty on workers far from the zone a request originates from, per the
+    /// [`scheduler::Topology`] given to the scheduler. Requests without a zone, and workers
+    /// without a known distance to it, are not penalized. Default: 0.0 (disabled)
+    pub locality_weight: f64,
 }
 
 impl Default for KvRouterConfig {
     fn default() -> Self {
         Self {
+            routing_mode: None,
             overlap_score_weight: 2.0,
             gpu_cache_usage_weight: 1.0,
+            gpu_cache_usage_curve: UsagePenaltyCurve::Linear,
             waiting_requests_weight: 1.0,
+            throughput_weight: 0.0,
+            free_blocks_weight: 0.0,
+            queue_age_weight: 0.0,
+            tie_break_epsilon: 0.0,
+            tie_break_policy: TieBreakPolicy::Random,
+            allow_draining_fallback: false,
+            max_isl_tokens: None,
+            deterministic: false,
+            gpu_cache_usage_hard_cap: 1.0,
+            prefix_coalesce_window_ms: 0,
+            max_metrics_staleness_ms: None,
+            high_priority_burst: 8,
+            overlap_cache_capacity: 0,
+            overlap_cache_ttl_ms: 10_000,
+            warmup_duration_ms: 0,
+            warmup_penalty: 1.0,
+            selection_cooldown_weight: 0.0,
+            selection_cooldown_ms: 5,
+            fairness_weight: 0.0,
+            fairness_window_ms: 60_000,
+            speculative_margin: 0.1,
+            predictive_decay_half_life_ms: None,
+            session_ttl_ms: 0,
+            session_rebalance_factor: None,
+            adaptive_target_hit_rate: None,
+            adaptive_overlap_step: 0.1,
+            adaptive_overlap_weight_min: 0.5,
+            adaptive_overlap_weight_max: 8.0,
+            adaptive_overload_usage: 0.9,
+            adaptive_interval_ms: 10_000,
+            load_shed_high_water_mark: None,
+            load_shed_priorities: vec![Priority::Low],
+            recency_weight: 0.0,
+            min_blocks_for_kv_routing: 0,
+            pressure_queue_weight: 1.0,
+            pressure_usage_weight: 1.0,
+            pressure_waiting_weight: 1.0,
+            pressure_waiting_saturation: 8,
+            waiting_normalization_floor: 0.0,
+            idempotency_window_ms: 5_000,
+            selection_timeout_ms: None,
+            max_predictive_waiting_delta: None,
+            tenant_rate_limits: HashMap::new(),
+            tenant_soft_quotas: HashMap::new(),
+            quota_penalty_weight: 0.0,
+            quota_window_ms: 60_000,
+            min_blocks_per_request: 1,
+            wait_for_first_worker: false,
+            waiting_percentile: None,
+            waiting_percentile_window: 10,
+            max_no_endpoints_retries: 0,
+            no_endpoints_retry_backoff_ms: 10,
+            endpoints_debounce_ms: None,
+            oscillation_window: 100,
+            oscillation_warn_threshold: 0.5,
+            locality_weight: 0.0,
         }
     }
 }
@@ -95,8 +586,212 @@ impl KvRouterConfig {
                 .unwrap_or(default.gpu_cache_usage_weight),
             waiting_requests_weight: waiting_requests_weight
                 .unwrap_or(default.waiting_requests_weight),
+            ..default
         }
     }
+
+    /// Check that every weight is finite and non-negative, and that limits are non-zero.
+    pub fn validate(&self) -> Result<()> {
+        let weights = [
+            ("overlap_score_weight", self.overlap_score_weight),
+            ("gpu_cache_usage_weight", self.gpu_cache_usage_weight),
+            ("waiting_requests_weight", self.waiting_requests_weight),
+            ("throughput_weight", self.throughput_weight),
+            ("free_blocks_weight", self.free_blocks_weight),
+            ("queue_age_weight", self.queue_age_weight),
+            ("tie_break_epsilon", self.tie_break_epsilon),
+            ("warmup_penalty", self.warmup_penalty),
+            ("selection_cooldown_weight", self.selection_cooldown_weight),
+            ("fairness_weight", self.fairness_weight),
+            ("speculative_margin", self.speculative_margin),
+            ("adaptive_overlap_step", self.adaptive_overlap_step),
+            (
+                "adaptive_overlap_weight_min",
+                self.adaptive_overlap_weight_min,
+            ),
+            (
+                "adaptive_overlap_weight_max",
+                self.adaptive_overlap_weight_max,
+            ),
+            ("pressure_queue_weight", self.pressure_queue_weight),
+            ("pressure_usage_weight", self.pressure_usage_weight),
+            ("pressure_waiting_weight", self.pressure_waiting_weight),
+            (
+                "waiting_normalization_floor",
+                self.waiting_normalization_floor,
+            ),
+            ("locality_weight", self.locality_weight),
+            ("quota_penalty_weight", self.quota_penalty_weight),
+        ];
+        for (name, value) in weights {
+            if !value.is_finite() || value < 0.0 {
+                anyhow::bail!("{name} must be a finite, non-negative number; got {value}");
+            }
+        }
+        if !(0.0..=1.0).contains(&self.gpu_cache_usage_hard_cap) {
+            anyhow::bail!(
+                "gpu_cache_usage_hard_cap must be between 0 and 1; got {}",
+                self.gpu_cache_usage_hard_cap
+            );
+        }
+        if self.high_priority_burst == 0 {
+            anyhow::bail!("high_priority_burst must be greater than zero");
+        }
+        if self.max_isl_tokens == Some(0) {
+            anyhow::bail!("max_isl_tokens must be greater than zero");
+        }
+        if self.predictive_decay_half_life_ms == Some(0) {
+            anyhow::bail!("predictive_decay_half_life_ms must be greater than zero");
+        }
+        if let Some(target) = self.adaptive_target_hit_rate {
+            if !(0.0..=1.0).contains(&target) {
+                anyhow::bail!("adaptive_target_hit_rate must be between 0 and 1; got {target}");
+            }
+        }
+        if !(0.0..=1.0).contains(&self.recency_weight) {
+            anyhow::bail!(
+                "recency_weight must be between 0 and 1; got {}",
+                self.recency_weight
+            );
+        }
+        if !(0.0..=1.0).contains(&self.adaptive_overload_usage) {
+            anyhow::bail!(
+                "adaptive_overload_usage must be between 0 and 1; got {}",
+                self.adaptive_overload_usage
+            );
+        }
+        if self.adaptive_overlap_weight_min > self.adaptive_overlap_weight_max {
+            anyhow::bail!(
+                "adaptive_overlap_weight_min must not exceed adaptive_overlap_weight_max; got {} > {}",
+                self.adaptive_overlap_weight_min,
+                self.adaptive_overlap_weight_max
+            );
+        }
+        if self.pressure_waiting_saturation == 0 {
+            anyhow::bail!("pressure_waiting_saturation must be greater than zero");
+        }
+        if self.adaptive_interval_ms == 0 {
+            anyhow::bail!("adaptive_interval_ms must be greater than zero");
+        }
+        if self.selection_timeout_ms == Some(0) {
+            anyhow::bail!("selection_timeout_ms must be greater than zero");
+        }
+        if let Some(percentile) = self.waiting_percentile {
+            if !(0.0..=1.0).contains(&percentile) {
+                anyhow::bail!("waiting_percentile must be between 0 and 1; got {percentile}");
+            }
+        }
+        if self.waiting_percentile_window == 0 {
+            anyhow::bail!("waiting_percentile_window must be greater than zero");
+        }
+        if self.no_endpoints_retry_backoff_ms == 0 {
+            anyhow::bail!("no_endpoints_retry_backoff_ms must be greater than zero");
+        }
+        if self.oscillation_window == 0 {
+            anyhow::bail!("oscillation_window must be greater than zero");
+        }
+        if !(0.0..=1.0).contains(&self.oscillation_warn_threshold) {
+            anyhow::bail!(
+                "oscillation_warn_threshold must be between 0 and 1; got {}",
+                self.oscillation_warn_threshold
+            );
+        }
+        if self.endpoints_debounce_ms == Some(0) {
+            anyhow::bail!("endpoints_debounce_ms must be greater than zero");
+        }
+        if let Some(factor) = self.session_rebalance_factor {
+            if !factor.is_finite() || factor < 1.0 {
+                anyhow::bail!(
+                    "session_rebalance_factor must be a finite number of at least 1; got {factor}"
+                );
+            }
+        }
+        if self.min_blocks_per_request == 0 {
+            anyhow::bail!("min_blocks_per_request must be greater than zero");
+        }
+        if self.quota_window_ms == 0 {
+            anyhow::bail!("quota_window_ms must be greater than zero");
+        }
+        if self.selection_cooldown_ms == 0 {
+            anyhow::bail!("selection_cooldown_ms must be greater than zero");
+        }
+        for (tenant_id, quota) in &self.tenant_soft_quotas {
+            if *quota == 0 {
+                anyhow::bail!("soft quota of tenant {tenant_id} must be greater than zero");
+            }
+        }
+        for (tenant_id, limit) in &self.tenant_rate_limits {
+            if !limit.requests_per_second.is_finite() || limit.requests_per_second <= 0.0 {
+                anyhow::bail!(
+                    "requests_per_second of tenant {tenant_id} must be a finite, positive number; got {}",
+                    limit.requests_per_second
+                );
+            }
+            if limit.burst == 0 {
+                anyhow::bail!("burst of tenant {tenant_id} must be greater than zero");
+            }
+        }
+        Ok(())
+    }
+
+    /// The default configuration with the weights of `mode`. Explicit weights can still be set
+    /// on top with struct update syntax.
+    pub fn for_mode(mode: RoutingMode) -> Self {
+        let (overlap_score_weight, gpu_cache_usage_weight, waiting_requests_weight) =
+            mode.weights();
+        Self {
+            routing_mode: Some(mode),
+            overlap_score_weight,
+            gpu_cache_usage_weight,
+            waiting_requests_weight,
+            ..Default::default()
+        }
+    }
+
+    /// Parse and validate a configuration from JSON. Missing fields take their default values,
+    /// or the `routing_mode` preset's for its weights.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(json)
+            .map_err(|e| anyhow::anyhow!("invalid KV router config: {e}"))?;
+        Self::from_value(value)
+    }
+
+    /// Deserialize and validate `value`, with the fields it leaves out taken from its
+    /// `routing_mode` preset if it names one.
+    fn from_value(value: serde_json::Value) -> Result<Self> {
+        let mode = value
+            .get("routing_mode")
+            .and_then(|mode| serde_json::from_value::<RoutingMode>(mode.clone()).ok());
+        let value = match (mode, value) {
+            (Some(mode), serde_json::Value::Object(explicit)) => {
+                let mut merged = match serde_json::to_value(Self::for_mode(mode))? {
+                    serde_json::Value::Object(preset) => preset,
+                    _ => unreachable!("a config always serializes to an object"),
+                };
+                merged.extend(explicit);
+                serde_json::Value::Object(merged)
+            }
+            (_, value) => value,
+        };
+        let config: Self = serde_json::from_value(value)
+            .map_err(|e| anyhow::anyhow!("invalid KV router config: {e}"))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Serialize the configuration to JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Parse and validate a configuration from YAML. Missing fields take their default values,
+    /// or the `routing_mode` preset's for its weights.
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml(yaml: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_yaml::from_str(yaml)
+            .map_err(|e| anyhow::anyhow!("invalid KV router config: {e}"))?;
+        Self::from_value(value)
+    }
 }
 
 /// A KvRouter only decides which worker you should use. It doesn't send you there.
@@ -160,7 +855,7 @@ impl KvRouter {
     }
 
     // [TODO] indexer needs to take 'lora_id' as parameter
-    pub async fn schedule(&self, token_ids: &Vec<u32>, _lora_id: u64) -> Result<i64> {
+    pub async fn schedule(&self, token_ids: &Vec<u32>, _lora_id: u64) -> Result<WorkerId> {
         // Extracting part of the code in KvRouter::generate() for only
         // the decision making part, routing is done by the caller
         let isl_tokens = token_ids.len();
@@ -175,7 +870,7 @@ impl KvRouter {
 
     /// Give these tokens, find the worker with the best match in it's KV cache.
     /// Returned overlap amount is in number of blocks.
-    async fn find_best_match(&self, tokens: &[u32]) -> anyhow::Result<(i64, u32)> {
+    async fn find_best_match(&self, tokens: &[u32]) -> anyhow::Result<(WorkerId, u32)> {
         let isl_tokens = tokens.len();
         let block_size = self.block_size;
 
@@ -191,7 +886,7 @@ impl KvRouter {
             .scheduler
             .schedule(overlap_scores.clone(), isl_tokens)
             .await?;
-        let overlap_amount = overlap_scores.scores.get(&worker_id).copied().unwrap_or(0);
+        let overlap_amount = overlap_scores.block_count(&worker_id);
         Ok((worker_id, overlap_amount))
     }
 
@@ -210,7 +905,9 @@ impl AsyncEngine<SingleIn<RouterRequest>, ManyOut<Annotated<RouterResponse>>, Er
         let (request, ctx) = request.into_parts();
         let (worker_id, _) = self.find_best_match(&request.tokens).await?;
 
-        let response = RouterResponse { worker_id };
+        let response = RouterResponse {
+            worker_id: worker_id.into(),
+        };
         let response = Annotated::from_data(response);
         let stream = stream::iter(vec![response]);
         Ok(ResponseStream::new(Box::pin(stream), ctx.context()))
@@ -248,8 +945,93 @@ impl AsyncEngine<SingleIn<PreprocessedRequest>, ManyOut<Annotated<LLMEngineOutpu
                 let (mut backend_input, context) = request.into_parts();
                 backend_input.estimated_prefix_hit_num_blocks = Some(overlap_amount);
                 let updated_request = context.map(|_| backend_input);
-                self.inner.direct(updated_request, instance_id).await
+                self.inner.direct(updated_request, instance_id.into()).await
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_from_json() {
+        let config = KvRouterConfig::from_json(
+            r#"{"overlap_score_weight": 3.0, "gpu_cache_usage_weight": 0.5}"#,
+        )
+        .unwrap();
+        assert_eq!(config.overlap_score_weight, 3.0);
+        assert_eq!(config.gpu_cache_usage_weight, 0.5);
+        // Unspecified fields keep their defaults
+        assert_eq!(
+            config.waiting_requests_weight,
+            KvRouterConfig::default().waiting_requests_weight
+        );
+    }
+
+    #[test]
+    fn test_config_json_round_trip() {
+        let config = KvRouterConfig {
+            overlap_score_weight: 1.5,
+            throughput_weight: 0.25,
+            tie_break_epsilon: 0.01,
+            allow_draining_fallback: true,
+            ..Default::default()
+        };
+        let json = config.to_json().unwrap();
+        assert_eq!(KvRouterConfig::from_json(&json).unwrap(), config);
+    }
+
+    #[test]
+    fn test_config_routing_mode() {
+        let config = KvRouterConfig::from_json(
+            r#"{"routing_mode": "latency_optimized", "overlap_score_weight": 3.0}"#,
+        )
+        .unwrap();
+        assert_eq!(config.routing_mode, Some(RoutingMode::LatencyOptimized));
+        // The explicit weight overrides the preset's; the others come from the preset
+        assert_eq!(config.overlap_score_weight, 3.0);
+        assert_eq!(config.gpu_cache_usage_weight, 1.5);
+        assert_eq!(config.waiting_requests_weight, 2.0);
+
+        let config = KvRouterConfig::for_mode(RoutingMode::ThroughputOptimized);
+        let json = config.to_json().unwrap();
+        assert_eq!(KvRouterConfig::from_json(&json).unwrap(), config);
+        assert_eq!(
+            KvRouterConfig::for_mode(RoutingMode::Balanced),
+            KvRouterConfig {
+                routing_mode: Some(RoutingMode::Balanced),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_config_unknown_field() {
+        let err = KvRouterConfig::from_json(r#"{"overlap_weight": 3.0}"#).unwrap_err();
+        assert!(err.to_string().contains("unknown field"), "{err}");
+    }
+
+    #[test]
+    fn test_config_out_of_range() {
+        let err = KvRouterConfig::from_json(r#"{"gpu_cache_usage_weight": -1.0}"#).unwrap_err();
+        assert!(err.to_string().contains("gpu_cache_usage_weight"), "{err}");
+
+        let err = KvRouterConfig::from_json(r#"{"gpu_cache_usage_hard_cap": 1.5}"#).unwrap_err();
+        assert!(
+            err.to_string().contains("gpu_cache_usage_hard_cap"),
+            "{err}"
+        );
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_config_from_yaml() {
+        let config = KvRouterConfig::from_yaml("overlap_score_weight: 3.0\n").unwrap();
+        assert_eq!(config.overlap_score_weight, 3.0);
+
+        assert!(KvRouterConfig::from_yaml("overlap_weight: 3.0\n").is_err());
+        assert!(KvRouterConfig::from_yaml("tie_break_epsilon: -0.5\n").is_err());
+    }
+}
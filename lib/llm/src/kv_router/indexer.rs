@@ -77,8 +77,7 @@ pub enum KvRouterError {
     IndexerDroppedRequest,
 }
 
-/// Identifier of a LLM worker which emits events to the router.
-pub type WorkerId = i64;
+pub use super::protocols::WorkerId;
 
 /// A shared reference to a [`RadixBlock`].
 type SharedRadixBlock = Rc<RefCell<RadixBlock>>;
@@ -153,8 +152,11 @@ impl RouterEvent {
     /// ### Returns
     ///
     /// A new `RouterEvent`.
-    pub fn new(worker_id: WorkerId, event: KvCacheEvent) -> Self {
-        Self { worker_id, event }
+    pub fn new(worker_id: impl Into<WorkerId>, event: KvCacheEvent) -> Self {
+        Self {
+            worker_id: worker_id.into(),
+            event,
+        }
     }
 }
 
@@ -406,12 +408,19 @@ impl RadixTree {
 }
 
 /// Scores representing the overlap of workers.
+///
+/// Scores are expressed in (possibly fractional) blocks so that indexers can report partial or
+/// recency-weighted matches. Integer block counts convert losslessly via [`OverlapScores::from_block_counts`].
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OverlapScores {
-    // map of worker_id to score
-    pub scores: HashMap<WorkerId, u32>,
+    // map of worker_id to score, in blocks
+    pub scores: HashMap<WorkerId, f64>,
     // List of frequencies that the blocks have been accessed. Entries with value 0 are omitted.
     pub frequencies: Vec<usize>,
+    // map of worker_id to score with each block weighted by its recency, from 0 (cold) to 1 (just
+    // used). Empty when the producer doesn't track recency.
+    #[serde(default)]
+    pub recency_scores: HashMap<WorkerId, f64>,
 }
 
 impl Default for OverlapScores {
@@ -430,6 +439,7 @@ impl OverlapScores {
         Self {
             scores: HashMap::new(),
             frequencies: Vec::with_capacity(32),
+            recency_scores: HashMap::new(),
         }
     }
 
@@ -440,11 +450,51 @@ impl OverlapScores {
     /// * `workers` - A reference to a `HashSet` of `WorkerId`s.
     pub fn update_scores(&mut self, workers: &HashSet<WorkerId>) {
         for worker in workers {
-            let score = self.scores.entry(*worker).or_insert(0);
-            *score += 1;
+            let score = self.scores.entry(*worker).or_insert(0.0);
+            *score += 1.0;
+        }
+    }
+
+    /// Add a matched block with the given `recency` (0 to 1) to the recency-weighted scores of a
+    /// set of workers.
+    pub fn update_recency(&mut self, workers: &HashSet<WorkerId>, recency: f64) {
+        for worker in workers {
+            let score = self.recency_scores.entry(*worker).or_insert(0.0);
+            *score += recency.clamp(0.0, 1.0);
         }
     }
 
+    /// The overlap for `worker`, discounted toward its recency-weighted score by
+    /// `recency_weight`: 0 gives the flat block count and 1 the recency-weighted score alone.
+    /// Workers without recency data keep their flat count.
+    pub fn weighted_score(&self, worker: &WorkerId, recency_weight: f64) -> Option<f64> {
+        let flat = *self.scores.get(worker)?;
+        Some(match self.recency_scores.get(worker) {
+            Some(recent) => flat - recency_weight * (flat - recent),
+            None => flat,
+        })
+    }
+
+    /// Create an `OverlapScores` from integer block counts, for producers that only report
+    /// whole-block matches.
+    pub fn from_block_counts(counts: HashMap<WorkerId, u32>) -> Self {
+        Self {
+            scores: counts
+                .into_iter()
+                .map(|(worker, count)| (worker, count as f64))
+                .collect(),
+            ..Self::new()
+        }
+    }
+
+    /// The overlap for `worker` rounded to the nearest whole block.
+    pub fn block_count(&self, worker: &WorkerId) -> u32 {
+        self.scores
+            .get(worker)
+            .map(|score| score.round().max(0.0) as u32)
+            .unwrap_or(0)
+    }
+
     /// Add an entry in the frequency list.
     pub fn add_frequency(&mut self, frequency: usize) {
         if frequency != 0 {
@@ -456,6 +506,12 @@ impl OverlapScores {
     }
 }
 
+impl From<HashMap<WorkerId, u32>> for OverlapScores {
+    fn from(counts: HashMap<WorkerId, u32>) -> Self {
+        Self::from_block_counts(counts)
+    }
+}
+
 /// A request to find matches in the Radix Tree.
 pub struct MatchRequest {
     /// A vector of `LocalBlockHash` representing the sequence to match.
@@ -976,8 +1032,8 @@ mod tests {
 
         let mut trie = RadixTree::new();
 
-        let worker_1 = 0;
-        let worker_2 = 1;
+        let worker_1 = WorkerId(0);
+        let worker_2 = WorkerId(1);
 
         trie.apply_event(create_store_event(worker_1, 1, vec![1, 2, 3], None));
 
@@ -985,7 +1041,7 @@ mod tests {
             vec![LocalBlockHash(1), LocalBlockHash(2), LocalBlockHash(3)],
             false,
         );
-        assert_eq!(scores.scores.get(&worker_1).unwrap(), &3);
+        assert_eq!(scores.scores.get(&worker_1).unwrap(), &3.0);
 
         assert_eq!(trie.lookup.len(), 1);
         assert_eq!(trie.lookup.get(&worker_1).unwrap().len(), 3);
@@ -1020,8 +1076,8 @@ mod tests {
             vec![LocalBlockHash(1), LocalBlockHash(2), LocalBlockHash(3)],
             false,
         );
-        assert_eq!(scores.scores.get(&worker_1).unwrap(), &3);
-        assert_eq!(scores.scores.get(&worker_2).unwrap(), &1);
+        assert_eq!(scores.scores.get(&worker_1).unwrap(), &3.0);
+        assert_eq!(scores.scores.get(&worker_2).unwrap(), &1.0);
 
         assert_eq!(trie.lookup.len(), 2);
         assert_eq!(trie.lookup.get(&worker_1).unwrap().len(), 3);
@@ -1121,8 +1177,8 @@ mod tests {
             vec![LocalBlockHash(1), LocalBlockHash(2), LocalBlockHash(3)],
             false,
         );
-        assert_eq!(scores.scores.get(&worker_1).unwrap(), &3);
-        assert_eq!(scores.scores.get(&worker_2).unwrap(), &2);
+        assert_eq!(scores.scores.get(&worker_1).unwrap(), &3.0);
+        assert_eq!(scores.scores.get(&worker_2).unwrap(), &2.0);
 
         assert_eq!(trie.lookup.len(), 2);
         assert_eq!(trie.lookup.get(&worker_1).unwrap().len(), 3);
@@ -1180,8 +1236,8 @@ mod tests {
         setup();
         let mut trie = RadixTree::new();
 
-        let worker_0 = 0;
-        let worker_1 = 1;
+        let worker_0 = WorkerId(0);
+        let worker_1 = WorkerId(1);
 
         assert!(trie
             .find_matches(vec![LocalBlockHash(0)], false)
@@ -1192,20 +1248,20 @@ mod tests {
         trie.apply_event(create_store_event(worker_1, 0, vec![0], None));
 
         let result = trie.find_matches(vec![LocalBlockHash(0)], false).scores;
-        assert!(result.len() == 2 && result[&worker_0] == 1 && result[&worker_1] == 1);
+        assert!(result.len() == 2 && result[&worker_0] == 1.0 && result[&worker_1] == 1.0);
 
         trie.remove_worker(worker_0);
 
         let result = trie.find_matches(vec![LocalBlockHash(0)], false).scores;
-        assert!(result.len() == 1 && result[&worker_1] == 1);
+        assert!(result.len() == 1 && result[&worker_1] == 1.0);
     }
 
     #[test]
     fn test_clear_all_blocks() {
         let mut trie = RadixTree::new();
 
-        let worker_0 = 0;
-        let worker_1 = 1;
+        let worker_0 = WorkerId(0);
+        let worker_1 = WorkerId(1);
 
         assert!(trie
             .find_matches(vec![LocalBlockHash(0)], false)
@@ -1221,7 +1277,7 @@ mod tests {
         trie.apply_event(create_store_event(worker_1, 0, vec![0, 2, 3], None));
 
         let result = trie.find_matches(vec![LocalBlockHash(0)], false).scores;
-        assert!(result.len() == 2 && result[&worker_0] == 1 && result[&worker_1] == 1);
+        assert!(result.len() == 2 && result[&worker_0] == 1.0 && result[&worker_1] == 1.0);
 
         trie.clear_all_blocks(worker_0);
 
@@ -1231,7 +1287,7 @@ mod tests {
             .find_matches(vec![LocalBlockHash(0), LocalBlockHash(2)], false)
             .scores;
         assert_eq!(result.len(), 1);
-        assert_eq!(result[&worker_1], 2);
+        assert_eq!(result[&worker_1], 2.0);
         let result = trie
             .find_matches(
                 vec![LocalBlockHash(0), LocalBlockHash(1), LocalBlockHash(3)],
@@ -1239,7 +1295,7 @@ mod tests {
             )
             .scores;
         assert_eq!(result.len(), 1);
-        assert_eq!(result[&worker_1], 1);
+        assert_eq!(result[&worker_1], 1.0);
 
         // Test re-adding blocks after clearing worker
         trie.apply_event(create_store_event(worker_0, 0, vec![4, 5], None));
@@ -1247,7 +1303,7 @@ mod tests {
             .find_matches(vec![LocalBlockHash(4), LocalBlockHash(5)], false)
             .scores;
         assert_eq!(result.len(), 1);
-        assert_eq!(result[&worker_0], 2);
+        assert_eq!(result[&worker_0], 2.0);
 
         // Test multiple clears
         trie.clear_all_blocks(worker_0);
@@ -1269,17 +1325,17 @@ mod tests {
         assert!(!trie.lookup.contains_key(&worker_0));
         let result = trie.find_matches(vec![LocalBlockHash(6)], false).scores;
         assert_eq!(result.len(), 1);
-        assert_eq!(result[&worker_1], 1);
+        assert_eq!(result[&worker_1], 1.0);
 
         // Test clearing a worker that doesn't exist
-        let worker_fake = 2;
+        let worker_fake = WorkerId(2);
         assert!(!trie.lookup.contains_key(&worker_fake));
         trie.clear_all_blocks(worker_fake);
         assert!(!trie.lookup.contains_key(&worker_fake));
         assert!(trie.lookup.contains_key(&worker_1));
         let result = trie.find_matches(vec![LocalBlockHash(6)], false).scores;
         assert_eq!(result.len(), 1);
-        assert_eq!(result[&worker_1], 1);
+        assert_eq!(result[&worker_1], 1.0);
     }
 
     #[test]
@@ -1287,8 +1343,8 @@ mod tests {
         setup();
         let mut trie = RadixTree::new();
 
-        let worker_0 = 0;
-        let worker_1 = 1;
+        let worker_0 = WorkerId(0);
+        let worker_1 = WorkerId(1);
 
         trie.apply_event(create_store_event(worker_0, 0, vec![0, 1, 2], None));
         trie.apply_event(create_store_event(worker_1, 0, vec![0], None));
@@ -1300,12 +1356,12 @@ mod tests {
             )
             .scores;
 
-        assert!(result.len() == 2 && result[&worker_0] == 2 && result[&worker_1] == 1);
+        assert!(result.len() == 2 && result[&worker_0] == 2.0 && result[&worker_1] == 1.0);
 
         let result = trie
             .find_matches(vec![LocalBlockHash(0), LocalBlockHash(1)], true)
             .scores;
-        assert!(result.len() == 2 && result[&worker_0] == 2 && result[&worker_1] == 1);
+        assert!(result.len() == 2 && result[&worker_0] == 2.0 && result[&worker_1] == 1.0);
     }
 
     #[rstest]
@@ -1396,7 +1452,7 @@ mod tests {
     #[apply(indexer_template)]
     async fn test_apply_event(num_shards: usize, kv_block_size: usize) {
         setup();
-        let worker_id = 0;
+        let worker_id = WorkerId(0);
 
         let token = CancellationToken::new();
         let mut kv_indexer = make_indexer(&token, num_shards, kv_block_size);
@@ -1458,7 +1514,7 @@ mod tests {
         );
 
         // Blocks go in cache
-        let worker_id = 0;
+        let worker_id = WorkerId(0);
         let event = create_store_event(worker_id, 0, vec![1, 2, 3, 4], None);
         kv_indexer.apply_event(event).await;
 
@@ -1521,7 +1577,7 @@ mod tests {
     #[test]
     fn test_router_event_new() {
         setup();
-        let worker_id = 0;
+        let worker_id = WorkerId(0);
         let kv_cache_event = KvCacheEvent {
             event_id: 1,
             data: KvCacheEventData::Stored(KvCacheStoreData {
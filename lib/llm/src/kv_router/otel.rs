@@ -0,0 +1,179 @@
+// SPDX-FileCopyrightText: Copyright (c) 2024-2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! OpenTelemetry spans for scheduling attempts, exported through the global tracer provider.
+
+use opentelemetry::global::{self, BoxedSpan};
+use opentelemetry::trace::{Span, Status, Tracer};
+use opentelemetry::{Context, KeyValue};
+
+use super::protocols::WorkerId;
+use super::scheduler::{KvSchedulerError, SchedulingTiming};
+
+const TRACER_NAME: &str = "dynamo-kv-router";
+
+pub(crate) const SPAN_NAME: &str = "kv_scheduler.schedule";
+
+/// A request's scheduling attempt, from leaving its queue until it is dispatched or rejected.
+///
+/// The underlying span ends when this is dropped, so attempts cut short by a scheduler shutdown
+/// are still exported.
+pub(crate) struct SchedulingSpan(BoxedSpan);
+
+impl SchedulingSpan {
+    pub(crate) fn start(parent: &Context) -> Self {
+        Self(global::tracer(TRACER_NAME).start_with_context(SPAN_NAME, parent))
+    }
+
+    pub(crate) fn selected(
+        mut self,
+        worker_id: WorkerId,
+        secondary: Option<WorkerId>,
+        logit: f64,
+        timing: &SchedulingTiming,
+    ) {
+        self.0.set_attributes([
+            KeyValue::new("kv_router.worker_id", worker_id.0),
+            KeyValue::new("kv_router.logit", logit),
+            KeyValue::new(
+                "kv_router.queue_wait_seconds",
+                timing.queue_wait.as_secs_f64(),
+            ),
+            KeyValue::new(
+                "kv_router.capacity_wait_seconds",
+                timing.capacity_wait.as_secs_f64(),
+            ),
+        ]);
+        if let Some(secondary) = secondary {
+            self.0
+                .set_attribute(KeyValue::new("kv_router.secondary_worker_id", secondary.0));
+        }
+        self.0.end();
+    }
+
+    pub(crate) fn rejected(mut self, error: &KvSchedulerError) {
+        self.0.set_status(Status::error(error.to_string()));
+        self.0.end();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_router::indexer::OverlapScores;
+    use crate::kv_router::scheduler::{
+        Endpoint, EventSink, ForwardPassMetrics, KvScheduler, ScheduleOptions, SchedulerEvent,
+    };
+    use crate::kv_router::scoring::ProcessedEndpoints;
+    use async_trait::async_trait;
+    use opentelemetry::trace::{TraceContextExt, TracerProvider};
+    use opentelemetry::Value;
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+    use std::time::Duration;
+
+    struct NullSink;
+
+    #[async_trait]
+    impl EventSink for NullSink {
+        async fn publish(&self, _event: &SchedulerEvent) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scheduling_spans() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        global::set_tracer_provider(provider.clone());
+
+        let workers = ProcessedEndpoints::new(vec![Endpoint {
+            name: "worker-1".to_string(),
+            subject: "worker-subject-1".to_string(),
+            data: ForwardPassMetrics::default(),
+        }]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(NullSink)
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        let parent = Context::current_with_span(provider.tracer("test").start("request"));
+        let parent_span_id = parent.span().span_context().span_id();
+        let traced = |deadline| ScheduleOptions {
+            otel_context: Some(parent.clone()),
+            deadline,
+            ..Default::default()
+        };
+        for _ in 0..2 {
+            let worker_id = scheduler
+                .schedule_with_options(OverlapScores::default(), 100, traced(None))
+                .await
+                .unwrap();
+            assert_eq!(worker_id, 1);
+        }
+        // Untraced requests get no span
+        scheduler
+            .schedule(OverlapScores::default(), 100)
+            .await
+            .unwrap();
+        // and rejected ones end theirs with an error
+        let result = scheduler
+            .schedule_with_options(
+                OverlapScores::default(),
+                100,
+                traced(Some(Duration::from_nanos(1))),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(KvSchedulerError::DeadlineUnmet { .. })
+        ));
+
+        let spans: Vec<_> = exporter
+            .get_finished_spans()
+            .unwrap()
+            .into_iter()
+            .filter(|span| span.name == SPAN_NAME)
+            .collect();
+        assert_eq!(spans.len(), 3);
+        assert!(spans
+            .iter()
+            .all(|span| span.parent_span_id == parent_span_id));
+
+        let attribute = |span: &SpanData, key: &str| {
+            span.attributes
+                .iter()
+                .find(|kv| kv.key.as_str() == key)
+                .map(|kv| kv.value.clone())
+        };
+        for span in &spans[..2] {
+            assert_eq!(attribute(span, "kv_router.worker_id"), Some(Value::I64(1)));
+            assert!(matches!(
+                attribute(span, "kv_router.logit"),
+                Some(Value::F64(_))
+            ));
+            assert!(attribute(span, "kv_router.queue_wait_seconds").is_some());
+            assert!(attribute(span, "kv_router.capacity_wait_seconds").is_some());
+            assert_eq!(span.status, Status::Unset);
+        }
+        assert_eq!(attribute(&spans[2], "kv_router.worker_id"), None);
+        assert!(matches!(spans[2].status, Status::Error { .. }));
+    }
+}
@@ -0,0 +1,256 @@
+// SPDX-FileCopyrightText: Copyright (c) 2024-2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! One [`KvScheduler`] per namespace, sharing a single event publishing task.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use dynamo_runtime::component::Namespace;
+use futures::stream::{BoxStream, SelectAll};
+use futures::{FutureExt, StreamExt};
+use tokio::sync::mpsc::{Receiver, UnboundedReceiver, UnboundedSender};
+use tokio_stream::wrappers::{ReceiverStream, UnboundedReceiverStream};
+
+use super::indexer::OverlapScores;
+use super::protocols::WorkerId;
+use super::scheduler::{
+    publish_event, EventSink, FlushRequest, KvScheduler, KvSchedulerBuilder, KvSchedulerError,
+    SchedulerEvent,
+};
+use super::scoring::ProcessedEndpoints;
+use super::KvRouterConfig;
+
+/// A scheduler's events, the sink they are published to, and its flush requests.
+type EventStream = (
+    Arc<dyn EventSink>,
+    Receiver<SchedulerEvent>,
+    UnboundedReceiver<FlushRequest>,
+);
+
+/// Schedulers for many namespaces with a common block size and [`KvRouterConfig`].
+///
+/// Each namespace tracks its own workers and endpoints, so requests only route among that
+/// namespace's workers, but all of their events are published from one task.
+pub struct KvSchedulerPool {
+    block_size: usize,
+    config: KvRouterConfig,
+    schedulers: HashMap<String, KvScheduler>,
+    event_streams_tx: UnboundedSender<EventStream>,
+}
+
+impl KvSchedulerPool {
+    /// Create an empty pool and start its event publishing task.
+    pub fn new(block_size: usize, config: KvRouterConfig) -> Result<Self, KvSchedulerError> {
+        config
+            .validate()
+            .map_err(|e| KvSchedulerError::InvalidConfig(e.to_string()))?;
+        let (event_streams_tx, event_streams_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(publish_all(event_streams_rx));
+        Ok(Self {
+            block_size,
+            config,
+            schedulers: HashMap::new(),
+            event_streams_tx,
+        })
+    }
+
+    /// Start a scheduler for `namespace`, publishing its events on the namespace.
+    pub async fn add_namespace(
+        &mut self,
+        namespace: Namespace,
+        endpoints: tokio::sync::watch::Receiver<ProcessedEndpoints>,
+    ) -> Result<(), KvSchedulerError> {
+        let name = namespace.name();
+        self.add(name, KvScheduler::builder().namespace(namespace), endpoints)
+            .await
+    }
+
+    /// Start a scheduler for the namespace called `name`, publishing its events to `sink`.
+    pub async fn add_with_sink(
+        &mut self,
+        name: impl Into<String>,
+        sink: impl EventSink + 'static,
+        endpoints: tokio::sync::watch::Receiver<ProcessedEndpoints>,
+    ) -> Result<(), KvSchedulerError> {
+        self.add(
+            name.into(),
+            KvScheduler::builder().event_sink(sink),
+            endpoints,
+        )
+        .await
+    }
+
+    async fn add(
+        &mut self,
+        name: String,
+        builder: KvSchedulerBuilder,
+        endpoints: tokio::sync::watch::Receiver<ProcessedEndpoints>,
+    ) -> Result<(), KvSchedulerError> {
+        if self.schedulers.contains_key(&name) {
+            return Err(KvSchedulerError::InvalidConfig(format!(
+                "namespace {name} already has a scheduler"
+            )));
+        }
+        let event_streams_tx = self.event_streams_tx.clone();
+        let scheduler = builder
+            .block_size(self.block_size)
+            .endpoints(endpoints)
+            .config(self.config.clone())
+            .start_with_events(move |sink, event_rx, flush_rx| {
+                if event_streams_tx.send((sink, event_rx, flush_rx)).is_err() {
+                    tracing::warn!("scheduler pool event task stopped; events will be dropped");
+                }
+            })
+            .await?;
+        self.schedulers.insert(name, scheduler);
+        Ok(())
+    }
+
+    /// The scheduler for `namespace`, for its full API.
+    pub fn scheduler(&self, namespace: &str) -> Option<&KvScheduler> {
+        self.schedulers.get(namespace)
+    }
+
+    /// Schedule a request among `namespace`'s workers.
+    pub async fn schedule(
+        &self,
+        namespace: &str,
+        overlap: OverlapScores,
+        isl_tokens: usize,
+    ) -> Result<WorkerId, KvSchedulerError> {
+        self.scheduler(namespace)
+            .ok_or_else(|| KvSchedulerError::UnknownNamespace(namespace.to_string()))?
+            .schedule(overlap, isl_tokens)
+            .await
+    }
+}
+
+/// Publish the events of every scheduler registered on `event_streams_rx` until the pool and
+/// all of its schedulers are gone. A flush from any scheduler publishes the events buffered by
+/// all of them.
+async fn publish_all(mut event_streams_rx: UnboundedReceiver<EventStream>) {
+    let mut events: SelectAll<BoxStream<'static, (Arc<dyn EventSink>, SchedulerEvent)>> =
+        SelectAll::new();
+    let mut flushes: SelectAll<UnboundedReceiverStream<FlushRequest>> = SelectAll::new();
+    loop {
+        tokio::select! {
+            biased;
+
+            Some((sink, event_rx, flush_rx)) = event_streams_rx.recv() => {
+                events.push(
+                    ReceiverStream::new(event_rx)
+                        .map(move |event| (sink.clone(), event))
+                        .boxed(),
+                );
+                flushes.push(UnboundedReceiverStream::new(flush_rx));
+            }
+
+            Some(done_tx) = flushes.next(), if !flushes.is_empty() => {
+                while let Some(Some((sink, event))) = events.next().now_or_never() {
+                    publish_event(sink.as_ref(), &event).await;
+                }
+                let _ = done_tx.send(());
+            }
+
+            Some((sink, event)) = events.next(), if !events.is_empty() => {
+                publish_event(sink.as_ref(), &event).await;
+            }
+
+            else => break,
+        }
+    }
+    tracing::trace!("scheduler pool event task shutting down");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_router::scheduler::{Endpoint, ForwardPassMetrics};
+    use async_trait::async_trait;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Clone, Default)]
+    struct CountingSink {
+        hit_rates: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl EventSink for CountingSink {
+        async fn publish(&self, event: &SchedulerEvent) -> anyhow::Result<()> {
+            if let SchedulerEvent::HitRate(_) = event {
+                self.hit_rates.fetch_add(1, Ordering::SeqCst);
+            }
+            Ok(())
+        }
+    }
+
+    fn workers(ids: &[i64]) -> ProcessedEndpoints {
+        ProcessedEndpoints::new(
+            ids.iter()
+                .map(|id| Endpoint {
+                    name: format!("worker-{id}"),
+                    subject: format!("worker-subject-{id:x}"),
+                    data: ForwardPassMetrics::default(),
+                })
+                .collect(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_pool_routes_within_namespace() {
+        let mut pool = KvSchedulerPool::new(20, KvRouterConfig::default()).unwrap();
+        let (_a_tx, a_rx) = tokio::sync::watch::channel(workers(&[1, 2]));
+        let (_b_tx, b_rx) = tokio::sync::watch::channel(workers(&[3, 4]));
+        let (a_sink, b_sink) = (CountingSink::default(), CountingSink::default());
+        pool.add_with_sink("a", a_sink.clone(), a_rx).await.unwrap();
+        pool.add_with_sink("b", b_sink.clone(), b_rx).await.unwrap();
+
+        let (_c_tx, c_rx) = tokio::sync::watch::channel(workers(&[5]));
+        let duplicate = pool.add_with_sink("a", CountingSink::default(), c_rx).await;
+        assert!(matches!(duplicate, Err(KvSchedulerError::InvalidConfig(_))));
+
+        let mut selected = HashMap::<&str, HashSet<WorkerId>>::new();
+        for _ in 0..4 {
+            for namespace in ["a", "b"] {
+                let worker_id = pool
+                    .schedule(namespace, OverlapScores::default(), 100)
+                    .await
+                    .unwrap();
+                selected.entry(namespace).or_default().insert(worker_id);
+            }
+        }
+        assert_eq!(selected["a"], HashSet::from([WorkerId(1), WorkerId(2)]));
+        assert_eq!(selected["b"], HashSet::from([WorkerId(3), WorkerId(4)]));
+
+        let unknown = pool.schedule("c", OverlapScores::default(), 100).await;
+        assert!(matches!(
+            unknown,
+            Err(KvSchedulerError::UnknownNamespace(_))
+        ));
+
+        // Both namespaces' events went out through the shared task, to their own sinks
+        for sink in [&a_sink, &b_sink] {
+            for _ in 0..100 {
+                if sink.hit_rates.load(Ordering::SeqCst) == 4 {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            assert_eq!(sink.hit_rates.load(Ordering::SeqCst), 4);
+        }
+    }
+}
@@ -15,6 +15,8 @@
 
 use crate::tokens::Token;
 use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::fmt;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct RouterRequest {
@@ -26,17 +28,78 @@ pub struct RouterResponse {
     pub worker_id: i64,
 }
 
-#[derive(Debug)]
+/// Identifies a worker: the lease id its endpoint subject ends in.
+///
+/// Wraps the bare `i64` so worker ids can't be mixed up with block or token counts. Converts
+/// to and from `i64`, compares equal to the `i64` it wraps and can look up maps keyed by
+/// [`WorkerId`] with one, and serializes as the bare number.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Ord, PartialOrd, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct WorkerId(pub i64);
+
+impl From<i64> for WorkerId {
+    fn from(value: i64) -> Self {
+        Self(value)
+    }
+}
+
+impl From<WorkerId> for i64 {
+    fn from(value: WorkerId) -> Self {
+        value.0
+    }
+}
+
+// Hashes and compares exactly as the wrapped i64, so maps keyed by WorkerId can be queried
+// with one
+impl Borrow<i64> for WorkerId {
+    fn borrow(&self) -> &i64 {
+        &self.0
+    }
+}
+
+impl PartialEq<i64> for WorkerId {
+    fn eq(&self, other: &i64) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<WorkerId> for i64 {
+    fn eq(&self, other: &WorkerId) -> bool {
+        *self == other.0
+    }
+}
+
+impl fmt::Display for WorkerId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct WorkerSelectionResult {
     /// The worker id of the selected worker
-    pub worker_id: i64,
+    pub worker_id: WorkerId,
 
-    /// The total number of blocks required to prefill the request
+    /// The total number of blocks required to prefill the request, counting a trailing partial
+    /// block as a whole one
     pub required_blocks: u64,
 
+    /// The number of blocks the request is expected to grow by while decoding its expected
+    /// output. 0 when the request has no expected output length.
+    pub decode_blocks: u64,
+
     /// The number of blocks that the selected worker may already have cached.
-    /// This is not a guarantee, but an estimate.
-    pub overlap_blocks: usize,
+    /// This is not a guarantee, but an estimate, and may be fractional.
+    pub overlap_blocks: f64,
+
+    /// How far the selected worker's logit is ahead of the runner-up's. 0.0 when there was a tie,
+    /// a single candidate, or no ranking at all.
+    pub margin: f64,
+
+    /// The selected worker's logit, or 0.0 if it was chosen without scoring.
+    pub logit: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -54,6 +117,37 @@ pub struct ForwardPassMetrics {
     pub gpu_cache_usage_perc: f32,
     // percentage represented as a float from 0 to 1
     pub gpu_prefix_cache_hit_rate: f32,
+    // which phase of disaggregated serving the worker handles
+    #[serde(default)]
+    pub role: WorkerRole,
+    // tokens per KV block, for engines whose block size differs from the router's
+    #[serde(default)]
+    pub kv_block_size: Option<u32>,
+    // most sequences the engine runs at once before queueing internally, if it has a limit
+    #[serde(default)]
+    pub max_concurrent: Option<u64>,
+    // how long the oldest waiting request has been queued, in milliseconds, if reported
+    #[serde(default)]
+    pub oldest_waiting_age_ms: Option<u64>,
+}
+
+/// The phase of serving a worker handles when prefill and decode are disaggregated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkerRole {
+    Prefill,
+    Decode,
+    /// Handles both phases; the only role without disaggregation
+    #[default]
+    Unified,
+}
+
+impl WorkerRole {
+    /// Whether a worker with this role can serve a request for `requested`.
+    /// Unified workers serve every role; the others only their own.
+    pub fn serves(self, requested: WorkerRole) -> bool {
+        self == WorkerRole::Unified || self == requested
+    }
 }
 
 /// A [`LocalBlockHash`] is a hash computed from the tokens_ids, extra_token_ids and the optional
@@ -200,6 +294,23 @@ mod tests {
         assert_eq!(deserialized, hash);
     }
 
+    #[test]
+    fn test_worker_id_conversions() {
+        let worker_id = WorkerId::from(0x7f3a);
+        assert_eq!(i64::from(worker_id), 0x7f3a);
+        assert_eq!(worker_id, 0x7f3a);
+        assert_eq!(worker_id.to_string(), "32570");
+
+        // Maps keyed by WorkerId can still be queried with a raw id
+        let loads = std::collections::HashMap::from([(worker_id, 3u64)]);
+        assert_eq!(loads.get(&0x7f3a), Some(&3));
+
+        let serialized = serde_json::to_string(&worker_id).unwrap();
+        assert_eq!(serialized, "32570");
+        let deserialized: WorkerId = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, worker_id);
+    }
+
     #[test]
     fn test_kv_cache_events_serialization() {
         let event_data = KvCacheEventData::Stored(KvCacheStoreData {
@@ -94,10 +94,10 @@ mod tests {
         let event_tx = recorder.event_sender();
 
         // Create first event from worker 1 using helper function
-        let event1 = create_store_event(1, 42, vec![1, 2, 3], None);
+        let event1 = create_store_event(WorkerId(1), 42, vec![1, 2, 3], None);
 
         // Create second event from worker 2 using helper function
-        let event2 = create_remove_event(1, 43, vec![2, 3]);
+        let event2 = create_remove_event(WorkerId(1), 43, vec![2, 3]);
 
         // Send both events one after another
         event_tx.send(event1).await.unwrap();
@@ -13,28 +13,345 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use async_trait::async_trait;
+use derive_builder::Builder;
 use dynamo_runtime::component::Namespace;
+use dynamo_runtime::pipeline::{
+    network::Ingress, AsyncEngine, AsyncEngineContextProvider, ManyOut, ResponseStream, SingleIn,
+};
+use dynamo_runtime::protocols::annotated::Annotated;
 use dynamo_runtime::traits::events::EventPublisher;
-use rand::Rng;
+use futures::FutureExt;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::borrow::BorrowMut;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
 
-use super::protocols::WorkerSelectionResult;
-use super::WorkerSelector;
+use super::protocols::{WorkerId, WorkerSelectionResult};
+use super::{AsyncWorkerSelector, WorkerSelector};
 use crate::kv_router::indexer::OverlapScores;
-pub use crate::kv_router::protocols::ForwardPassMetrics;
+pub use crate::kv_router::protocols::{ForwardPassMetrics, WorkerRole};
 use crate::kv_router::scoring::ProcessedEndpoints;
 use crate::kv_router::KvRouterConfig;
 use crate::kv_router::KV_HIT_RATE_SUBJECT;
+use crate::kv_router::KV_SCHEDULER_COMPONENT;
+use crate::kv_router::KV_SCHEDULER_STATE_ENDPOINT;
+use crate::kv_router::KV_SCHEDULING_REJECTED_SUBJECT;
+use crate::kv_router::KV_WORKER_DRAINED_SUBJECT;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+mod selector;
+mod trackers;
+
+use selector::{decode_blocks, required_blocks, worker_block_size};
+pub use selector::{
+    CandidateExplanation, DefaultWorkerSelector, LogitContributions, SelectionExplanation,
+};
+use trackers::{
+    DecisionLog, FairnessTracker, HitRateTracker, IdempotencyCache, LogitHistogram,
+    OscillationTracker, OutcomeWindow, OverlapCache, PredictionDecay, PrefixCoalescer,
+    QuotaTracker, SelectionDistribution, ServiceQueue, SessionAffinity, TenantRateLimiter,
+    ThroughputTracker, WaitingSamples, WarmupTracker,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct KVHitRateEvent {
-    pub worker_id: i64,
+    pub worker_id: WorkerId,
     pub isl_blocks: usize,
     pub overlap_blocks: usize,
 }
 
+/// Emitted once a draining worker has no predicted in-flight requests left.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerDrainedEvent {
+    pub worker_id: WorkerId,
+}
+
+/// Why a request could not be scheduled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectionReason {
+    NoEndpoints,
+    AllWorkersBusy,
+    InvalidRequest,
+    RequestTooLarge,
+    StaleMetrics,
+    DeadlineUnmet,
+    LoadShed,
+    SelectionTimeout,
+    RateLimited,
+    SelectorPanicked,
+    /// Any other selector error
+    Other,
+}
+
+impl From<&KvSchedulerError> for RejectionReason {
+    fn from(error: &KvSchedulerError) -> Self {
+        match error {
+            KvSchedulerError::NoEndpoints => RejectionReason::NoEndpoints,
+            KvSchedulerError::AllWorkersBusy => RejectionReason::AllWorkersBusy,
+            KvSchedulerError::InvalidRequest(_) => RejectionReason::InvalidRequest,
+            KvSchedulerError::RequestTooLarge { .. } => RejectionReason::RequestTooLarge,
+            KvSchedulerError::StaleMetrics(_) => RejectionReason::StaleMetrics,
+            KvSchedulerError::DeadlineUnmet { .. } => RejectionReason::DeadlineUnmet,
+            KvSchedulerError::LoadShed { .. } => RejectionReason::LoadShed,
+            KvSchedulerError::SelectionTimeout(_) => RejectionReason::SelectionTimeout,
+            KvSchedulerError::RateLimited { .. } => RejectionReason::RateLimited,
+            KvSchedulerError::SelectorPanicked(_) => RejectionReason::SelectorPanicked,
+            _ => RejectionReason::Other,
+        }
+    }
+}
+
+/// Emitted whenever a request fails to be scheduled.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchedulingRejectedEvent {
+    pub reason: RejectionReason,
+    pub isl_tokens: usize,
+}
+
+/// Events emitted by the scheduler.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SchedulerEvent {
+    HitRate(KVHitRateEvent),
+    WorkerDrained(WorkerDrainedEvent),
+    SchedulingRejected(SchedulingRejectedEvent),
+}
+
+/// Default capacity of the queue between the scheduler and its event publishing task.
+pub const DEFAULT_EVENT_CHANNEL_CAPACITY: usize = 4096;
+
+/// Sending half of the scheduler's bounded event queue.
+///
+/// Sends never wait for the publisher: an event that finds the queue full is dropped and
+/// counted, so a stalled sink can't hold up routing.
+#[derive(Clone)]
+pub struct EventSender {
+    tx: tokio::sync::mpsc::Sender<SchedulerEvent>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl EventSender {
+    /// A sender and receiver for a queue of at most `capacity` events.
+    pub fn channel(capacity: usize) -> (Self, tokio::sync::mpsc::Receiver<SchedulerEvent>) {
+        let (tx, rx) = tokio::sync::mpsc::channel(capacity);
+        let sender = Self {
+            tx,
+            dropped: Arc::new(AtomicU64::new(0)),
+        };
+        (sender, rx)
+    }
+
+    /// Queue `event`, or drop it if the queue is full. Fails only once the receiver is gone.
+    pub fn send(
+        &self,
+        event: SchedulerEvent,
+    ) -> Result<(), tokio::sync::mpsc::error::SendError<SchedulerEvent>> {
+        match self.tx.try_send(event) {
+            Ok(()) => Ok(()),
+            Err(tokio::sync::mpsc::error::TrySendError::Full(event)) => {
+                let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                tracing::debug!("event queue full, dropped {event:?} ({dropped} dropped so far)");
+                Ok(())
+            }
+            Err(tokio::sync::mpsc::error::TrySendError::Closed(event)) => {
+                Err(tokio::sync::mpsc::error::SendError(event))
+            }
+        }
+    }
+
+    /// Events dropped so far because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Queue a [`SchedulingRejectedEvent`] for a request that failed with `error`.
+fn emit_rejected(event_tx: &EventSender, error: &KvSchedulerError, isl_tokens: usize) {
+    let event = SchedulingRejectedEvent {
+        reason: error.into(),
+        isl_tokens,
+    };
+    if let Err(e) = event_tx.send(SchedulerEvent::SchedulingRejected(event)) {
+        tracing::warn!("Failed to send scheduling rejected event: {:?}", e);
+    }
+}
+
+/// A destination for [`SchedulerEvent`]s.
+///
+/// The scheduler publishes on its [`Namespace`] by default; a custom sink can be supplied through
+/// [`KvSchedulerBuilder`] to redirect events elsewhere.
+#[async_trait]
+pub trait EventSink: Send + Sync {
+    async fn publish(&self, event: &SchedulerEvent) -> anyhow::Result<()>;
+}
+
+/// Wire format of the events a [`NamespaceEventSink`] publishes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EventEncoding {
+    /// JSON, on the plain event subjects
+    #[default]
+    Json,
+    /// MessagePack, a compact binary format, on the event subjects with
+    /// [`EventEncoding::subject_suffix`] appended
+    MessagePack,
+}
+
+impl EventEncoding {
+    /// Appended to each event subject, so consumers can tell the format from the subject.
+    pub fn subject_suffix(&self) -> &'static str {
+        match self {
+            EventEncoding::Json => "",
+            EventEncoding::MessagePack => ".msgpack",
+        }
+    }
+
+    pub fn encode(&self, event: &impl Serialize) -> anyhow::Result<Vec<u8>> {
+        Ok(match self {
+            EventEncoding::Json => serde_json::to_vec(event)?,
+            EventEncoding::MessagePack => rmp_serde::to_vec(event)?,
+        })
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> anyhow::Result<T> {
+        Ok(match self {
+            EventEncoding::Json => serde_json::from_slice(bytes)?,
+            EventEncoding::MessagePack => rmp_serde::from_slice(bytes)?,
+        })
+    }
+}
+
+/// Publishes [`SchedulerEvent`]s on a [`Namespace`].
+pub struct NamespaceEventSink {
+    namespace: Namespace,
+    hit_rate_subject: String,
+    encoding: EventEncoding,
+}
+
+impl NamespaceEventSink {
+    pub fn new(namespace: Namespace, hit_rate_subject: impl Into<String>) -> Self {
+        Self {
+            namespace,
+            hit_rate_subject: hit_rate_subject.into(),
+            encoding: EventEncoding::default(),
+        }
+    }
+
+    /// Publish events in `encoding` instead of JSON.
+    pub fn with_encoding(mut self, encoding: EventEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    async fn publish_encoded(
+        &self,
+        subject: &str,
+        event: &(impl Serialize + Send + Sync),
+    ) -> anyhow::Result<()> {
+        match self.encoding {
+            EventEncoding::Json => self.namespace.publish(subject, event).await,
+            encoding => {
+                let subject = format!("{subject}{}", encoding.subject_suffix());
+                self.namespace
+                    .publish_bytes(subject, encoding.encode(event)?)
+                    .await
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl EventSink for NamespaceEventSink {
+    async fn publish(&self, event: &SchedulerEvent) -> anyhow::Result<()> {
+        match event {
+            SchedulerEvent::HitRate(event) => {
+                self.publish_encoded(&self.hit_rate_subject, event).await
+            }
+            SchedulerEvent::WorkerDrained(event) => {
+                self.publish_encoded(KV_WORKER_DRAINED_SUBJECT, event).await
+            }
+            SchedulerEvent::SchedulingRejected(event) => {
+                self.publish_encoded(KV_SCHEDULING_REJECTED_SUBJECT, event)
+                    .await
+            }
+        }
+    }
+}
+
+/// An [`EventSink`] publishing every event to each of several sinks, such as a
+/// [`NamespaceEventSink`] for the live router alongside a local one for debugging.
+///
+/// Sinks publish concurrently, so a failing or slow sink doesn't hold back delivery to the
+/// others. Publishing fails if any sink did, after all of them were tried.
+pub struct FanOutEventSink {
+    sinks: Vec<Box<dyn EventSink>>,
+}
+
+impl FanOutEventSink {
+    pub fn new(sinks: Vec<Box<dyn EventSink>>) -> Self {
+        Self { sinks }
+    }
+}
+
+#[async_trait]
+impl EventSink for FanOutEventSink {
+    async fn publish(&self, event: &SchedulerEvent) -> anyhow::Result<()> {
+        let results =
+            futures::future::join_all(self.sinks.iter().map(|sink| sink.publish(event))).await;
+        let mut errors = results.into_iter().filter_map(Result::err);
+        match errors.next() {
+            Some(first) => Err(first.context(format!(
+                "{} of {} event sinks failed",
+                1 + errors.count(),
+                self.sinks.len()
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Estimates how long a worker takes to serve a request, for queue time estimates.
+///
+/// The default [`LinearServiceTimeModel`] suits a single request class; pools mixing short and
+/// long generations can supply a model fitted to their traffic through
+/// [`KvSchedulerBuilder::service_time_model`].
+pub trait ServiceTimeModel: Send + Sync {
+    /// Time to serve a request of `isl_tokens`, of which `overlap_blocks` are already cached.
+    fn estimate(&self, isl_tokens: usize, overlap_blocks: f64) -> Duration;
+}
+
+/// Service time growing linearly with the request's tokens, less a saving per cached block.
+/// Never below `base`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinearServiceTimeModel {
+    /// Fixed cost of every request
+    pub base: Duration,
+    /// Cost of each input token
+    pub per_token: Duration,
+    /// Saving for each block already in the worker's cache
+    pub per_cached_block: Duration,
+}
+
+impl Default for LinearServiceTimeModel {
+    fn default() -> Self {
+        Self {
+            base: Duration::from_millis(50),
+            per_token: Duration::from_micros(100),
+            per_cached_block: Duration::from_millis(1),
+        }
+    }
+}
+
+impl ServiceTimeModel for LinearServiceTimeModel {
+    fn estimate(&self, isl_tokens: usize, overlap_blocks: f64) -> Duration {
+        let cost = self.base + self.per_token * isl_tokens as u32;
+        let saving = self.per_cached_block.mul_f64(overlap_blocks.max(0.0));
+        cost.saturating_sub(saving).max(self.base)
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum KvSchedulerError {
     #[error("no endpoints aviailable to route work")]
@@ -45,6 +362,61 @@ pub enum KvSchedulerError {
 
     #[error("endpoint subscriber shutdown")]
     SubscriberShutdown,
+
+    #[error("invalid scheduler configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("invalid scheduling request: {0}")]
+    InvalidRequest(String),
+
+    #[error("request of {isl_tokens} tokens exceeds the maximum of {max}")]
+    RequestTooLarge { isl_tokens: usize, max: usize },
+
+    #[error("blocking schedule called from within an async runtime")]
+    BlockingInAsyncContext,
+
+    #[error("worker metrics are stale; last updated {0:?} ago")]
+    StaleMetrics(Duration),
+
+    #[error("worker id {worker_id} is shared by subjects {kept} and {dropped}")]
+    WorkerIdCollision {
+        worker_id: WorkerId,
+        kept: String,
+        dropped: String,
+    },
+
+    #[error("selected worker {worker_id} is not among the known workers")]
+    WorkerNotFound { worker_id: WorkerId },
+
+    #[error("no scheduler for namespace {0}")]
+    UnknownNamespace(String),
+
+    #[error("estimated completion in {estimated:?} misses the request's deadline")]
+    DeadlineUnmet { estimated: Duration },
+
+    #[error("{load} requests outstanding, at or above the high-water mark of {high_water_mark}")]
+    LoadShed { load: usize, high_water_mark: usize },
+
+    #[error("worker selection did not complete within {0:?}")]
+    SelectionTimeout(Duration),
+
+    #[error("tenant {tenant_id} is over its request rate limit")]
+    RateLimited { tenant_id: String },
+
+    #[error("buffered events were not published within {0:?}")]
+    FlushTimeout(Duration),
+
+    /// The worker selector panicked while choosing a worker for the request. The panic is
+    /// caught on the scheduling task, so only this request fails and the task keeps serving
+    /// the others.
+    #[error("worker selector panicked: {0}")]
+    SelectorPanicked(String),
+
+    /// The scheduling task stopped while the scheduler was still in use, such as after a
+    /// panic in a selection hook, once the endpoints stream closed, or after
+    /// [`KvScheduler::shutdown`]. Nothing will route requests on this scheduler again.
+    #[error("scheduler background task is no longer running")]
+    SchedulerDead,
 }
 
 /// [gluo FIXME] exactly the same as EndpointInfo except that 'data'
@@ -57,8 +429,8 @@ pub struct Endpoint {
 }
 
 impl Endpoint {
-    pub fn worker_id(&self) -> i64 {
-        i64::from_str_radix(
+    pub fn worker_id(&self) -> WorkerId {
+        let worker_id = i64::from_str_radix(
             self.subject
                 .split("-")
                 .last()
@@ -67,472 +439,8678 @@ impl Endpoint {
                 .as_str(),
             16,
         )
-        .expect("invalid worker id")
+        .expect("invalid worker id");
+        WorkerId(worker_id)
     }
 }
 
 pub struct SchedulingRequest {
     pub isl_tokens: usize,
     pub overlap: OverlapScores,
-    resp_tx: tokio::sync::oneshot::Sender<i64>,
+    /// Only workers serving this role are considered
+    pub role: WorkerRole,
+    /// Hash identifying the request's prefix, used to coalesce concurrent identical prefixes
+    pub prefix_hash: Option<u64>,
+    /// Queue the request waited in
+    pub priority: Priority,
+    /// Tenant the request is accounted to for fairness
+    pub tenant_id: Option<String>,
+    /// Conversation the request continues, routed to the session's previous worker
+    pub session_id: Option<String>,
+    /// Key shared by retries of the same request, which are scheduled only once
+    pub idempotency_key: Option<String>,
+    /// Time from enqueueing within which the request must be served
+    pub deadline: Option<Duration>,
+    /// Workers to avoid for this attempt, unless no other worker is eligible
+    pub exclude: Vec<WorkerId>,
+    /// Zone the request originates from, for the locality penalty
+    pub zone: Option<String>,
+    /// Tokens the request is expected to generate, for the predicted decode growth
+    pub expected_output_tokens: Option<usize>,
+    /// Stream of similar requests the request belongs to, for oscillation tracking
+    pub stream_id: Option<String>,
+    /// Caller's id for the request, for the decision log
+    pub request_id: Option<String>,
+    /// Tokens per KV block for the request's model, in place of the scheduler's block size
+    pub block_size: Option<usize>,
+    /// Trace context the scheduling span is parented to
+    #[cfg(feature = "otel")]
+    pub otel_context: Option<opentelemetry::Context>,
+    resp_tx: tokio::sync::oneshot::Sender<Result<Scheduled, KvSchedulerError>>,
+    /// Speculative requests may also be dispatched to the runner-up
+    speculative: bool,
+    /// Where to send the selector's explanation of its choice, if anywhere
+    explain_tx: Option<tokio::sync::oneshot::Sender<SelectionExplanation>>,
+    enqueued_at: Instant,
+    /// Where the request's outcome is counted, if anywhere
+    outcomes: Option<Arc<Mutex<OutcomeWindow>>>,
 }
 
-impl SchedulingRequest {
-    pub fn respond(self, worker_id: i64) {
-        if self.resp_tx.send(worker_id).is_err() {
-            tracing::trace!("failed to send response to requestor");
-        }
-    }
+/// The worker a request was scheduled on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scheduled {
+    pub worker_id: WorkerId,
+    /// Runner-up the request was also dispatched to. Only set for speculative requests.
+    pub secondary: Option<WorkerId>,
+    pub timing: SchedulingTiming,
 }
 
-pub struct KvScheduler {
-    request_tx: tokio::sync::mpsc::Sender<SchedulingRequest>,
+/// Where a request spent its time before a worker was selected.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SchedulingTiming {
+    /// Waiting in the scheduling queue behind other requests
+    pub queue_wait: Duration,
+    /// Waiting for a worker to free up while all were busy
+    pub capacity_wait: Duration,
 }
 
-impl KvScheduler {
-    pub async fn start(
-        ns: Namespace,
-        block_size: usize,
-        endpoints_rx: tokio::sync::watch::Receiver<ProcessedEndpoints>,
-        selector: Option<Box<dyn WorkerSelector + Send + Sync>>,
-    ) -> Result<Self, KvSchedulerError> {
-        let selector = selector.unwrap_or(Box::new(DefaultWorkerSelector::default()));
-        let mut endpoints_rx = endpoints_rx;
-        let mut endpoints: ProcessedEndpoints = endpoints_rx.borrow_and_update().clone();
+/// What scheduling a request would do right now, as reported by [`KvScheduler::probe`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProbeResult {
+    /// A worker has capacity; this is the selection that would be made for the request
+    Available(WorkerSelectionResult),
+    /// Every worker is busy; the request would wait roughly this long for one
+    Wait(Duration),
+}
 
-        let (event_tx, event_rx) = tokio::sync::mpsc::unbounded_channel::<KVHitRateEvent>();
-        tokio::spawn(async move {
-            let mut event_rx = event_rx;
-            while let Some(event) = event_rx.recv().await {
-                if let Err(e) = ns.publish(KV_HIT_RATE_SUBJECT, &event).await {
-                    tracing::warn!("Failed to publish KV hit rate event: {:?}", e);
-                }
-            }
-        });
+/// Per-request options for [`KvScheduler::schedule_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct ScheduleOptions {
+    /// Serving phase the request is routed for. Default: [`WorkerRole::Unified`]
+    pub role: WorkerRole,
+    /// Hash of the request's shared prefix. Requests with the same hash are coalesced onto one
+    /// worker while `prefix_coalesce_window_ms` is enabled.
+    pub prefix_hash: Option<u64>,
+    /// Queue the request waits in. Default: [`Priority::High`]
+    pub priority: Priority,
+    /// Tenant the request is accounted to while `fairness_weight` is enabled. Requests without
+    /// a tenant are neither counted nor penalized.
+    pub tenant_id: Option<String>,
+    /// Conversation the request belongs to. While `session_ttl_ms` is enabled, requests of a
+    /// recently seen session go to the worker its previous request went to.
+    pub session_id: Option<String>,
+    /// Key identifying retries of one logical request. Within `idempotency_window_ms` of it
+    /// first being scheduled, a request with the same key is answered with the same worker and
+    /// leaves the predicted load untouched.
+    pub idempotency_key: Option<String>,
+    /// Reject the request with [`KvSchedulerError::DeadlineUnmet`] unless the selected worker
+    /// is estimated to serve it within this long of it being scheduled, counting the estimated
+    /// service time of the requests already routed there.
+    pub deadline: Option<Duration>,
+    /// Workers not to route this attempt to, such as one that just failed it, while their
+    /// metrics catch up. Unlike the blacklist this only applies to the one request, and if it
+    /// would leave no eligible worker the listed workers are considered after all.
+    pub exclude: Vec<WorkerId>,
+    /// Zone the request originates from. While `locality_weight` is enabled, workers are
+    /// penalized by their [`Topology`] distance from it.
+    pub zone: Option<String>,
+    /// Tokens the request is expected to generate. The blocks they will take up while decoding
+    /// are added to the selected worker's predicted load along with the prompt's.
+    pub expected_output_tokens: Option<usize>,
+    /// Stream of similar requests the request belongs to, such as one client's steady
+    /// workload. [`KvScheduler::oscillation_rate`] counts how often consecutive requests of a
+    /// stream switch workers; requests without one are counted as a single stream.
+    pub stream_id: Option<String>,
+    /// Caller's id for the request, recorded with its decision while the builder's
+    /// `decision_log_capacity` is set.
+    pub request_id: Option<String>,
+    /// Tokens per KV block of the model the request is for, when models with different block
+    /// sizes share the scheduler. Its blocks and overlap are counted in this size rather than
+    /// the scheduler's, though a worker reporting its own `kv_block_size` still uses that.
+    pub block_size: Option<usize>,
+    /// Trace context to record the scheduling attempt under, as a child span carrying the
+    /// selected worker, its logit and the time spent waiting.
+    #[cfg(feature = "otel")]
+    pub otel_context: Option<opentelemetry::Context>,
+}
 
-        // Channel to accept new scheduling requests
-        let (request_tx, request_rx) = tokio::sync::mpsc::channel::<SchedulingRequest>(1024);
-        // Background task to handle scheduling requests
-        tokio::spawn(async move {
-            let mut request: SchedulingRequest;
-            let mut request_rx = request_rx;
-            tracing::trace!("scheduler background task started");
+/// Network layout of a deployment: the zone each worker runs in and the cost of routing
+/// between zones, for [`KvRouterConfig::locality_weight`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Topology {
+    /// Zone of each worker. Workers absent have no known distance.
+    pub worker_zones: HashMap<WorkerId, String>,
+    /// Cost of routing a request from a client zone (outer key) to a worker zone (inner key).
+    /// Pairs absent cost nothing.
+    pub distances: HashMap<String, HashMap<String, f64>>,
+}
 
-            'outer: loop {
-                request = tokio::select! {
-                    biased;
-
-                    new_request = request_rx.recv() => {
-                        match new_request {
-                            Some(new_request) => {
-                                tracing::trace!("received request to be scheduled");
-                                new_request
-                            },
-                            None => {
-                                tracing::trace!("scheduler shutdown");
-                                break 'outer;
-                            }
-                        }
-                    }
+impl Topology {
+    /// Distance from `zone` to each worker with a known one.
+    fn distances_from(&self, zone: &str) -> HashMap<WorkerId, f64> {
+        let Some(distances) = self.distances.get(zone) else {
+            return HashMap::new();
+        };
+        self.worker_zones
+            .iter()
+            .filter_map(|(worker_id, worker_zone)| {
+                distances
+                    .get(worker_zone)
+                    .map(|distance| (*worker_id, *distance))
+            })
+            .collect()
+    }
+}
 
-                    _ = endpoints_rx.changed() => {
-                        endpoints = endpoints_rx.borrow_and_update().clone();
-                        continue 'outer;
-                    }
-                };
-                loop {
-                    match selector.select_worker(&endpoints, &request, block_size) {
-                        Ok(selection) => {
-                            let worker_id = process_worker_selection(
-                                endpoints.borrow_mut(),
-                                selection,
-                                &event_tx,
-                            );
-                            request.respond(worker_id);
-                            continue 'outer;
-                        }
-                        Err(KvSchedulerError::AllWorkersBusy) => {
-                            tracing::trace!("all workers busy; waiting for more capacity");
-                            match endpoints_rx.changed().await {
-                                Ok(_) => {}
-                                Err(e) => {
-                                    tracing::error!("error waiting for endpoints change: {:?}", e);
-                                    break 'outer;
-                                }
-                            };
-                            endpoints = endpoints_rx.borrow_and_update().clone();
-                        }
-                        Err(e) => {
-                            tracing::error!("error scheduling request: {:?}", e);
-                            break 'outer;
-                        }
-                    }
-                }
-            }
+/// Scheduling priority classes, each with its own queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// Latency-sensitive work, such as interactive streams
+    #[default]
+    High,
+    /// Bulk work that yields to high priority requests
+    Low,
+}
 
-            tracing::trace!("background endpoint subscriber shutting down");
+impl SchedulingRequest {
+    /// A request with no caller waiting on the response, for driving a selector directly.
+    pub(crate) fn detached(overlap: OverlapScores, isl_tokens: usize) -> Self {
+        Self {
+            isl_tokens,
+            overlap,
+            role: WorkerRole::default(),
+            prefix_hash: None,
+            priority: Priority::default(),
+            tenant_id: None,
+            session_id: None,
+            idempotency_key: None,
+            deadline: None,
+            exclude: Vec::new(),
+            zone: None,
+            expected_output_tokens: None,
+            stream_id: None,
+            request_id: None,
+            block_size: None,
+            #[cfg(feature = "otel")]
+            otel_context: None,
+            resp_tx: tokio::sync::oneshot::channel().0,
+            speculative: false,
+            explain_tx: None,
+            enqueued_at: Instant::now(),
+            outcomes: None,
+        }
+    }
+
+    pub fn respond(self, worker_id: WorkerId) {
+        self.dispatch(Scheduled {
+            worker_id,
+            secondary: None,
+            timing: SchedulingTiming::default(),
         });
+    }
 
-        Ok(KvScheduler { request_tx })
+    fn dispatch(self, scheduled: Scheduled) {
+        // A requester that went away before its selection arrived counts as a failure
+        let delivered = self.resp_tx.send(Ok(scheduled)).is_ok();
+        if !delivered {
+            tracing::trace!("failed to send response to requestor");
+        }
+        record_outcome(self.outcomes.as_deref(), !delivered);
     }
 
-    pub async fn schedule(
-        &self,
-        overlap: OverlapScores,
-        isl_tokens: usize,
-    ) -> Result<i64, KvSchedulerError> {
-        let (resp_tx, resp_rx) = tokio::sync::oneshot::channel();
-        let request = SchedulingRequest {
-            isl_tokens,
-            overlap,
-            resp_tx,
-        };
-        self.request_tx
-            .send(request)
-            .await
-            .map_err(|_| KvSchedulerError::SubscriberShutdown)?;
-        let res = resp_rx
-            .await
-            .map_err(|_| KvSchedulerError::SubscriberShutdown)?;
-        Ok(res)
+    /// Whether the requester stopped waiting for the response.
+    fn abandoned(&self) -> bool {
+        self.resp_tx.is_closed()
+    }
+
+    /// Drop a request whose requester went away, without selecting a worker for it.
+    fn discard(self) {
+        tracing::trace!("requester went away; skipping selection");
+        record_outcome(self.outcomes.as_deref(), true);
+    }
+
+    /// Fail the request with `error`.
+    fn reject(self, error: KvSchedulerError) {
+        if self.resp_tx.send(Err(error)).is_err() {
+            tracing::trace!("failed to send rejection to requestor");
+        }
+        record_outcome(self.outcomes.as_deref(), true);
     }
 }
 
-// This becomes the driver function that handles the selection result
-pub fn process_worker_selection(
-    workers: &mut ProcessedEndpoints,
-    selection: WorkerSelectionResult,
-    event_tx: &tokio::sync::mpsc::UnboundedSender<KVHitRateEvent>,
-) -> i64 {
-    let worker = workers
-        .endpoints
-        .get_mut(&selection.worker_id)
-        .expect("worker not found");
+/// Default time [`KvScheduler::rejection_rate`] remembers outcomes for.
+pub const DEFAULT_REJECTION_RATE_RETENTION: Duration = Duration::from_secs(600);
 
-    // Update worker state predictively
-    // Will be overwritten on next polling of metrics
-    worker.data.num_requests_waiting += 1;
-    // Assumes radix attention so KV load is only incremented by uncached blocks
-    // overlap_blocks can be bigger than required_blocks. I don't know if that's a bug or not.
-    worker.data.kv_active_blocks += selection
-        .required_blocks
-        .saturating_sub(selection.overlap_blocks as u64);
+/// One scheduling decision, as kept by [`KvScheduler::recent_decisions`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecisionRecord {
+    pub timestamp: std::time::SystemTime,
+    pub request_id: Option<String>,
+    pub isl_tokens: usize,
+    pub chosen_worker: WorkerId,
+    /// Logit of the chosen worker, or 0.0 if it was chosen without scoring
+    pub logit: f64,
+    /// Logit of every candidate scored, in worker id order. Empty for decisions made without
+    /// scoring, such as sticky sessions, or by an async selector.
+    pub candidates: Vec<(WorkerId, f64)>,
+}
 
-    // Emit event
-    if let Err(e) = event_tx.send(KVHitRateEvent {
-        worker_id: selection.worker_id,
-        isl_blocks: selection.required_blocks as usize,
-        overlap_blocks: selection.overlap_blocks,
-    }) {
-        tracing::warn!("Failed to send KV hit rate event: {:?}", e);
-    }
+/// Run a selector call, turning a panic in it into [`KvSchedulerError::SelectorPanicked`] so it
+/// can't take down the scheduling task.
+fn isolate_panics<T>(
+    select: impl FnOnce() -> Result<T, KvSchedulerError>,
+) -> Result<T, KvSchedulerError> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(select))
+        .unwrap_or_else(|payload| Err(selector_panicked(payload)))
+}
 
-    selection.worker_id
+/// The error for a selector panic with `payload`.
+fn selector_panicked(payload: Box<dyn std::any::Any + Send>) -> KvSchedulerError {
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    tracing::error!("worker selector panicked: {message}");
+    KvSchedulerError::SelectorPanicked(message)
 }
 
-// Default implementation matching the Python _cost_function
-#[derive(Debug, Clone, Default)]
-pub struct DefaultWorkerSelector {
-    pub kv_router_config: KvRouterConfig,
+fn record_outcome(outcomes: Option<&Mutex<OutcomeWindow>>, failed: bool) {
+    if let Some(outcomes) = outcomes {
+        outcomes
+            .lock()
+            .expect("outcome window lock poisoned")
+            .record(failed, Instant::now());
+    }
+}
+
+/// A request taken off the queues, with its scheduling so far.
+struct PendingRequest {
+    request: SchedulingRequest,
+    timing: SchedulingTiming,
+    /// When the request last found every worker busy, while it waits for capacity
+    busy_since: Option<Instant>,
+    /// Retries taken so far because there were no eligible workers
+    no_endpoints_retries: u32,
+    /// When the request is next retried, while it backs off after finding no eligible workers
+    retry_at: Option<Instant>,
+    /// Ended when dropped, should the task stop before the request is scheduled
+    #[cfg(feature = "otel")]
+    span: Option<super::otel::SchedulingSpan>,
 }
 
-impl DefaultWorkerSelector {
-    pub fn new(kv_router_config: Option<KvRouterConfig>) -> Self {
+impl PendingRequest {
+    fn new(request: SchedulingRequest) -> Self {
         Self {
-            kv_router_config: kv_router_config.unwrap_or_default(),
+            timing: SchedulingTiming {
+                queue_wait: request.enqueued_at.elapsed(),
+                capacity_wait: Duration::ZERO,
+            },
+            busy_since: None,
+            no_endpoints_retries: 0,
+            retry_at: None,
+            #[cfg(feature = "otel")]
+            span: request
+                .otel_context
+                .as_ref()
+                .map(super::otel::SchedulingSpan::start),
+            request,
         }
     }
 }
 
-impl WorkerSelector for DefaultWorkerSelector {
-    fn select_worker(
-        &self,
-        workers: &ProcessedEndpoints,
-        request: &SchedulingRequest,
-        block_size: usize,
-    ) -> Result<WorkerSelectionResult, KvSchedulerError> {
-        assert!(request.isl_tokens > 0);
-
-        if workers.endpoints.is_empty() {
-            return Err(KvSchedulerError::NoEndpoints);
-        }
+/// A request on `worker_id` finished and released `freed_blocks` KV blocks.
+#[derive(Debug, Clone, Copy)]
+struct Completion {
+    worker_id: WorkerId,
+    freed_blocks: u64,
+}
 
-        let mut worker_scores = HashMap::new();
-        let mut max_waiting = 0.0;
+/// Selections of unknown workers are retried this many times before the request fails.
+const MAX_SELECTION_ATTEMPTS: usize = 3;
 
-        // Calculate worker scores and find max waiting requests
-        for (worker_id, ep) in workers.endpoints.iter() {
-            // Calculate score similar to Python version
-            if let Some(score) = request.overlap.scores.get(worker_id) {
-                let score = *score as f64 * block_size as f64 / request.isl_tokens as f64;
-                worker_scores.insert(worker_id, score);
-            }
+/// Minimum interval between published selection distribution snapshots.
+const DISTRIBUTION_PUBLISH_INTERVAL: Duration = Duration::from_millis(100);
 
-            // Track max waiting requests
-            max_waiting = f64::max(max_waiting, ep.data.num_requests_waiting as f64);
-        }
+/// Default upper bounds of the [`KvScheduler::logit_histogram`] buckets.
+pub const DEFAULT_LOGIT_HISTOGRAM_BUCKETS: &[f64] = &[-2.0, -1.0, -0.5, 0.0, 0.5, 1.0, 2.0];
 
-        // make immutable
-        let worker_scores = worker_scores;
-        let max_waiting = max_waiting;
+/// Load on the scheduler and its workers as a single value from 0 (idle) to 1 (saturated): the
+/// weighted mean of `queue_fill`, the workers' mean GPU cache usage and their mean waiting
+/// requests relative to `pressure_waiting_saturation`.
+fn pressure(config: &KvRouterConfig, endpoints: &ProcessedEndpoints, queue_fill: f64) -> f64 {
+    let workers = &endpoints.endpoints;
+    let (usage, waiting) = if workers.is_empty() {
+        (0.0, 0.0)
+    } else {
+        let saturation = config.pressure_waiting_saturation.max(1) as f64;
+        let (usage, waiting) = workers.values().fold((0.0, 0.0), |(usage, waiting), ep| {
+            (
+                usage + (ep.data.gpu_cache_usage_perc as f64).clamp(0.0, 1.0),
+                waiting + (ep.data.num_requests_waiting as f64 / saturation).min(1.0),
+            )
+        });
+        (usage / workers.len() as f64, waiting / workers.len() as f64)
+    };
+    let terms = [
+        (config.pressure_queue_weight, queue_fill.clamp(0.0, 1.0)),
+        (config.pressure_usage_weight, usage),
+        (config.pressure_waiting_weight, waiting),
+    ];
+    let total_weight: f64 = terms.iter().map(|(weight, _)| weight).sum();
+    if total_weight <= 0.0 {
+        return 0.0;
+    }
+    terms
+        .iter()
+        .map(|(weight, value)| weight * value)
+        .sum::<f64>()
+        / total_weight
+}
 
-        // Calculate logits for each worker
-        let mut best_logit = f64::NEG_INFINITY;
-        let mut best_workers = Vec::new();
+/// The overlap weight adaptive tuning moves `config` to, given the recent `hit_rate` and the
+/// workers' `mean_usage`, or `None` if tuning is disabled or the weight stays put.
+///
+/// Overload takes precedence, so a low hit rate never steers more work onto full workers.
+fn tuned_overlap_weight(
+    config: &KvRouterConfig,
+    hit_rate: Option<f64>,
+    mean_usage: f64,
+) -> Option<f64> {
+    let target = config.adaptive_target_hit_rate?;
+    let weight = config.overlap_score_weight;
+    let tuned = if mean_usage > config.adaptive_overload_usage {
+        weight - config.adaptive_overlap_step
+    } else if hit_rate? < target {
+        weight + config.adaptive_overlap_step
+    } else {
+        return None;
+    };
+    let tuned = tuned.clamp(
+        config.adaptive_overlap_weight_min,
+        config.adaptive_overlap_weight_max,
+    );
+    (tuned != weight).then_some(tuned)
+}
 
-        for (worker_id, ep) in workers.endpoints.iter() {
-            let worker_id = *worker_id;
+/// The scheduler's view of a worker, as returned by [`KvScheduler::worker_stats`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorkerStats {
+    /// Metrics from the worker's latest report
+    pub metrics: ForwardPassMetrics,
+    /// Change the scheduler has predicted to `kv_active_blocks` since that report
+    pub kv_active_blocks_delta: i64,
+    /// Change the scheduler has predicted to `num_requests_waiting` since that report
+    pub num_requests_waiting_delta: i64,
+}
 
-            // Get score or default to 0.0
-            let score = worker_scores.get(&worker_id).copied().unwrap_or(0.0);
+/// A point-in-time view of a running scheduler, as returned by [`KvScheduler::state_snapshot`]
+/// and served by [`KvScheduler::serve_state`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchedulerStateSnapshot {
+    /// Each known worker's latest metrics and predicted load, as in [`KvScheduler::worker_stats`]
+    pub workers: HashMap<WorkerId, WorkerStats>,
+    /// Routing configuration last applied
+    pub config: KvRouterConfig,
+    /// Requests waiting in the scheduling queues, across priorities
+    pub queue_len: usize,
+    /// Capacity of each priority's scheduling queue
+    pub queue_capacity: usize,
+    /// Requests queued, held or predicted in flight, as in [`KvScheduler::load`]
+    pub load: usize,
+    /// Per-worker hit rates, as in [`KvScheduler::hit_rates`]
+    pub hit_rates: HashMap<WorkerId, f64>,
+    /// Events dropped because the publishing task fell behind
+    pub dropped_events: u64,
+}
 
-            // Calculate normalized metrics
-            let gpu_cache_usage = ep.data.gpu_cache_usage_perc as f64;
-            let normalized_waiting = if max_waiting > 0.0 {
-                ep.data.num_requests_waiting as f64 / max_waiting
-            } else {
-                0.0
-            };
+/// Predictive state of a scheduler, as exported by [`KvScheduler::export_state`], so it can be
+/// persisted across a restart and seeded with [`KvSchedulerBuilder::start_with_state`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SchedulerState {
+    /// Load predicted on each worker since its latest metrics report
+    pub predicted: HashMap<WorkerId, PredictedLoad>,
+    /// Workers never selected, as set by [`KvScheduler::set_blacklist`]
+    pub blacklist: HashSet<WorkerId>,
+    /// Worker each live session was last routed to
+    pub sessions: HashMap<String, SessionAssignment>,
+    /// Routing configuration last applied
+    pub config: KvRouterConfig,
+}
 
-            // Calculate logit using same formula as Python
-            let logit = self.kv_router_config.overlap_score_weight * score
-                - self.kv_router_config.gpu_cache_usage_weight * gpu_cache_usage
-                - self.kv_router_config.waiting_requests_weight * normalized_waiting;
+/// Load predicted on a worker on top of its latest metrics report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PredictedLoad {
+    pub kv_active_blocks_delta: i64,
+    pub num_requests_waiting_delta: i64,
+}
 
-            tracing::trace!(
-                "Formula for {worker_id}: {logit:.3} = {:.1} * {score:.3} - {:.1} * {gpu_cache_usage:.3} - {:.1} * {normalized_waiting:.3}",
-                self.kv_router_config.overlap_score_weight,
-                self.kv_router_config.gpu_cache_usage_weight,
-                self.kv_router_config.waiting_requests_weight,
-            );
+/// A session's affinity, as carried over by [`SchedulerState`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionAssignment {
+    pub worker_id: WorkerId,
+    /// Milliseconds since the session was last routed
+    pub idle_ms: u64,
+}
 
-            // Track best workers
-            match logit.partial_cmp(&best_logit) {
-                Some(std::cmp::Ordering::Greater) => {
-                    best_logit = logit;
-                    best_workers.clear();
-                    best_workers.push(worker_id);
-                }
-                Some(std::cmp::Ordering::Equal) => {
-                    best_workers.push(worker_id);
-                }
-                _ => {}
-            }
-        }
+/// Read-only handles on a scheduler's published state. The queues are held weakly so a state
+/// endpoint doesn't keep the scheduler running after it is dropped.
+#[derive(Clone)]
+struct StateSource {
+    request_tx: tokio::sync::mpsc::WeakSender<SchedulingRequest>,
+    low_priority_tx: tokio::sync::mpsc::WeakSender<SchedulingRequest>,
+    stats_rx: tokio::sync::watch::Receiver<HashMap<WorkerId, WorkerStats>>,
+    hit_rate_rx: tokio::sync::watch::Receiver<HashMap<WorkerId, f64>>,
+    config_rx: tokio::sync::watch::Receiver<KvRouterConfig>,
+    accepted: Arc<AtomicUsize>,
+    dropped_events: Arc<AtomicU64>,
+}
 
-        // Return early if no valid workers found
-        if best_workers.is_empty() {
-            return Err(KvSchedulerError::NoEndpoints);
-        } else if best_logit == 0.0 {
-            tracing::debug!("best worker logit is 0");
+impl StateSource {
+    fn snapshot(&self) -> SchedulerStateSnapshot {
+        let queues: Vec<_> = [&self.request_tx, &self.low_priority_tx]
+            .into_iter()
+            .filter_map(|tx| tx.upgrade())
+            .collect();
+        let queue_len = queues.iter().map(queued).sum();
+        SchedulerStateSnapshot {
+            workers: self.stats_rx.borrow().clone(),
+            config: self.config_rx.borrow().clone(),
+            queue_len,
+            queue_capacity: queues.first().map_or(0, |tx| tx.max_capacity()),
+            load: queue_len + self.accepted.load(Ordering::Relaxed),
+            hit_rates: self.hit_rate_rx.borrow().clone(),
+            dropped_events: self.dropped_events.load(Ordering::Relaxed),
         }
+    }
+}
 
-        let worker_id = if best_workers.len() == 1 {
-            best_workers[0]
-        } else {
-            // Randomly select from best workers
-            let mut rng = rand::rng();
-            best_workers[rng.random_range(0..best_workers.len())]
-        };
-
-        // Lower to trace level eventually. Nice to see KV routing working for now.
-        tracing::debug!("Selected worker: {worker_id}, logit: {best_logit:.3}");
+/// Requests waiting in a scheduling queue.
+fn queued(tx: &tokio::sync::mpsc::Sender<SchedulingRequest>) -> usize {
+    tx.max_capacity() - tx.capacity()
+}
 
-        // Log selection metrics
-        let total_blocks = std::cmp::max(request.isl_tokens / block_size, 1) as u64;
-        let overlap_blocks = request.overlap.scores.get(&worker_id).copied().unwrap_or(0) as usize;
+/// Answers each request on the state endpoint with a single [`SchedulerStateSnapshot`].
+struct StateEndpointHandler {
+    source: StateSource,
+}
 
-        Ok(WorkerSelectionResult {
-            worker_id,
-            required_blocks: total_blocks,
-            overlap_blocks,
-        })
+#[async_trait]
+impl AsyncEngine<SingleIn<()>, ManyOut<Annotated<SchedulerStateSnapshot>>, dynamo_runtime::Error>
+    for StateEndpointHandler
+{
+    async fn generate(
+        &self,
+        request: SingleIn<()>,
+    ) -> anyhow::Result<ManyOut<Annotated<SchedulerStateSnapshot>>> {
+        let context = request.context();
+        let snapshot = self.source.snapshot();
+        let stream = futures::stream::iter(vec![Annotated::from_data(snapshot)]);
+        Ok(ResponseStream::new(Box::pin(stream), context))
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Publishes [`WorkerStats`] by comparing the predicted endpoints against the last poll.
+struct WorkerStatsPublisher {
+    polled: ProcessedEndpoints,
+    dirty: bool,
+    tx: tokio::sync::watch::Sender<HashMap<WorkerId, WorkerStats>>,
+}
 
-    // Helper to create a worker endpoint
-    fn create_endpoint(
-        worker_id: i64,
-        gpu_cache_usage_perc: f32,
-        num_requests_waiting: u64,
-    ) -> Endpoint {
-        Endpoint {
-            name: format!("worker-{}", worker_id),
-            subject: format!("worker-subject-{:x}", worker_id),
-            data: ForwardPassMetrics {
-                gpu_cache_usage_perc,
-                num_requests_waiting,
-                // Other fields can be default initialized for this test
-                ..Default::default()
+impl WorkerStatsPublisher {
+    fn new(tx: tokio::sync::watch::Sender<HashMap<WorkerId, WorkerStats>>) -> Self {
+        Self {
+            polled: ProcessedEndpoints::default(),
+            dirty: false,
+            tx,
+        }
+    }
+
+    /// Publish the stats for `predicted` if anything changed since the last call.
+    fn publish(&mut self, predicted: &ProcessedEndpoints) {
+        if !self.dirty {
+            return;
+        }
+        self.tx.send_replace(self.stats(predicted));
+        self.dirty = false;
+    }
+
+    /// Each worker's latest polled metrics, and the load `predicted` adds on top of them.
+    fn stats(&self, predicted: &ProcessedEndpoints) -> HashMap<WorkerId, WorkerStats> {
+        predicted
+            .endpoints
+            .iter()
+            .map(|(worker_id, endpoint)| {
+                let metrics = self
+                    .polled
+                    .endpoints
+                    .get(worker_id)
+                    .map(|polled| polled.data.clone())
+                    .unwrap_or_else(|| endpoint.data.clone());
+                let stats = WorkerStats {
+                    kv_active_blocks_delta: endpoint.data.kv_active_blocks as i64
+                        - metrics.kv_active_blocks as i64,
+                    num_requests_waiting_delta: endpoint.data.num_requests_waiting as i64
+                        - metrics.num_requests_waiting as i64,
+                    metrics,
+                };
+                (*worker_id, stats)
+            })
+            .collect()
+    }
+}
+
+/// Histograms of how long requests wait before a worker is selected.
+struct SchedulerMetrics {
+    queue_wait: prometheus::Histogram,
+    capacity_wait: prometheus::Histogram,
+}
+
+impl SchedulerMetrics {
+    fn new(registry: &prometheus::Registry) -> Result<Self, prometheus::Error> {
+        let opts = |name: &str, help: &str| {
+            prometheus::HistogramOpts::new(name, help)
+                .namespace("dynamo")
+                .subsystem("kv_scheduler")
+        };
+        let queue_wait = prometheus::register_histogram_with_registry!(
+            opts(
+                "queue_wait_seconds",
+                "Time requests spent in the scheduling queue"
+            ),
+            registry
+        )?;
+        let capacity_wait = prometheus::register_histogram_with_registry!(
+            opts(
+                "capacity_wait_seconds",
+                "Time requests spent waiting for a worker to free up"
+            ),
+            registry
+        )?;
+        Ok(Self {
+            queue_wait,
+            capacity_wait,
+        })
+    }
+
+    fn observe(&self, timing: &SchedulingTiming) {
+        self.queue_wait.observe(timing.queue_wait.as_secs_f64());
+        self.capacity_wait
+            .observe(timing.capacity_wait.as_secs_f64());
+    }
+}
+
+pub struct KvScheduler {
+    request_tx: tokio::sync::mpsc::Sender<SchedulingRequest>,
+    low_priority_tx: tokio::sync::mpsc::Sender<SchedulingRequest>,
+    completion_tx: tokio::sync::mpsc::UnboundedSender<Completion>,
+    control_tx: tokio::sync::mpsc::UnboundedSender<ControlMessage>,
+    distribution_rx: tokio::sync::watch::Receiver<HashMap<WorkerId, usize>>,
+    stats_rx: tokio::sync::watch::Receiver<HashMap<WorkerId, WorkerStats>>,
+    hit_rate_rx: tokio::sync::watch::Receiver<HashMap<WorkerId, f64>>,
+    logit_histogram_rx: tokio::sync::watch::Receiver<Vec<(f64, u64)>>,
+    pressure_rx: tokio::sync::watch::Receiver<f64>,
+    oscillation_rx: tokio::sync::watch::Receiver<f64>,
+    event_tx: EventSender,
+    config_tx: tokio::sync::watch::Sender<KvRouterConfig>,
+    flush_tx: tokio::sync::mpsc::UnboundedSender<FlushRequest>,
+    /// Requests predicted in flight or held by the background task
+    accepted: Arc<AtomicUsize>,
+    /// Set once the background task has stopped, for whatever reason
+    dead: Arc<AtomicBool>,
+    endpoint_reads: Arc<AtomicU64>,
+    rate_limiter: Mutex<TenantRateLimiter>,
+    outcomes: Arc<Mutex<OutcomeWindow>>,
+    decisions: Option<Arc<Mutex<DecisionLog>>>,
+}
+
+/// Asks a publishing task to publish every event buffered so far, answered once it has.
+pub(crate) type FlushRequest = tokio::sync::oneshot::Sender<()>;
+
+/// Longest [`KvScheduler::shutdown`] waits for buffered events to be published.
+pub const DEFAULT_EVENT_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where the background task delivers a request's selection.
+pub(crate) type ResponseReceiver =
+    tokio::sync::oneshot::Receiver<Result<Scheduled, KvSchedulerError>>;
+
+/// Default capacity of the scheduling request queue.
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Default window over which the selection distribution is counted.
+pub const DEFAULT_DISTRIBUTION_WINDOW: Duration = Duration::from_secs(60);
+
+/// Default window over which per-worker hit rates are computed.
+pub const DEFAULT_HIT_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Options for starting a [`KvScheduler`], assembled through [`KvSchedulerBuilder`].
+#[derive(Builder)]
+#[builder(
+    name = "KvSchedulerBuilder",
+    pattern = "owned",
+    build_fn(private, name = "build_internal")
+)]
+pub struct KvSchedulerOptions {
+    /// Namespace on which scheduler events are published.
+    /// Required unless an event sink is provided.
+    #[builder(default, setter(strip_option))]
+    namespace: Option<Namespace>,
+
+    /// Custom destination for scheduler events
+    #[builder(default, private)]
+    _event_sink: Option<Arc<dyn EventSink>>,
+
+    /// KV block size in tokens, for workers that don't report their own
+    block_size: usize,
+
+    /// Watch channel delivering the latest worker metrics
+    endpoints: tokio::sync::watch::Receiver<ProcessedEndpoints>,
+
+    /// Worker selection logic; defaults to [`DefaultWorkerSelector`]
+    #[builder(default, private)]
+    _selector: Option<Box<dyn WorkerSelector + Send + Sync>>,
+
+    /// Asynchronous selection logic, awaited in place of the selector for plain selections
+    #[builder(default, private)]
+    _async_selector: Option<Arc<dyn AsyncWorkerSelector>>,
+
+    /// Service time estimates behind request deadlines; defaults to [`LinearServiceTimeModel`]
+    #[builder(default, private)]
+    _service_time_model: Option<Box<dyn ServiceTimeModel>>,
+
+    /// Routing configuration. Defaults to the selector's configuration, if it exposes one.
+    #[builder(default, setter(strip_option))]
+    config: Option<KvRouterConfig>,
+
+    /// Capacity of the scheduling request queue
+    #[builder(default = "DEFAULT_CHANNEL_CAPACITY")]
+    channel_capacity: usize,
+
+    /// Capacity of the queue of events awaiting publication. Events beyond it are dropped and
+    /// counted in [`KvScheduler::dropped_events`].
+    #[builder(default = "DEFAULT_EVENT_CHANNEL_CAPACITY")]
+    event_channel_capacity: usize,
+
+    /// Subject on which [`KVHitRateEvent`]s are published
+    #[builder(default = "KV_HIT_RATE_SUBJECT.to_string()", setter(into))]
+    hit_rate_subject: String,
+
+    /// Format events are published in on the namespace. Ignored with a custom event sink.
+    #[builder(default)]
+    event_encoding: EventEncoding,
+
+    /// Window over which [`KvScheduler::selection_distribution`] counts selections
+    #[builder(default = "DEFAULT_DISTRIBUTION_WINDOW")]
+    distribution_window: Duration,
+
+    /// Window over which [`KvScheduler::hit_rate`] is computed
+    #[builder(default = "DEFAULT_HIT_RATE_WINDOW")]
+    hit_rate_window: Duration,
+
+    /// Upper bounds of the buckets [`KvScheduler::logit_histogram`] counts selected logits into.
+    /// Logits above the highest bound fall in a final unbounded bucket.
+    #[builder(default = "DEFAULT_LOGIT_HISTOGRAM_BUCKETS.to_vec()")]
+    logit_histogram_buckets: Vec<f64>,
+
+    /// How long [`KvScheduler::rejection_rate`] remembers outcomes for
+    #[builder(default = "DEFAULT_REJECTION_RATE_RETENTION")]
+    rejection_rate_retention: Duration,
+
+    /// Number of scheduling decisions [`KvScheduler::recent_decisions`] keeps. Unset keeps
+    /// none, and decisions aren't recorded at all.
+    #[builder(default, setter(strip_option))]
+    decision_log_capacity: Option<usize>,
+
+    /// Callback invoked on every selection
+    #[builder(default, private)]
+    _on_selection: Option<SelectionHook>,
+
+    /// Registry for the scheduler's queue and capacity wait histograms
+    #[builder(default, setter(strip_option))]
+    metrics_registry: Option<prometheus::Registry>,
+
+    /// Health of each worker from an external liveness checker. Workers reported unhealthy are
+    /// not selected until reported healthy again; workers absent from the map are healthy.
+    #[builder(default, setter(strip_option))]
+    health: Option<tokio::sync::watch::Receiver<HashMap<WorkerId, bool>>>,
+
+    /// Worker zones and the distances between zones, for the locality penalty
+    #[builder(default, setter(strip_option))]
+    topology: Option<Topology>,
+
+    /// Stream of `(worker_id, freed_blocks)` completions, applied like
+    /// [`KvScheduler::complete`] by the background task. Completions for unknown workers are
+    /// ignored.
+    #[builder(default, setter(strip_option))]
+    completions: Option<tokio::sync::mpsc::Receiver<(WorkerId, u64)>>,
+
+    /// State exported by a previous scheduler, seeded before the first selection
+    #[builder(default, private)]
+    _restored_state: Option<SchedulerState>,
+}
+
+/// Most completions applied from the completion stream at once.
+const COMPLETION_BATCH_SIZE: usize = 64;
+
+/// Receive the next batch of completions into `batch`, returning how many arrived. Returns 0
+/// once the stream closes, and never if there is no stream.
+async fn recv_completions(
+    completions_rx: &mut Option<tokio::sync::mpsc::Receiver<(WorkerId, u64)>>,
+    batch: &mut Vec<(WorkerId, u64)>,
+) -> usize {
+    match completions_rx {
+        Some(rx) => rx.recv_many(batch, COMPLETION_BATCH_SIZE).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// In-process callback invoked with each selection once its predictive updates are applied.
+pub type SelectionHook = Box<dyn Fn(&WorkerSelectionResult) + Send + Sync>;
+
+impl KvSchedulerBuilder {
+    /// Select workers with `selector` instead of a [`DefaultWorkerSelector`].
+    ///
+    /// A panic in the selector fails the request being selected for with
+    /// [`KvSchedulerError::SelectorPanicked`] rather than the whole scheduling task. The
+    /// isolation ends at the selector's own state: a lock it held while panicking stays
+    /// poisoned and anything it mutated stays half done, so later selections may panic or
+    /// misroute in turn. Panics with `panic = "abort"` are not caught at all.
+    pub fn selector(self, selector: Box<dyn WorkerSelector + Send + Sync>) -> Self {
+        self._selector(Some(selector))
+    }
+
+    /// Select workers with `selector`, awaited on the scheduling task for each request that
+    /// isn't speculative or explained. Scheduling waits on it, up to
+    /// [`KvRouterConfig::selection_timeout_ms`]. Panics are isolated to the request as with
+    /// [`KvSchedulerBuilder::selector`].
+    pub fn async_selector(self, selector: impl AsyncWorkerSelector + 'static) -> Self {
+        self._async_selector(Some(Arc::new(selector)))
+    }
+
+    pub fn service_time_model(self, model: impl ServiceTimeModel + 'static) -> Self {
+        self._service_time_model(Some(Box::new(model)))
+    }
+
+    /// Invoke `hook` synchronously on the scheduling task after each selection. It should return
+    /// quickly, since scheduling waits on it.
+    pub fn on_selection(
+        self,
+        hook: impl Fn(&WorkerSelectionResult) + Send + Sync + 'static,
+    ) -> Self {
+        self._on_selection(Some(Box::new(hook)))
+    }
+
+    pub fn event_sink(self, sink: impl EventSink + 'static) -> Self {
+        self._event_sink(Some(Arc::new(sink)))
+    }
+
+    /// Publish events to every one of `sinks`, through a [`FanOutEventSink`].
+    pub fn event_sinks(self, sinks: Vec<Box<dyn EventSink>>) -> Self {
+        self.event_sink(FanOutEventSink::new(sinks))
+    }
+
+    /// Like [`KvSchedulerBuilder::start`], seeded with `state` exported from a previous scheduler
+    /// by [`KvScheduler::export_state`], so a restart doesn't forget the load it predicted. The
+    /// exported configuration applies unless one is set on the builder.
+    pub async fn start_with_state(
+        self,
+        state: SchedulerState,
+    ) -> Result<KvScheduler, KvSchedulerError> {
+        self._restored_state(Some(state)).start().await
+    }
+
+    /// Validate the options and start the scheduler's background tasks.
+    pub async fn start(self) -> Result<KvScheduler, KvSchedulerError> {
+        self.start_with_events(|event_sink, event_rx, flush_rx| {
+            tokio::spawn(publish_events(event_sink, event_rx, flush_rx));
+        })
+        .await
+    }
+
+    /// Like [`KvSchedulerBuilder::start`], but with the scheduler's events handed to
+    /// `publish_events` along with their sink and flush requests instead of to a publishing
+    /// task of its own.
+    pub(crate) async fn start_with_events(
+        self,
+        publish_events: impl FnOnce(
+            Arc<dyn EventSink>,
+            tokio::sync::mpsc::Receiver<SchedulerEvent>,
+            tokio::sync::mpsc::UnboundedReceiver<FlushRequest>,
+        ),
+    ) -> Result<KvScheduler, KvSchedulerError> {
+        let options = self
+            .build_internal()
+            .map_err(|e| KvSchedulerError::InvalidConfig(e.to_string()))?;
+        if options.channel_capacity == 0 {
+            return Err(KvSchedulerError::InvalidConfig(
+                "channel_capacity must be greater than zero".to_string(),
+            ));
+        }
+        if options.event_channel_capacity == 0 {
+            return Err(KvSchedulerError::InvalidConfig(
+                "event_channel_capacity must be greater than zero".to_string(),
+            ));
+        }
+        if options
+            .logit_histogram_buckets
+            .iter()
+            .any(|b| !b.is_finite())
+        {
+            return Err(KvSchedulerError::InvalidConfig(
+                "logit_histogram_buckets must be finite".to_string(),
+            ));
+        }
+        if options.decision_log_capacity == Some(0) {
+            return Err(KvSchedulerError::InvalidConfig(
+                "decision_log_capacity must be greater than zero".to_string(),
+            ));
+        }
+
+        let event_sink: Arc<dyn EventSink> = match (options._event_sink, options.namespace) {
+            (Some(sink), _) => sink,
+            (None, Some(ns)) => Arc::new(
+                NamespaceEventSink::new(ns, options.hit_rate_subject)
+                    .with_encoding(options.event_encoding),
+            ),
+            (None, None) => {
+                return Err(KvSchedulerError::InvalidConfig(
+                    "either a namespace or an event sink is required".to_string(),
+                ));
+            }
+        };
+        let restored = options._restored_state;
+        let mut selector = options
+            ._selector
+            .unwrap_or(Box::new(DefaultWorkerSelector::new(options.config.clone())));
+        let config = match (options.config, &restored) {
+            (Some(config), _) => config,
+            (None, Some(restored)) => {
+                restored
+                    .config
+                    .validate()
+                    .map_err(|e| KvSchedulerError::InvalidConfig(e.to_string()))?;
+                selector.set_kv_router_config(restored.config.clone());
+                restored.config.clone()
+            }
+            (None, None) => selector.kv_router_config().unwrap_or_default(),
+        };
+        let metrics = options
+            .metrics_registry
+            .as_ref()
+            .map(SchedulerMetrics::new)
+            .transpose()
+            .map_err(|e| {
+                KvSchedulerError::InvalidConfig(format!("failed to register metrics: {e}"))
+            })?;
+        // Channel to deliver configuration updates
+        let (config_tx, mut config_rx) = tokio::sync::watch::channel(config.clone());
+        let mut endpoints_rx = options.endpoints;
+        let endpoints: ProcessedEndpoints = endpoints_rx.borrow_and_update().clone();
+
+        let (event_tx, event_rx) = EventSender::channel(options.event_channel_capacity);
+        let scheduler_event_tx = event_tx.clone();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let dead = Arc::new(AtomicBool::new(false));
+        let endpoint_reads = Arc::new(AtomicU64::new(0));
+        let (flush_tx, flush_rx) = tokio::sync::mpsc::unbounded_channel();
+        publish_events(event_sink, event_rx, flush_rx);
+
+        // Channels to accept new scheduling requests, one per priority
+        let (request_tx, request_rx) =
+            tokio::sync::mpsc::channel::<SchedulingRequest>(options.channel_capacity);
+        let (low_priority_tx, low_priority_rx) =
+            tokio::sync::mpsc::channel::<SchedulingRequest>(options.channel_capacity);
+        // Channel to accept completion reports
+        let (completion_tx, completion_rx) = tokio::sync::mpsc::unbounded_channel::<Completion>();
+        // Channel to accept operator controls
+        let (control_tx, control_rx) = tokio::sync::mpsc::unbounded_channel::<ControlMessage>();
+        // Channel to publish the selection distribution
+        let (distribution_tx, distribution_rx) = tokio::sync::watch::channel(HashMap::new());
+        // Channel to publish per-worker stats
+        let (stats_tx, stats_rx) = tokio::sync::watch::channel(HashMap::new());
+        // Channel to publish per-worker hit rates
+        let (hit_rate_tx, hit_rate_rx) = tokio::sync::watch::channel(HashMap::new());
+        // Channel to publish the autoscaling pressure
+        let (pressure_tx, pressure_rx) = tokio::sync::watch::channel(0.0);
+        let (oscillation_tx, oscillation_rx) = tokio::sync::watch::channel(0.0);
+        let decisions = options
+            .decision_log_capacity
+            .map(|capacity| Arc::new(Mutex::new(DecisionLog::new(capacity))));
+        let logits = LogitHistogram::new(options.logit_histogram_buckets);
+        let logit_histogram_rx = logits.tx.subscribe();
+
+        let mut state = TaskState {
+            block_size: options.block_size,
+            selector,
+            async_selector: options._async_selector,
+            endpoints: ProcessedEndpoints::default(),
+            event_tx,
+            throughput: ThroughputTracker::default(),
+            draining: HashSet::new(),
+            blacklist: HashSet::new(),
+            allowlist: None,
+            paused: false,
+            held: VecDeque::new(),
+            busy: VecDeque::new(),
+            unhealthy: options
+                .health
+                .as_ref()
+                .map(|health| unhealthy_workers(&health.borrow()))
+                .unwrap_or_default(),
+            health: options.health,
+            topology: options.topology,
+            service_time: options
+                ._service_time_model
+                .unwrap_or_else(|| Box::new(LinearServiceTimeModel::default())),
+            outstanding: HashMap::new(),
+            in_flight: HashMap::new(),
+            accepted: accepted.clone(),
+            endpoint_reads: endpoint_reads.clone(),
+            endpoints_pending: false,
+            distribution: SelectionDistribution::new(options.distribution_window, distribution_tx),
+            stats: WorkerStatsPublisher::new(stats_tx),
+            metrics_updated_at: Instant::now(),
+            decay: PredictionDecay::new(
+                config
+                    .predictive_decay_half_life_ms
+                    .map(Duration::from_millis),
+            ),
+            warmup: WarmupTracker::new(
+                Duration::from_millis(config.warmup_duration_ms),
+                &endpoints,
+            ),
+            waiting_samples: WaitingSamples::new(config.waiting_percentile_window),
+            fairness: FairnessTracker::new(Duration::from_millis(config.fairness_window_ms)),
+            quotas: QuotaTracker::new(Duration::from_millis(config.quota_window_ms)),
+            oscillation: OscillationTracker::new(config.oscillation_window, oscillation_tx),
+            decisions: decisions.clone(),
+            hit_rates: HitRateTracker::new(options.hit_rate_window, hit_rate_tx),
+            logits,
+            pressure_tx,
+            on_selection: options._on_selection,
+            overlap_cache: OverlapCache::new(
+                config.overlap_cache_capacity,
+                Duration::from_millis(config.overlap_cache_ttl_ms),
+            ),
+            sessions: SessionAffinity::new(Duration::from_millis(config.session_ttl_ms)),
+            idempotency: IdempotencyCache::new(Duration::from_millis(config.idempotency_window_ms)),
+            tuned_at: Instant::now(),
+            coalescer: PrefixCoalescer::new(Duration::from_millis(
+                config.prefix_coalesce_window_ms,
+            )),
+            config,
+        };
+        state.update_endpoints(endpoints);
+        if let Some(restored) = restored {
+            state.restore(restored, Instant::now());
+        }
+        state.stats.publish(&state.endpoints);
+        state.publish_pressure(&request_rx, &low_priority_rx);
+
+        let completions_rx = options.completions;
+
+        // Background task to handle scheduling requests
+        let task_dead = dead.clone();
+        tokio::spawn(async move {
+            let dead = DeadOnDrop(task_dead);
+            let mut request: SchedulingRequest;
+            let mut request_rx = request_rx;
+            let mut low_priority_rx = low_priority_rx;
+            // High priority requests served since the last low priority one
+            let mut high_streak = 0;
+            let mut completion_rx = completion_rx;
+            let mut completions_rx = completions_rx;
+            let mut completion_batch = Vec::with_capacity(COMPLETION_BATCH_SIZE);
+            let mut control_rx = control_rx;
+            let mut publish_interval = tokio::time::interval(DISTRIBUTION_PUBLISH_INTERVAL);
+            // Answered once the task has stopped, if it was asked to
+            let mut stopped_tx = None;
+            tracing::trace!("scheduler background task started");
+
+            'outer: loop {
+                // Let a waiting low priority request through after a burst of high priority ones
+                let starved = if !state.paused && high_streak >= state.config.high_priority_burst {
+                    low_priority_rx.try_recv().ok()
+                } else {
+                    None
+                };
+                request = if let Some(low_request) = starved {
+                    tracing::trace!("serving low priority request after {high_streak} high");
+                    high_streak = 0;
+                    low_request
+                } else if let Some(held_request) = state.next_held() {
+                    held_request
+                } else {
+                    let next_retry = state.next_retry();
+                    tokio::select! {
+                        biased;
+
+                        Some(control) = control_rx.recv() => {
+                            if let ControlMessage::Shutdown(done_tx) = control {
+                                tracing::trace!("scheduler shutdown requested");
+                                state
+                                    .drain(
+                                        &mut request_rx,
+                                        &mut low_priority_rx,
+                                        &mut endpoints_rx,
+                                        metrics.as_ref(),
+                                    )
+                                    .await;
+                                stopped_tx = Some(done_tx);
+                                break 'outer;
+                            }
+                            state.handle_control(control);
+                            continue 'outer;
+                        }
+
+                        Ok(()) = config_rx.changed() => {
+                            state.update_config(config_rx.borrow_and_update().clone());
+                            continue 'outer;
+                        }
+
+                        new_request = request_rx.recv() => {
+                            match new_request {
+                                Some(new_request) if state.paused => {
+                                    tracing::trace!("holding request while paused");
+                                    state.hold(new_request);
+                                    continue 'outer;
+                                },
+                                Some(new_request) => {
+                                    tracing::trace!("received request to be scheduled");
+                                    high_streak += 1;
+                                    new_request
+                                },
+                                None => {
+                                    tracing::trace!("scheduler shutdown");
+                                    break 'outer;
+                                }
+                            }
+                        }
+
+                        Some(new_request) = low_priority_rx.recv() => {
+                            if state.paused {
+                                tracing::trace!("holding low priority request while paused");
+                                state.hold(new_request);
+                                continue 'outer;
+                            }
+                            tracing::trace!("received low priority request to be scheduled");
+                            high_streak = 0;
+                            new_request
+                        }
+
+                        changed = endpoints_rx.changed() => {
+                            // Without metrics nothing could be routed well, and the closed
+                            // watch would be ready on every turn of the loop
+                            if changed.is_err() {
+                                tracing::error!("endpoints stream closed; stopping the scheduler");
+                                state.fail_all(&mut request_rx, &mut low_priority_rx);
+                                break 'outer;
+                            }
+                            if state.refresh_endpoints(&mut endpoints_rx) {
+                                state.retry_busy(&mut endpoints_rx, metrics.as_ref()).await;
+                                state.publish_pressure(&request_rx, &low_priority_rx);
+                            }
+                            continue 'outer;
+                        }
+
+                        _ = tokio::time::sleep_until(state.endpoints_due()), if state.endpoints_pending => {
+                            if state.refresh_endpoints(&mut endpoints_rx) {
+                                state.retry_busy(&mut endpoints_rx, metrics.as_ref()).await;
+                                state.publish_pressure(&request_rx, &low_priority_rx);
+                            }
+                            continue 'outer;
+                        }
+
+                        _ = tokio::time::sleep_until(next_retry.unwrap_or_else(Instant::now)), if next_retry.is_some() => {
+                            state.retry_backed_off(&mut endpoints_rx, metrics.as_ref()).await;
+                            state.publish_pressure(&request_rx, &low_priority_rx);
+                            continue 'outer;
+                        }
+
+                        Some(completion) = completion_rx.recv() => {
+                            state.record_completion(completion);
+                            continue 'outer;
+                        }
+
+                        received = recv_completions(&mut completions_rx, &mut completion_batch) => {
+                            if received == 0 {
+                                tracing::trace!("completion stream closed");
+                                completions_rx = None;
+                            }
+                            state.record_completions(completion_batch.drain(..));
+                            continue 'outer;
+                        }
+
+                        _ = publish_interval.tick() => {
+                            if state.decay.apply(&mut state.endpoints, &state.stats.polled, Instant::now()) {
+                                state.stats.dirty = true;
+                            }
+                            state.distribution.publish(Instant::now());
+                            state.stats.publish(&state.endpoints);
+                            state.hit_rates.publish(Instant::now());
+                            state.coalescer.expire(Instant::now());
+                            state.sessions.expire(Instant::now());
+                            state.idempotency.expire(Instant::now());
+                            state.fairness.expire(Instant::now());
+                            state.quotas.expire(Instant::now());
+                            state.expire_outstanding(Instant::now());
+                            state.tune_weights(Instant::now());
+                            state.publish_pressure(&request_rx, &low_priority_rx);
+                            continue 'outer;
+                        }
+                    }
+                };
+                // Pick up metrics that arrived alongside the request, giving requests already
+                // waiting for capacity the first chance at any that freed up
+                if (endpoints_rx.has_changed().unwrap_or(false) || state.endpoints_pending)
+                    && state.refresh_endpoints(&mut endpoints_rx)
+                {
+                    state.retry_busy(&mut endpoints_rx, metrics.as_ref()).await;
+                }
+                if config_rx.has_changed().unwrap_or(false) {
+                    state.update_config(config_rx.borrow_and_update().clone());
+                }
+                state.overlap_cache.resolve(&mut request, Instant::now());
+                let pending = PendingRequest::new(request);
+                if let Some(pending) = state
+                    .try_schedule(pending, &mut endpoints_rx, metrics.as_ref())
+                    .await
+                {
+                    state.wait_for_capacity(pending);
+                    state.publish_pressure(&request_rx, &low_priority_rx);
+                }
+            }
+
+            tracing::trace!("background endpoint subscriber shutting down");
+            drop(dead);
+            if let Some(stopped_tx) = stopped_tx {
+                let _ = stopped_tx.send(());
+            }
+        });
+
+        Ok(KvScheduler {
+            request_tx,
+            low_priority_tx,
+            completion_tx,
+            control_tx,
+            distribution_rx,
+            stats_rx,
+            hit_rate_rx,
+            logit_histogram_rx,
+            pressure_rx,
+            oscillation_rx,
+            event_tx: scheduler_event_tx,
+            config_tx,
+            flush_tx,
+            accepted,
+            dead,
+            endpoint_reads,
+            rate_limiter: Mutex::new(TenantRateLimiter::default()),
+            outcomes: Arc::new(Mutex::new(OutcomeWindow::new(
+                options.rejection_rate_retention,
+            ))),
+            decisions,
+        })
+    }
+}
+
+/// Marks the scheduler dead when the scheduling task ends, however it ends.
+///
+/// The task returns once every handle on the scheduler is gone, once the endpoints stream
+/// closes, or on [`KvScheduler::shutdown`], failing what it still holds. If it panics instead
+/// its queues close with it, so requests waiting on it fail with
+/// [`KvSchedulerError::SubscriberShutdown`]. Either way later requests fail with
+/// [`KvSchedulerError::SchedulerDead`] rather than waiting forever; it is not restarted, since
+/// its predictive state went with it.
+struct DeadOnDrop(Arc<AtomicBool>);
+
+impl Drop for DeadOnDrop {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            tracing::error!("scheduler background task panicked; scheduling will fail");
+        }
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The workers a health report marks unhealthy.
+fn unhealthy_workers(health: &HashMap<WorkerId, bool>) -> HashSet<WorkerId> {
+    health
+        .iter()
+        .filter(|(_, healthy)| !**healthy)
+        .map(|(worker_id, _)| *worker_id)
+        .collect()
+}
+
+/// Publish each of a scheduler's events to `event_sink` until the scheduler shuts down,
+/// draining the queue whenever a flush is requested.
+async fn publish_events(
+    event_sink: Arc<dyn EventSink>,
+    mut event_rx: tokio::sync::mpsc::Receiver<SchedulerEvent>,
+    mut flush_rx: tokio::sync::mpsc::UnboundedReceiver<FlushRequest>,
+) {
+    loop {
+        tokio::select! {
+            biased;
+
+            Some(done_tx) = flush_rx.recv() => {
+                while let Ok(event) = event_rx.try_recv() {
+                    publish_event(event_sink.as_ref(), &event).await;
+                }
+                let _ = done_tx.send(());
+            }
+
+            event = event_rx.recv() => match event {
+                Some(event) => publish_event(event_sink.as_ref(), &event).await,
+                None => break,
             },
         }
     }
+}
 
-    // Helper to create ProcessedEndpoints
-    struct WorkerInfo {
-        id: i64,
-        usage: f32,
-        waiting: u64,
+pub(crate) async fn publish_event(event_sink: &dyn EventSink, event: &SchedulerEvent) {
+    if let Err(e) = event_sink.publish(event).await {
+        tracing::warn!("Failed to publish scheduler event: {:?}", e);
     }
-    fn create_workers(workers: Vec<WorkerInfo>) -> ProcessedEndpoints {
-        let mut endpoints = HashMap::new();
-        for worker in workers {
-            endpoints.insert(
-                worker.id,
-                create_endpoint(worker.id, worker.usage, worker.waiting),
+}
+
+/// Operator controls delivered to the background task.
+enum ControlMessage {
+    SetDraining {
+        worker_id: WorkerId,
+        draining: bool,
+    },
+    SetBlacklist(HashSet<WorkerId>),
+    SetAllowlist(Option<HashSet<WorkerId>>),
+    Pause,
+    Resume,
+    /// Reply with the state to carry over to a restarted scheduler
+    ExportState(tokio::sync::oneshot::Sender<SchedulerState>),
+    /// Report what scheduling `request` would do, with `queued` requests ahead of it
+    Probe {
+        request: Box<SchedulingRequest>,
+        queued: usize,
+        resp_tx: tokio::sync::oneshot::Sender<Result<ProbeResult, KvSchedulerError>>,
+    },
+    /// Select a worker for `request` without applying the selection
+    DryRun {
+        request: Box<SchedulingRequest>,
+        resp_tx: tokio::sync::oneshot::Sender<Result<WorkerSelectionResult, KvSchedulerError>>,
+    },
+    /// Schedule the requests already queued, fail the rest, and stop, answering once stopped
+    Shutdown(tokio::sync::oneshot::Sender<()>),
+}
+
+/// A request's selected workers, as committed by the background task.
+struct Selected {
+    worker_id: WorkerId,
+    /// Runner-up for speculative requests
+    secondary: Option<WorkerId>,
+    /// Logit of `worker_id`, reported on the request's span
+    #[cfg_attr(not(feature = "otel"), allow(dead_code))]
+    logit: f64,
+    /// The selector's reasoning, for requests that asked for it
+    explanation: Option<SelectionExplanation>,
+}
+
+/// State owned by the scheduler's background task.
+struct TaskState {
+    block_size: usize,
+    config: KvRouterConfig,
+    selector: Box<dyn WorkerSelector + Send + Sync>,
+    async_selector: Option<Arc<dyn AsyncWorkerSelector>>,
+    /// Latest worker metrics, adjusted by predictive updates until the next poll
+    endpoints: ProcessedEndpoints,
+    event_tx: EventSender,
+    throughput: ThroughputTracker,
+    /// Workers excluded from new selections
+    draining: HashSet<WorkerId>,
+    /// Workers never selected, regardless of their metrics
+    blacklist: HashSet<WorkerId>,
+    /// If set, the only workers that may be selected
+    allowlist: Option<HashSet<WorkerId>>,
+    /// External health signal, checked before every selection
+    health: Option<tokio::sync::watch::Receiver<HashMap<WorkerId, bool>>>,
+    /// Workers last reported unhealthy
+    unhealthy: HashSet<WorkerId>,
+    topology: Option<Topology>,
+    /// Whether selection is suspended, with requests held until it resumes
+    paused: bool,
+    /// Requests received while paused, in arrival order
+    held: VecDeque<SchedulingRequest>,
+    /// Requests that found every worker busy or none eligible, oldest first, retried whenever
+    /// the endpoints change or their backoff runs out, so that one of them can't hold up the
+    /// requests behind it
+    busy: VecDeque<PendingRequest>,
+    service_time: Box<dyn ServiceTimeModel>,
+    /// Estimated service of each worker's in-flight requests
+    outstanding: HashMap<WorkerId, ServiceQueue>,
+    /// Predicted number of in-flight requests per worker, capped by each poll at what the
+    /// worker reports
+    in_flight: HashMap<WorkerId, u64>,
+    /// Shared with [`KvScheduler`] for load shedding: predicted in-flight plus held and busy
+    /// requests
+    accepted: Arc<AtomicUsize>,
+    /// Shared with [`KvScheduler`]: times `endpoints` was replaced by a metrics poll
+    endpoint_reads: Arc<AtomicU64>,
+    /// Whether an endpoints update is waiting out `endpoints_debounce_ms`
+    endpoints_pending: bool,
+    distribution: SelectionDistribution,
+    stats: WorkerStatsPublisher,
+    /// When `endpoints` was last replaced by a metrics poll
+    metrics_updated_at: Instant,
+    decay: PredictionDecay,
+    warmup: WarmupTracker,
+    waiting_samples: WaitingSamples,
+    fairness: FairnessTracker,
+    quotas: QuotaTracker,
+    oscillation: OscillationTracker,
+    /// Recent decisions, while the decision log is enabled
+    decisions: Option<Arc<Mutex<DecisionLog>>>,
+    hit_rates: HitRateTracker,
+    logits: LogitHistogram,
+    pressure_tx: tokio::sync::watch::Sender<f64>,
+    coalescer: PrefixCoalescer,
+    sessions: SessionAffinity,
+    idempotency: IdempotencyCache,
+    /// When adaptive tuning last considered the weights
+    tuned_at: Instant,
+    on_selection: Option<SelectionHook>,
+    overlap_cache: OverlapCache,
+}
+
+impl TaskState {
+    /// Read the latest endpoints, unless `endpoints_debounce_ms` holds them off, in which case
+    /// they are read once it has passed. Returns whether they were read.
+    fn refresh_endpoints(
+        &mut self,
+        endpoints_rx: &mut tokio::sync::watch::Receiver<ProcessedEndpoints>,
+    ) -> bool {
+        if Instant::now() < self.endpoints_due() {
+            // Mark the update seen without copying it
+            endpoints_rx.borrow_and_update();
+            self.endpoints_pending = true;
+            return false;
+        }
+        self.update_endpoints(endpoints_rx.borrow_and_update().clone());
+        true
+    }
+
+    /// When the endpoints may next be read. Requests waiting for a busy worker don't wait out the
+    /// debounce.
+    fn endpoints_due(&self) -> Instant {
+        match self.config.endpoints_debounce_ms {
+            Some(debounce_ms) if self.busy.is_empty() => {
+                self.metrics_updated_at + Duration::from_millis(debounce_ms)
+            }
+            _ => self.metrics_updated_at,
+        }
+    }
+
+    fn update_endpoints(&mut self, endpoints: ProcessedEndpoints) {
+        self.endpoint_reads.fetch_add(1, Ordering::Relaxed);
+        self.endpoints_pending = false;
+        self.stats.polled = endpoints.clone();
+        self.stats.dirty = true;
+        self.metrics_updated_at = Instant::now();
+        self.warmup.observe(&endpoints, self.metrics_updated_at);
+        self.waiting_samples.observe(&endpoints);
+        self.decay.reset(self.metrics_updated_at);
+        let last_selected = std::mem::take(&mut self.endpoints.last_selected);
+        self.endpoints = endpoints;
+        self.endpoints.last_selected = last_selected;
+        self.expire_outstanding(self.metrics_updated_at);
+        self.reconcile_in_flight();
+        self.endpoints.throughput = self.throughput.rates.clone();
+        self.endpoints.waiting_percentile = self
+            .config
+            .waiting_percentile
+            .map(|percentile| self.waiting_samples.percentiles(percentile))
+            .unwrap_or_default();
+    }
+
+    /// The predictive state to carry over to a restarted scheduler.
+    fn export(&self, now: Instant) -> SchedulerState {
+        let predicted = self
+            .stats
+            .stats(&self.endpoints)
+            .into_iter()
+            .map(|(worker_id, stats)| {
+                let predicted = PredictedLoad {
+                    kv_active_blocks_delta: stats.kv_active_blocks_delta,
+                    num_requests_waiting_delta: stats.num_requests_waiting_delta,
+                };
+                (worker_id, predicted)
+            })
+            .collect();
+        SchedulerState {
+            predicted,
+            blacklist: self.blacklist.clone(),
+            sessions: self.sessions.export(now),
+            config: self.config.clone(),
+        }
+    }
+
+    /// Seed the predictive state exported by a previous scheduler on top of the current worker
+    /// metrics. Predicted load and sessions of workers no longer known are dropped; the
+    /// blacklist is kept whole, since it's operator policy rather than load.
+    fn restore(&mut self, restored: SchedulerState, now: Instant) {
+        for (worker_id, predicted) in restored.predicted {
+            let Some(worker) = self.endpoints.endpoints.get_mut(&worker_id) else {
+                tracing::debug!("dropping predicted load of unknown worker {worker_id}");
+                continue;
+            };
+            worker.data.kv_active_blocks = worker
+                .data
+                .kv_active_blocks
+                .saturating_add_signed(predicted.kv_active_blocks_delta);
+            worker.data.num_requests_waiting = worker
+                .data
+                .num_requests_waiting
+                .saturating_add_signed(predicted.num_requests_waiting_delta);
+        }
+        self.stats.dirty = true;
+        self.blacklist = restored.blacklist;
+        for (session_id, assignment) in restored.sessions {
+            if self.endpoints.endpoints.contains_key(&assignment.worker_id) {
+                self.sessions.restore(session_id, assignment, now);
+            }
+        }
+    }
+
+    fn update_config(&mut self, config: KvRouterConfig) {
+        tracing::info!("applying updated KV router config");
+        self.selector.set_kv_router_config(config.clone());
+        self.coalescer.window = Duration::from_millis(config.prefix_coalesce_window_ms);
+        self.sessions.ttl = Duration::from_millis(config.session_ttl_ms);
+        self.idempotency.window = Duration::from_millis(config.idempotency_window_ms);
+        self.warmup.duration = Duration::from_millis(config.warmup_duration_ms);
+        self.waiting_samples.window = config.waiting_percentile_window;
+        self.decay.half_life = config
+            .predictive_decay_half_life_ms
+            .map(Duration::from_millis);
+        self.fairness.window = Duration::from_millis(config.fairness_window_ms);
+        self.quotas.window = Duration::from_millis(config.quota_window_ms);
+        self.oscillation.window = config.oscillation_window;
+        self.overlap_cache.capacity = config.overlap_cache_capacity;
+        self.overlap_cache.ttl = Duration::from_millis(config.overlap_cache_ttl_ms);
+        self.config = config;
+    }
+
+    /// Publish the current [`pressure`], counting queued, held and busy-waiting requests against
+    /// the capacity of a scheduling queue.
+    fn publish_pressure(
+        &self,
+        request_rx: &tokio::sync::mpsc::Receiver<SchedulingRequest>,
+        low_priority_rx: &tokio::sync::mpsc::Receiver<SchedulingRequest>,
+    ) {
+        let waiting = request_rx.len() + low_priority_rx.len() + self.held.len() + self.busy.len();
+        let queue_fill = waiting as f64 / request_rx.max_capacity() as f64;
+        let pressure = pressure(&self.config, &self.endpoints, queue_fill);
+        self.pressure_tx.send_if_modified(|current| {
+            let changed = *current != pressure;
+            *current = pressure;
+            changed
+        });
+    }
+
+    /// Move the overlap weight toward the hit rate target, at most once per adaptive interval.
+    fn tune_weights(&mut self, now: Instant) {
+        if self.config.adaptive_target_hit_rate.is_none()
+            || now.duration_since(self.tuned_at)
+                < Duration::from_millis(self.config.adaptive_interval_ms)
+        {
+            return;
+        }
+        self.tuned_at = now;
+
+        let workers = &self.endpoints.endpoints;
+        let mean_usage = if workers.is_empty() {
+            0.0
+        } else {
+            workers
+                .values()
+                .map(|worker| worker.data.gpu_cache_usage_perc as f64)
+                .sum::<f64>()
+                / workers.len() as f64
+        };
+        let hit_rate = self.hit_rates.overall();
+        if let Some(weight) = tuned_overlap_weight(&self.config, hit_rate, mean_usage) {
+            tracing::info!(
+                "adaptive tuning moved overlap_score_weight from {} to {weight} (hit rate {hit_rate:?}, mean usage {mean_usage:.2})",
+                self.config.overlap_score_weight
             );
+            self.config.overlap_score_weight = weight;
+            self.selector.set_kv_router_config(self.config.clone());
         }
-        ProcessedEndpoints {
-            endpoints,
-            load_avg: 0.0,
-            load_std: 0.0,
+    }
+
+    /// Forget the service estimates of requests expected to have finished by `now`, whether or
+    /// not their completion was reported, and of workers no longer in the pool.
+    fn expire_outstanding(&mut self, now: Instant) {
+        let workers = &self.endpoints.endpoints;
+        self.outstanding.retain(|worker_id, outstanding| {
+            outstanding.expire(now);
+            workers.contains_key(worker_id) && !outstanding.is_empty()
+        });
+    }
+
+    /// Cap each worker's predicted in-flight count at the requests it reports waiting or
+    /// active, so completions that are never reported don't keep it up, and forget workers no
+    /// longer in the pool. A draining worker whose count reaches zero this way is drained.
+    fn reconcile_in_flight(&mut self) {
+        let workers = &self.endpoints.endpoints;
+        let mut drained = Vec::new();
+        self.in_flight.retain(|worker_id, in_flight| {
+            let Some(worker) = workers.get(worker_id) else {
+                return false;
+            };
+            let reported = worker.data.num_requests_waiting + worker.data.request_active_slots;
+            if *in_flight > 0 && reported == 0 && self.draining.contains(worker_id) {
+                drained.push(*worker_id);
+            }
+            *in_flight = (*in_flight).min(reported);
+            true
+        });
+        for worker_id in drained {
+            self.emit_drained(worker_id);
+        }
+        self.publish_accepted();
+    }
+
+    fn record_completion(&mut self, completion: Completion) {
+        let worker_id = completion.worker_id;
+        self.throughput.record(worker_id, Instant::now());
+        self.endpoints.throughput = self.throughput.rates.clone();
+        if let Some(worker) = self.endpoints.endpoints.get_mut(&worker_id) {
+            worker.data.kv_active_blocks = worker
+                .data
+                .kv_active_blocks
+                .saturating_sub(completion.freed_blocks);
+            self.stats.dirty = true;
+        }
+
+        if let Some(outstanding) = self.outstanding.get_mut(&worker_id) {
+            outstanding.complete(Instant::now());
+        }
+        if let Some(in_flight) = self.in_flight.get_mut(&worker_id) {
+            *in_flight = in_flight.saturating_sub(1);
+            if *in_flight == 0 && self.draining.contains(&worker_id) {
+                self.emit_drained(worker_id);
+            }
         }
+        self.publish_accepted();
+    }
+
+    /// Apply `(worker_id, freed_blocks)` completions from the completion stream, skipping
+    /// workers the scheduler doesn't know.
+    fn record_completions(&mut self, completions: impl Iterator<Item = (WorkerId, u64)>) {
+        for (worker_id, freed_blocks) in completions {
+            if !self.endpoints.endpoints.contains_key(&worker_id) {
+                tracing::trace!("ignoring completion for unknown worker {worker_id}");
+                continue;
+            }
+            self.record_completion(Completion {
+                worker_id,
+                freed_blocks,
+            });
+        }
+    }
+
+    fn handle_control(&mut self, control: ControlMessage) {
+        match control {
+            ControlMessage::SetDraining {
+                worker_id,
+                draining: true,
+            } => {
+                if self.draining.insert(worker_id) {
+                    tracing::info!("worker {worker_id} draining");
+                    if self.in_flight.get(&worker_id).copied().unwrap_or(0) == 0 {
+                        self.emit_drained(worker_id);
+                    }
+                }
+            }
+            ControlMessage::SetDraining {
+                worker_id,
+                draining: false,
+            } => {
+                if self.draining.remove(&worker_id) {
+                    tracing::info!("worker {worker_id} no longer draining");
+                }
+            }
+            ControlMessage::SetBlacklist(worker_ids) => {
+                tracing::info!("blacklisted workers: {worker_ids:?}");
+                self.blacklist = worker_ids;
+            }
+            ControlMessage::SetAllowlist(worker_ids) => {
+                tracing::info!("allowlisted workers: {worker_ids:?}");
+                self.allowlist = worker_ids;
+            }
+            ControlMessage::Pause => {
+                if !self.paused {
+                    tracing::info!("worker selection paused");
+                    self.paused = true;
+                }
+            }
+            ControlMessage::Resume => {
+                if self.paused {
+                    tracing::info!(
+                        "worker selection resumed; {} requests held",
+                        self.held.len()
+                    );
+                    self.paused = false;
+                }
+            }
+            ControlMessage::ExportState(resp_tx) => {
+                if resp_tx.send(self.export(Instant::now())).is_err() {
+                    tracing::trace!("state export caller went away");
+                }
+            }
+            ControlMessage::Probe {
+                request,
+                queued,
+                resp_tx,
+            } => {
+                let result = self.probe(&request, queued + self.held.len());
+                if resp_tx.send(result).is_err() {
+                    tracing::trace!("probe caller went away");
+                }
+            }
+            ControlMessage::DryRun { request, resp_tx } => {
+                let result = self
+                    .prepare(&request, Instant::now())
+                    .and_then(|()| self.scored_selection(&request));
+                if resp_tx.send(result).is_err() {
+                    tracing::trace!("dry run caller went away");
+                }
+            }
+            ControlMessage::Shutdown(_) => unreachable!("shutdown is handled by the task loop"),
+        }
+    }
+
+    fn hold(&mut self, request: SchedulingRequest) {
+        self.held.push_back(request);
+        self.publish_accepted();
+    }
+
+    /// The oldest request held while paused, once selection has resumed.
+    fn next_held(&mut self) -> Option<SchedulingRequest> {
+        if self.paused {
+            return None;
+        }
+        let request = self.held.pop_front();
+        self.publish_accepted();
+        request
+    }
+
+    /// Fail every request waiting for capacity, held, or queued with
+    /// [`KvSchedulerError::SchedulerDead`], closing the queues so later requests fail too.
+    fn fail_all(
+        &mut self,
+        request_rx: &mut tokio::sync::mpsc::Receiver<SchedulingRequest>,
+        low_priority_rx: &mut tokio::sync::mpsc::Receiver<SchedulingRequest>,
+    ) {
+        request_rx.close();
+        low_priority_rx.close();
+        let busy = std::mem::take(&mut self.busy)
+            .into_iter()
+            .map(|pending| pending.request);
+        let held = std::mem::take(&mut self.held);
+        let mut queued = Vec::new();
+        while let Ok(request) = request_rx.try_recv() {
+            queued.push(request);
+        }
+        while let Ok(request) = low_priority_rx.try_recv() {
+            queued.push(request);
+        }
+        for request in busy.chain(held).chain(queued) {
+            request.reject(KvSchedulerError::SchedulerDead);
+        }
+        self.publish_accepted();
+    }
+
+    /// Stop taking requests and schedule the ones already queued, high priority first, so their
+    /// events are emitted before the task stops. Those left to wait, for capacity or for
+    /// selection to resume, fail as [`TaskState::fail_all`] fails them.
+    async fn drain(
+        &mut self,
+        request_rx: &mut tokio::sync::mpsc::Receiver<SchedulingRequest>,
+        low_priority_rx: &mut tokio::sync::mpsc::Receiver<SchedulingRequest>,
+        endpoints_rx: &mut tokio::sync::watch::Receiver<ProcessedEndpoints>,
+        metrics: Option<&SchedulerMetrics>,
+    ) {
+        request_rx.close();
+        low_priority_rx.close();
+        while let Ok(mut request) = request_rx
+            .try_recv()
+            .or_else(|_| low_priority_rx.try_recv())
+        {
+            if self.paused {
+                self.hold(request);
+                continue;
+            }
+            self.overlap_cache.resolve(&mut request, Instant::now());
+            let pending = PendingRequest::new(request);
+            if let Some(pending) = self.try_schedule(pending, endpoints_rx, metrics).await {
+                self.wait_for_capacity(pending);
+            }
+        }
+        self.fail_all(request_rx, low_priority_rx);
+    }
+
+    fn publish_accepted(&self) {
+        let in_flight: u64 = self.in_flight.values().sum();
+        self.accepted.store(
+            in_flight as usize + self.held.len() + self.busy.len(),
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Set `pending` aside until the endpoints change, when [`TaskState::retry_busy`] tries it
+    /// again, or its no-endpoints backoff runs out, when [`TaskState::retry_backed_off`] does.
+    fn wait_for_capacity(&mut self, pending: PendingRequest) {
+        self.busy.push_back(pending);
+        self.publish_accepted();
+    }
+
+    /// Try each request waiting for capacity again, oldest first, keeping those that still find
+    /// every worker busy. Requests stay put while selection is paused.
+    async fn retry_busy(
+        &mut self,
+        endpoints_rx: &mut tokio::sync::watch::Receiver<ProcessedEndpoints>,
+        metrics: Option<&SchedulerMetrics>,
+    ) {
+        if self.paused || self.busy.is_empty() {
+            return;
+        }
+        let busy = std::mem::take(&mut self.busy);
+        let mut still_busy = VecDeque::with_capacity(busy.len());
+        for pending in busy {
+            if let Some(pending) = self.try_schedule(pending, endpoints_rx, metrics).await {
+                still_busy.push_back(pending);
+            }
+        }
+        self.busy = still_busy;
+        self.publish_accepted();
+    }
+
+    /// When the next request backing off after finding no eligible workers is due for a retry.
+    /// None while selection is paused, since [`TaskState::retry_backed_off`] would leave it be.
+    fn next_retry(&self) -> Option<Instant> {
+        if self.paused {
+            return None;
+        }
+        self.busy
+            .iter()
+            .filter_map(|pending| pending.retry_at)
+            .min()
+    }
+
+    /// Try the requests whose no-endpoints backoff has run out again, leaving the rest in place.
+    async fn retry_backed_off(
+        &mut self,
+        endpoints_rx: &mut tokio::sync::watch::Receiver<ProcessedEndpoints>,
+        metrics: Option<&SchedulerMetrics>,
+    ) {
+        if self.paused {
+            return;
+        }
+        let now = Instant::now();
+        let busy = std::mem::take(&mut self.busy);
+        let mut still_busy = VecDeque::with_capacity(busy.len());
+        for pending in busy {
+            if pending.retry_at.is_none_or(|retry_at| retry_at > now) {
+                still_busy.push_back(pending);
+                continue;
+            }
+            if let Some(pending) = self.try_schedule(pending, endpoints_rx, metrics).await {
+                still_busy.push_back(pending);
+            }
+        }
+        self.busy = still_busy;
+        self.publish_accepted();
+    }
+
+    /// Select a worker for `pending` and answer its requester, unless every worker is busy or
+    /// there are none to retry on yet, in which case it's handed back to wait, its capacity wait
+    /// clock restarted so the next attempt counts the time until then.
+    async fn try_schedule(
+        &mut self,
+        mut pending: PendingRequest,
+        endpoints_rx: &mut tokio::sync::watch::Receiver<ProcessedEndpoints>,
+        metrics: Option<&SchedulerMetrics>,
+    ) -> Option<PendingRequest> {
+        if let Some(busy_since) = pending.busy_since.take() {
+            pending.timing.capacity_wait += busy_since.elapsed();
+        }
+        pending.retry_at = None;
+        let mut retries = 0;
+        loop {
+            // Nobody would see the selection, and its predictive updates would be wrong
+            if pending.request.abandoned() {
+                pending.request.discard();
+                return None;
+            }
+            let selected = if pending.request.speculative {
+                self.select_speculative(&pending.request)
+            } else {
+                self.select_async(&pending.request).await
+            };
+            match selected {
+                Ok(mut selected) => {
+                    if let (Some(explain_tx), Some(explanation)) = (
+                        pending.request.explain_tx.take(),
+                        selected.explanation.take(),
+                    ) {
+                        if explain_tx.send(explanation).is_err() {
+                            tracing::trace!("explanation requester went away");
+                        }
+                    }
+                    if let Some(metrics) = metrics {
+                        metrics.observe(&pending.timing);
+                    }
+                    #[cfg(feature = "otel")]
+                    if let Some(span) = pending.span.take() {
+                        span.selected(
+                            selected.worker_id,
+                            selected.secondary,
+                            selected.logit,
+                            &pending.timing,
+                        );
+                    }
+                    pending.request.dispatch(Scheduled {
+                        worker_id: selected.worker_id,
+                        secondary: selected.secondary,
+                        timing: pending.timing,
+                    });
+                    return None;
+                }
+                Err(ref e @ (KvSchedulerError::AllWorkersBusy | KvSchedulerError::NoEndpoints))
+                    if self.waits_on(e) =>
+                {
+                    tracing::trace!("{e}; waiting for more capacity");
+                    pending.busy_since = Some(Instant::now());
+                    return Some(pending);
+                }
+                // Backing off in place would hold up every request behind this one
+                Err(KvSchedulerError::NoEndpoints)
+                    if pending.no_endpoints_retries < self.config.max_no_endpoints_retries =>
+                {
+                    let backoff = Duration::from_millis(self.config.no_endpoints_retry_backoff_ms)
+                        .saturating_mul(1 << pending.no_endpoints_retries.min(16));
+                    pending.no_endpoints_retries += 1;
+                    tracing::debug!(
+                        "no endpoints; retry {} of {} in {backoff:?}",
+                        pending.no_endpoints_retries,
+                        self.config.max_no_endpoints_retries
+                    );
+                    pending.retry_at = Some(Instant::now() + backoff);
+                    pending.busy_since = Some(Instant::now());
+                    return Some(pending);
+                }
+                Err(KvSchedulerError::WorkerNotFound { worker_id })
+                    if retries + 1 < MAX_SELECTION_ATTEMPTS =>
+                {
+                    tracing::warn!("selector chose unknown worker {worker_id}; retrying");
+                    retries += 1;
+                    if endpoints_rx.has_changed().unwrap_or(false) {
+                        self.update_endpoints(endpoints_rx.borrow_and_update().clone());
+                    }
+                }
+                Err(e) => {
+                    tracing::debug!("error scheduling request: {:?}", e);
+                    emit_rejected(&self.event_tx, &e, pending.request.isl_tokens);
+                    #[cfg(feature = "otel")]
+                    if let Some(span) = pending.span.take() {
+                        span.rejected(&e);
+                    }
+                    pending.request.reject(e);
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn emit_drained(&self, worker_id: WorkerId) {
+        tracing::info!("worker {worker_id} drained");
+        if let Err(e) = self
+            .event_tx
+            .send(SchedulerEvent::WorkerDrained(WorkerDrainedEvent {
+                worker_id,
+            }))
+        {
+            tracing::warn!("Failed to send worker drained event: {:?}", e);
+        }
+    }
+
+    /// Select a worker for `request` among the eligible workers and apply the predictive updates.
+    fn select(&mut self, request: &SchedulingRequest) -> Result<Selected, KvSchedulerError> {
+        let now = Instant::now();
+        if let Some(selected) = self.deduplicated(request, now) {
+            return Ok(selected);
+        }
+        self.prepare(request, now)?;
+        let (selection, explanation) = match self
+            .session_selection(request, now)
+            .or_else(|| self.coalesced_selection(request, now))
+        {
+            Some(selection) => (selection, None),
+            None => {
+                let (selection, explanation) =
+                    if request.explain_tx.is_some() || self.decisions.is_some() {
+                        let (selection, explanation) = self.explained_selection(request)?;
+                        (selection, Some(explanation))
+                    } else {
+                        (self.scored_selection(request)?, None)
+                    };
+                self.logits.record(selection.logit);
+                if let Some(prefix_hash) = request.prefix_hash {
+                    self.coalescer.record(prefix_hash, selection.worker_id, now);
+                }
+                (selection, explanation)
+            }
+        };
+        let logit = selection.logit;
+        let worker_id = self.finish(request, selection, now)?;
+        let candidates = explanation.iter().flat_map(|explanation| {
+            explanation
+                .candidates
+                .iter()
+                .map(|candidate| (candidate.worker_id, candidate.logit))
+        });
+        self.audit(request, worker_id, logit, candidates);
+        Ok(Selected {
+            worker_id,
+            secondary: None,
+            logit,
+            explanation,
+        })
+    }
+
+    /// Like [`TaskState::select`], but awaiting the async selector, if there is one, for
+    /// requests that don't ask for an explanation.
+    async fn select_async(
+        &mut self,
+        request: &SchedulingRequest,
+    ) -> Result<Selected, KvSchedulerError> {
+        let Some(selector) = self
+            .async_selector
+            .clone()
+            .filter(|_| request.explain_tx.is_none())
+        else {
+            return self.select(request);
+        };
+        let now = Instant::now();
+        if let Some(selected) = self.deduplicated(request, now) {
+            return Ok(selected);
+        }
+        self.prepare(request, now)?;
+        let selection = match self
+            .session_selection(request, now)
+            .or_else(|| self.coalesced_selection(request, now))
+        {
+            Some(selection) => selection,
+            None => {
+                let candidates = self.candidates(request)?;
+                let workers = candidates.as_ref().unwrap_or(&self.endpoints);
+                let selecting = std::panic::AssertUnwindSafe(selector.select_worker(
+                    workers,
+                    request,
+                    self.block_size_for(request),
+                ))
+                .catch_unwind()
+                .map(|selected| selected.unwrap_or_else(|payload| Err(selector_panicked(payload))));
+                let selection = match self.config.selection_timeout_ms {
+                    Some(timeout_ms) => {
+                        let timeout = Duration::from_millis(timeout_ms);
+                        tokio::time::timeout(timeout, selecting)
+                            .await
+                            .map_err(|_| {
+                                tracing::warn!("worker selection timed out after {timeout:?}");
+                                KvSchedulerError::SelectionTimeout(timeout)
+                            })??
+                    }
+                    None => selecting.await?,
+                };
+                self.logits.record(selection.logit);
+                if let Some(prefix_hash) = request.prefix_hash {
+                    self.coalescer.record(prefix_hash, selection.worker_id, now);
+                }
+                selection
+            }
+        };
+        let logit = selection.logit;
+        let worker_id = self.finish(request, selection, now)?;
+        self.audit(request, worker_id, logit, std::iter::empty());
+        Ok(Selected {
+            worker_id,
+            secondary: None,
+            logit,
+            explanation: None,
+        })
+    }
+
+    /// Record the decision to route `request` to `worker_id` in the decision log, if enabled.
+    fn audit(
+        &self,
+        request: &SchedulingRequest,
+        worker_id: WorkerId,
+        logit: f64,
+        candidates: impl Iterator<Item = (WorkerId, f64)>,
+    ) {
+        let Some(decisions) = &self.decisions else {
+            return;
+        };
+        let record = DecisionRecord {
+            timestamp: std::time::SystemTime::now(),
+            request_id: request.request_id.clone(),
+            isl_tokens: request.isl_tokens,
+            chosen_worker: worker_id,
+            logit,
+            candidates: candidates.collect(),
+        };
+        decisions
+            .lock()
+            .expect("decision log lock poisoned")
+            .record(record);
+    }
+
+    /// Commit `selection` and remember it for the request's session and idempotency key.
+    fn finish(
+        &mut self,
+        request: &SchedulingRequest,
+        selection: WorkerSelectionResult,
+        now: Instant,
+    ) -> Result<WorkerId, KvSchedulerError> {
+        let worker_id = self.commit(request, selection, now)?;
+        self.oscillation.record(
+            request.stream_id.as_deref(),
+            worker_id,
+            self.config.oscillation_warn_threshold,
+        );
+        if let Some(session_id) = &request.session_id {
+            self.sessions.record(session_id, worker_id, now);
+        }
+        if let Some(key) = &request.idempotency_key {
+            self.idempotency.record(key, worker_id, None, now);
+        }
+        Ok(worker_id)
+    }
+
+    /// The outcome of an earlier attempt of `request`, if its idempotency key was scheduled
+    /// within the window.
+    fn deduplicated(&self, request: &SchedulingRequest, now: Instant) -> Option<Selected> {
+        let key = request.idempotency_key.as_deref()?;
+        let (worker_id, secondary) = self.idempotency.lookup(key, now)?;
+        tracing::debug!("request {key} already scheduled on worker {worker_id}");
+        Some(Selected {
+            worker_id,
+            secondary,
+            logit: 0.0,
+            explanation: None,
+        })
+    }
+
+    /// The selector's choice for `request` among the eligible workers, with nothing applied.
+    fn scored_selection(
+        &self,
+        request: &SchedulingRequest,
+    ) -> Result<WorkerSelectionResult, KvSchedulerError> {
+        let candidates = self.candidates(request)?;
+        let workers = candidates.as_ref().unwrap_or(&self.endpoints);
+        isolate_panics(|| {
+            self.selector
+                .select_worker(workers, request, self.block_size_for(request))
+        })
+    }
+
+    /// Like [`TaskState::scored_selection`], along with the selector's explanation.
+    fn explained_selection(
+        &self,
+        request: &SchedulingRequest,
+    ) -> Result<(WorkerSelectionResult, SelectionExplanation), KvSchedulerError> {
+        let candidates = self.candidates(request)?;
+        let workers = candidates.as_ref().unwrap_or(&self.endpoints);
+        isolate_panics(|| {
+            self.selector
+                .explain_selection(workers, request, self.block_size_for(request))
+        })
+    }
+
+    /// Select the best worker for `request`, plus the runner-up if it is within
+    /// `speculative_margin` of it, and apply the predictive updates to both.
+    fn select_speculative(
+        &mut self,
+        request: &SchedulingRequest,
+    ) -> Result<Selected, KvSchedulerError> {
+        let now = Instant::now();
+        if let Some(selected) = self.deduplicated(request, now) {
+            return Ok(selected);
+        }
+        self.prepare(request, now)?;
+        let candidates = self.candidates(request)?;
+        let workers = candidates.as_ref().unwrap_or(&self.endpoints);
+        let ranking = isolate_panics(|| {
+            self.selector
+                .rank_workers(workers, request, self.block_size_for(request))
+        })?;
+        let (primary, best_logit) = *ranking.first().ok_or(KvSchedulerError::NoEndpoints)?;
+        self.logits.record(best_logit);
+        let runner_up = ranking.get(1).copied();
+        let margin = runner_up.map_or(0.0, |(_, logit)| best_logit - logit);
+        let secondary = runner_up.filter(|_| margin < self.config.speculative_margin);
+
+        let selection = self.selection_for(request, primary, best_logit, margin);
+        let primary = self.commit(request, selection, now)?;
+        self.oscillation.record(
+            request.stream_id.as_deref(),
+            primary,
+            self.config.oscillation_warn_threshold,
+        );
+        if let Some(session_id) = &request.session_id {
+            self.sessions.record(session_id, primary, now);
+        }
+        let mut candidates = ranking.clone();
+        candidates.sort_by_key(|(worker_id, _)| *worker_id);
+        self.audit(request, primary, best_logit, candidates.into_iter());
+        let secondary = secondary.and_then(|(worker_id, logit)| {
+            tracing::debug!("Dispatching speculatively to worker {worker_id} as well");
+            let selection = self.selection_for(request, worker_id, logit, 0.0);
+            self.commit(request, selection, now)
+                .inspect_err(|e| tracing::warn!("skipping speculative dispatch: {e}"))
+                .ok()
+        });
+        if let Some(key) = &request.idempotency_key {
+            self.idempotency.record(key, primary, secondary, now);
+        }
+        Ok(Selected {
+            worker_id: primary,
+            secondary,
+            logit: best_logit,
+            explanation: None,
+        })
+    }
+
+    /// Check the worker metrics can be routed on, and refresh the time and request dependent
+    /// terms the selector scores them with.
+    fn prepare(
+        &mut self,
+        request: &SchedulingRequest,
+        now: Instant,
+    ) -> Result<(), KvSchedulerError> {
+        self.refresh_health();
+        if let Some(max_staleness) = self.config.max_metrics_staleness_ms {
+            let staleness = now.duration_since(self.metrics_updated_at);
+            if staleness > Duration::from_millis(max_staleness) {
+                tracing::warn!("not routing on worker metrics last updated {staleness:?} ago");
+                return Err(KvSchedulerError::StaleMetrics(staleness));
+            }
+        }
+        self.endpoints.warmup = self.warmup.remaining(now);
+        self.endpoints.fairness = match &request.tenant_id {
+            Some(tenant_id) if self.config.fairness_weight > 0.0 => {
+                self.fairness.penalties(tenant_id, now)
+            }
+            _ => HashMap::new(),
+        };
+        self.endpoints.quota_overage = match self.soft_quota(request) {
+            Some((tenant_id, quota)) => self.quotas.overage(tenant_id, quota, now),
+            None => 0.0,
+        };
+        self.endpoints.locality = match (&self.topology, &request.zone) {
+            (Some(topology), Some(zone)) if self.config.locality_weight > 0.0 => {
+                topology.distances_from(zone)
+            }
+            _ => HashMap::new(),
+        };
+        Ok(())
+    }
+
+    /// Pick up the latest health report, if it changed.
+    fn refresh_health(&mut self) {
+        let Some(health) = &mut self.health else {
+            return;
+        };
+        if !health.has_changed().unwrap_or(false) {
+            return;
+        }
+        let unhealthy = unhealthy_workers(&health.borrow_and_update());
+        if unhealthy != self.unhealthy {
+            tracing::info!("unhealthy workers: {unhealthy:?}");
+            self.unhealthy = unhealthy;
+        }
+    }
+
+    /// Apply the predictive updates for `selection` and record it.
+    fn commit(
+        &mut self,
+        request: &SchedulingRequest,
+        selection: WorkerSelectionResult,
+        now: Instant,
+    ) -> Result<WorkerId, KvSchedulerError> {
+        let (required_blocks, overlap_blocks) =
+            (selection.required_blocks, selection.overlap_blocks);
+        let service_time = self
+            .service_time
+            .estimate(request.isl_tokens, overlap_blocks);
+        if let Some(deadline) = request.deadline {
+            let estimated = self.estimated_queue_time(selection.worker_id) + service_time;
+            if estimated > deadline.saturating_sub(request.enqueued_at.elapsed()) {
+                return Err(KvSchedulerError::DeadlineUnmet { estimated });
+            }
+        }
+        let worker_id = process_worker_selection(
+            &mut self.endpoints,
+            selection,
+            &self.event_tx,
+            self.on_selection.as_ref(),
+        )?;
+        self.cap_predicted_waiting(worker_id);
+        self.hit_rates
+            .record(worker_id, required_blocks, overlap_blocks, now);
+        *self.in_flight.entry(worker_id).or_insert(0) += 1;
+        self.publish_accepted();
+        self.outstanding
+            .entry(worker_id)
+            .or_default()
+            .push(service_time, now);
+        self.distribution.record(worker_id, now);
+        if let Some(tenant_id) = &request.tenant_id {
+            if self.config.fairness_weight > 0.0 {
+                self.fairness.record(tenant_id, worker_id, now);
+            }
+        }
+        if let Some((tenant_id, _)) = self.soft_quota(request) {
+            self.quotas.record(tenant_id, now);
+        }
+        self.stats.dirty = true;
+        Ok(worker_id)
+    }
+
+    /// The tenant of `request` and its soft quota, while the quota penalty is enabled.
+    fn soft_quota<'a>(&self, request: &'a SchedulingRequest) -> Option<(&'a str, u64)> {
+        if self.config.quota_penalty_weight <= 0.0 {
+            return None;
+        }
+        let tenant_id = request.tenant_id.as_deref()?;
+        let quota = self.config.tenant_soft_quotas.get(tenant_id)?;
+        Some((tenant_id, *quota))
+    }
+
+    /// Clamp `worker_id`'s predicted waiting count to
+    /// [`KvRouterConfig::max_predictive_waiting_delta`] above its last polled value.
+    fn cap_predicted_waiting(&mut self, worker_id: WorkerId) {
+        let Some(max_delta) = self.config.max_predictive_waiting_delta else {
+            return;
+        };
+        let polled = self
+            .stats
+            .polled
+            .endpoints
+            .get(&worker_id)
+            .map_or(0, |polled| polled.data.num_requests_waiting);
+        if let Some(worker) = self.endpoints.endpoints.get_mut(&worker_id) {
+            worker.data.num_requests_waiting = worker
+                .data
+                .num_requests_waiting
+                .min(polled.saturating_add(max_delta));
+        }
+    }
+
+    /// Whether a request that failed with `error` should wait for the endpoints to change
+    /// rather than be rejected: always when every worker is busy, and when there are no workers
+    /// at all if [`KvRouterConfig::wait_for_first_worker`] is set.
+    fn waits_on(&self, error: &KvSchedulerError) -> bool {
+        match error {
+            KvSchedulerError::AllWorkersBusy => true,
+            KvSchedulerError::NoEndpoints => {
+                self.config.wait_for_first_worker && self.endpoints.endpoints.is_empty()
+            }
+            _ => false,
+        }
+    }
+
+    /// The selection `request` would get now, or its estimated wait if every worker is busy.
+    fn probe(
+        &mut self,
+        request: &SchedulingRequest,
+        queued: usize,
+    ) -> Result<ProbeResult, KvSchedulerError> {
+        self.prepare(request, Instant::now())?;
+        match self.scored_selection(request) {
+            Ok(selection) => Ok(ProbeResult::Available(selection)),
+            Err(KvSchedulerError::AllWorkersBusy) => {
+                Ok(ProbeResult::Wait(self.estimated_wait(request, queued)))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Rough time until `request` gets a worker while every worker is busy: until the first
+    /// worker is expected to finish its oldest request, plus the service time of the `queued`
+    /// requests ahead, spread over the workers. Requests ahead are assumed to be the size of
+    /// `request`, and workers with nothing outstanding to free up after one service time.
+    fn estimated_wait(&self, request: &SchedulingRequest, queued: usize) -> Duration {
+        let service_time = self.service_time.estimate(request.isl_tokens, 0.0);
+        let now = Instant::now();
+        let first_free = self
+            .endpoints
+            .endpoints
+            .keys()
+            .map(|worker_id| {
+                self.outstanding
+                    .get(worker_id)
+                    .and_then(|outstanding| outstanding.first_free(now))
+                    .unwrap_or(service_time)
+            })
+            .min()
+            .unwrap_or(service_time);
+        let workers = self.endpoints.endpoints.len().max(1);
+        first_free + service_time.mul_f64(queued as f64 / workers as f64)
+    }
+
+    /// Estimated time for `worker_id` to serve the requests already routed to it.
+    fn estimated_queue_time(&self, worker_id: WorkerId) -> Duration {
+        self.outstanding
+            .get(&worker_id)
+            .map_or(Duration::ZERO, |outstanding| {
+                outstanding.queue_time(Instant::now())
+            })
+    }
+
+    /// Block size `request` is counted in where a worker doesn't report its own.
+    fn block_size_for(&self, request: &SchedulingRequest) -> usize {
+        request.block_size.unwrap_or(self.block_size)
+    }
+
+    /// Selection of `worker_id` for `request`, made outside the selector.
+    fn selection_for(
+        &self,
+        request: &SchedulingRequest,
+        worker_id: WorkerId,
+        logit: f64,
+        margin: f64,
+    ) -> WorkerSelectionResult {
+        let default = self.block_size_for(request);
+        let block_size = self
+            .endpoints
+            .endpoints
+            .get(&worker_id)
+            .map_or(default, |ep| worker_block_size(ep, default));
+        WorkerSelectionResult {
+            worker_id,
+            required_blocks: required_blocks(
+                request.isl_tokens,
+                block_size,
+                self.config.min_blocks_per_request,
+            ),
+            decode_blocks: decode_blocks(request, block_size, self.config.min_blocks_per_request),
+            overlap_blocks: request
+                .overlap
+                .scores
+                .get(&worker_id)
+                .copied()
+                .filter(|_| request.role != WorkerRole::Decode)
+                .unwrap_or(0.0),
+            margin,
+            logit,
+        }
+    }
+
+    /// Route `request` to the worker its prefix was recently sent to, if that worker can still
+    /// take it.
+    fn coalesced_selection(
+        &self,
+        request: &SchedulingRequest,
+        now: Instant,
+    ) -> Option<WorkerSelectionResult> {
+        let worker_id = self.coalescer.lookup(request.prefix_hash?, now)?;
+        if !self.can_take(request, worker_id) {
+            return None;
+        }
+
+        tracing::debug!("Coalescing request onto worker {worker_id} by prefix");
+        Some(self.selection_for(request, worker_id, 0.0, 0.0))
+    }
+
+    /// Route `request` to the worker its session was last sent to, if that worker can still
+    /// take it.
+    fn session_selection(
+        &self,
+        request: &SchedulingRequest,
+        now: Instant,
+    ) -> Option<WorkerSelectionResult> {
+        let session_id = request.session_id.as_deref()?;
+        let worker_id = self.sessions.lookup(session_id, now)?;
+        if !self.can_take(request, worker_id) {
+            tracing::debug!("worker {worker_id} of session {session_id} unavailable; reselecting");
+            return None;
+        }
+        if self.overloaded(worker_id) {
+            tracing::debug!("worker {worker_id} of session {session_id} overloaded; rebalancing");
+            return None;
+        }
+
+        tracing::debug!("Routing session {session_id} to worker {worker_id}");
+        Some(self.selection_for(request, worker_id, 0.0, 0.0))
+    }
+
+    /// Whether `worker_id`'s predicted KV block load exceeds `session_rebalance_factor` times
+    /// the average over every known worker.
+    fn overloaded(&self, worker_id: WorkerId) -> bool {
+        let Some(factor) = self.config.session_rebalance_factor else {
+            return false;
+        };
+        let endpoints = &self.endpoints.endpoints;
+        let Some(endpoint) = endpoints.get(&worker_id) else {
+            return false;
+        };
+        let total: u64 = endpoints.values().map(|ep| ep.data.kv_active_blocks).sum();
+        let average = total as f64 / endpoints.len() as f64;
+        average > 0.0 && endpoint.data.kv_active_blocks as f64 > factor * average
+    }
+
+    /// Whether `worker_id` is known and may be sent `request` without the selector.
+    fn can_take(&self, request: &SchedulingRequest, worker_id: WorkerId) -> bool {
+        let Some(endpoint) = self.endpoints.endpoints.get(&worker_id) else {
+            return false;
+        };
+        !self.draining.contains(&worker_id)
+            && !request.exclude.contains(&worker_id)
+            && self.permitted(worker_id)
+            && endpoint.data.role.serves(request.role)
+            && endpoint.data.gpu_cache_usage_perc as f64 <= self.config.gpu_cache_usage_hard_cap
+    }
+
+    /// The workers eligible for `request`, or `None` if every known worker is.
+    ///
+    /// The request's own exclusions are dropped if no worker serving its role would be left.
+    fn candidates(
+        &self,
+        request: &SchedulingRequest,
+    ) -> Result<Option<ProcessedEndpoints>, KvSchedulerError> {
+        let candidates = self.listed_candidates()?;
+        if request.exclude.is_empty() {
+            return Ok(candidates);
+        }
+
+        let mut remaining = candidates.clone().unwrap_or_else(|| self.endpoints.clone());
+        remaining
+            .endpoints
+            .retain(|worker_id, _| !request.exclude.contains(worker_id));
+        if !remaining
+            .endpoints
+            .values()
+            .any(|ep| ep.data.role.serves(request.role))
+        {
+            tracing::debug!("every eligible worker is excluded; including excluded workers");
+            return Ok(candidates);
+        }
+        Ok(Some(remaining))
+    }
+
+    /// The workers eligible for any request, or `None` if every known worker is.
+    ///
+    /// Fails with [`KvSchedulerError::NoEndpoints`] if the blacklist, allowlist and health
+    /// reports exclude every known worker.
+    fn listed_candidates(&self) -> Result<Option<ProcessedEndpoints>, KvSchedulerError> {
+        if self.draining.is_empty()
+            && self.blacklist.is_empty()
+            && self.allowlist.is_none()
+            && self.unhealthy.is_empty()
+        {
+            return Ok(None);
+        }
+
+        let mut permitted = self.endpoints.clone();
+        permitted
+            .endpoints
+            .retain(|worker_id, _| self.permitted(*worker_id));
+        if permitted.endpoints.is_empty() && !self.endpoints.endpoints.is_empty() {
+            tracing::debug!("every worker is excluded by the lists or unhealthy");
+            return Err(KvSchedulerError::NoEndpoints);
+        }
+        if self.draining.is_empty() {
+            return Ok(Some(permitted));
+        }
+
+        let mut candidates = permitted.clone();
+        candidates
+            .endpoints
+            .retain(|worker_id, _| !self.draining.contains(worker_id));
+        if candidates.endpoints.is_empty() && self.config.allow_draining_fallback {
+            tracing::debug!("only draining workers available; routing to a draining worker");
+            return Ok(Some(permitted));
+        }
+        Ok(Some(candidates))
+    }
+
+    /// Whether the blacklist, allowlist and health reports let `worker_id` be selected.
+    fn permitted(&self, worker_id: WorkerId) -> bool {
+        !self.blacklist.contains(&worker_id)
+            && !self.unhealthy.contains(&worker_id)
+            && self
+                .allowlist
+                .as_ref()
+                .is_none_or(|allowed| allowed.contains(&worker_id))
+    }
+}
+
+impl KvScheduler {
+    pub fn builder() -> KvSchedulerBuilder {
+        KvSchedulerBuilder::default()
+    }
+
+    pub async fn start(
+        ns: Namespace,
+        block_size: usize,
+        endpoints_rx: tokio::sync::watch::Receiver<ProcessedEndpoints>,
+        selector: Option<Box<dyn WorkerSelector + Send + Sync>>,
+    ) -> Result<Self, KvSchedulerError> {
+        let mut builder = Self::builder()
+            .namespace(ns)
+            .block_size(block_size)
+            .endpoints(endpoints_rx);
+        if let Some(selector) = selector {
+            builder = builder.selector(selector);
+        }
+        builder.start().await
+    }
+
+    /// Report that a request previously scheduled on `worker_id` has completed and released
+    /// `freed_blocks` KV blocks.
+    ///
+    /// Completions feed the per-worker throughput estimate and relax the predicted KV load until
+    /// the next metrics poll overwrites it.
+    pub fn complete(&self, worker_id: impl Into<WorkerId>, freed_blocks: u64) {
+        if self
+            .completion_tx
+            .send(Completion {
+                worker_id: worker_id.into(),
+                freed_blocks,
+            })
+            .is_err()
+        {
+            tracing::trace!("failed to report completion; scheduler shut down");
+        }
+    }
+
+    pub async fn schedule(
+        &self,
+        overlap: OverlapScores,
+        isl_tokens: usize,
+    ) -> Result<WorkerId, KvSchedulerError> {
+        self.schedule_with_options(overlap, isl_tokens, ScheduleOptions::default())
+            .await
+    }
+
+    /// [`KvScheduler::schedule`] with per-request options.
+    pub async fn schedule_with_options(
+        &self,
+        overlap: OverlapScores,
+        isl_tokens: usize,
+        options: ScheduleOptions,
+    ) -> Result<WorkerId, KvSchedulerError> {
+        let scheduled = self.schedule_timed(overlap, isl_tokens, options).await?;
+        Ok(scheduled.worker_id)
+    }
+
+    /// [`KvScheduler::schedule_with_options`] with the overlap scores streamed in as the indexer
+    /// finds them, as `(worker_id, overlap_blocks)` pairs.
+    ///
+    /// Selection starts once `scores` closes or `wait` has passed, whichever comes first, with
+    /// only the scores received by then: workers whose score hasn't arrived are scored as having
+    /// nothing cached. This trades the completeness of the overlap for latency on fleets too
+    /// large to wait for every worker's score. A worker sent twice keeps its last score.
+    pub async fn schedule_streaming(
+        &self,
+        mut scores: tokio::sync::mpsc::Receiver<(WorkerId, f64)>,
+        wait: Duration,
+        isl_tokens: usize,
+        options: ScheduleOptions,
+    ) -> Result<WorkerId, KvSchedulerError> {
+        let deadline = Instant::now() + wait;
+        let mut overlap = OverlapScores::new();
+        loop {
+            match tokio::time::timeout_at(deadline, scores.recv()).await {
+                Ok(Some((worker_id, overlap_blocks))) => {
+                    overlap.scores.insert(worker_id, overlap_blocks);
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    tracing::debug!(
+                        "selecting with the {} overlap scores received within {wait:?}",
+                        overlap.scores.len()
+                    );
+                    break;
+                }
+            }
+        }
+        self.schedule_with_options(overlap, isl_tokens, options)
+            .await
+    }
+
+    /// [`KvScheduler::schedule`] avoiding the `exclude`d workers, as when retrying a request
+    /// that failed on one of them. See [`ScheduleOptions::exclude`].
+    pub async fn schedule_excluding(
+        &self,
+        overlap: OverlapScores,
+        isl_tokens: usize,
+        exclude: &[WorkerId],
+    ) -> Result<WorkerId, KvSchedulerError> {
+        let options = ScheduleOptions {
+            exclude: exclude.to_vec(),
+            ..Default::default()
+        };
+        self.schedule_with_options(overlap, isl_tokens, options)
+            .await
+    }
+
+    /// [`KvScheduler::schedule_with_options`], also reporting how long the request waited.
+    pub async fn schedule_timed(
+        &self,
+        overlap: OverlapScores,
+        isl_tokens: usize,
+        options: ScheduleOptions,
+    ) -> Result<Scheduled, KvSchedulerError> {
+        let resp_rx = self.submit(overlap, isl_tokens, options).await?;
+        resp_rx
+            .await
+            .map_err(|_| KvSchedulerError::SubscriberShutdown)?
+    }
+
+    /// Schedule a request for dispatch to two workers at once, keeping whichever responds first.
+    ///
+    /// Returns the best worker, and the runner-up when its logit is within `speculative_margin`
+    /// of the best. The predicted load of the request is applied to both, so once the losing
+    /// worker is cancelled, report it through [`KvScheduler::complete`] to release its load.
+    pub async fn schedule_speculative(
+        &self,
+        overlap: OverlapScores,
+        isl_tokens: usize,
+    ) -> Result<(WorkerId, Option<WorkerId>), KvSchedulerError> {
+        let (mut request, resp_rx) =
+            self.new_request(overlap, isl_tokens, ScheduleOptions::default())?;
+        request.speculative = true;
+        self.request_tx
+            .send(request)
+            .await
+            .map_err(|_| KvSchedulerError::SubscriberShutdown)?;
+        let scheduled = resp_rx
+            .await
+            .map_err(|_| KvSchedulerError::SubscriberShutdown)??;
+        Ok((scheduled.worker_id, scheduled.secondary))
+    }
+
+    /// Schedule a request like [`KvScheduler::schedule`], also returning how the selector scored
+    /// every candidate and broke any tie, for debugging routing decisions.
+    ///
+    /// Selectors that don't score workers return an empty explanation.
+    pub async fn schedule_explained(
+        &self,
+        overlap: OverlapScores,
+        isl_tokens: usize,
+    ) -> Result<(WorkerId, SelectionExplanation), KvSchedulerError> {
+        let (mut request, resp_rx) =
+            self.new_request(overlap, isl_tokens, ScheduleOptions::default())?;
+        let (explain_tx, explain_rx) = tokio::sync::oneshot::channel();
+        request.explain_tx = Some(explain_tx);
+        self.request_tx
+            .send(request)
+            .await
+            .map_err(|_| KvSchedulerError::SubscriberShutdown)?;
+        let scheduled = resp_rx
+            .await
+            .map_err(|_| KvSchedulerError::SubscriberShutdown)??;
+        let explanation = explain_rx.await.unwrap_or_default();
+        Ok((scheduled.worker_id, explanation))
+    }
+
+    /// The worker [`KvScheduler::schedule`] would pick for a request right now, without
+    /// routing it.
+    ///
+    /// The selector runs on the background task against the same view of the workers real
+    /// requests see, but no predictive load is applied and no events are emitted, so dry runs
+    /// can shadow live traffic without affecting it. Prefix coalescing and sessions are not
+    /// consulted.
+    pub async fn schedule_dry_run(
+        &self,
+        overlap: OverlapScores,
+        isl_tokens: usize,
+    ) -> Result<WorkerSelectionResult, KvSchedulerError> {
+        self.check_request(isl_tokens)?;
+        let (resp_tx, resp_rx) = tokio::sync::oneshot::channel();
+        let request = Box::new(SchedulingRequest::detached(overlap, isl_tokens));
+        self.control_tx
+            .send(ControlMessage::DryRun { request, resp_tx })
+            .map_err(|_| KvSchedulerError::SubscriberShutdown)?;
+        resp_rx
+            .await
+            .map_err(|_| KvSchedulerError::SubscriberShutdown)?
+    }
+
+    /// The scheduler's predicted load, blacklist, sessions and configuration, to persist across a
+    /// restart and seed into the new scheduler with [`KvSchedulerBuilder::start_with_state`].
+    pub async fn export_state(&self) -> Result<SchedulerState, KvSchedulerError> {
+        let (resp_tx, resp_rx) = tokio::sync::oneshot::channel();
+        self.control_tx
+            .send(ControlMessage::ExportState(resp_tx))
+            .map_err(|_| KvSchedulerError::SubscriberShutdown)?;
+        resp_rx
+            .await
+            .map_err(|_| KvSchedulerError::SubscriberShutdown)
+    }
+
+    /// What [`KvScheduler::schedule`] would do with a request right now, without queueing it or
+    /// applying any predictive update: the worker it would be routed to if one has capacity, or
+    /// roughly how long it would wait for one, from the queue depth and the service time model.
+    /// Lets callers make their own admission decisions instead of blocking.
+    pub async fn probe(
+        &self,
+        overlap: OverlapScores,
+        isl_tokens: usize,
+    ) -> Result<ProbeResult, KvSchedulerError> {
+        self.check_request(isl_tokens)?;
+        let (resp_tx, resp_rx) = tokio::sync::oneshot::channel();
+        let request = Box::new(SchedulingRequest::detached(overlap, isl_tokens));
+        let queued = queued(&self.request_tx) + queued(&self.low_priority_tx);
+        self.control_tx
+            .send(ControlMessage::Probe {
+                request,
+                queued,
+                resp_tx,
+            })
+            .map_err(|_| KvSchedulerError::SubscriberShutdown)?;
+        resp_rx
+            .await
+            .map_err(|_| KvSchedulerError::SubscriberShutdown)?
+    }
+
+    /// Take `worker_id` out of rotation, or put it back.
+    ///
+    /// A draining worker receives no new requests but keeps serving its in-flight ones; a
+    /// [`WorkerDrainedEvent`] is emitted once its predicted in-flight count reaches zero.
+    pub fn set_worker_draining(&self, worker_id: impl Into<WorkerId>, draining: bool) {
+        self.send_control(ControlMessage::SetDraining {
+            worker_id: worker_id.into(),
+            draining,
+        });
+    }
+
+    /// Replace the routing configuration without restarting the scheduler or dropping queued
+    /// requests. The new configuration is validated first, and applies from the next selection.
+    pub fn update_config(&self, config: KvRouterConfig) -> Result<(), KvSchedulerError> {
+        config
+            .validate()
+            .map_err(|e| KvSchedulerError::InvalidConfig(e.to_string()))?;
+        self.config_tx.send_replace(config);
+        Ok(())
+    }
+
+    /// Stop routing to `worker_ids` immediately, whatever their metrics or draining state.
+    /// Replaces the previous blacklist; an empty list clears it.
+    pub fn set_blacklist(&self, worker_ids: Vec<WorkerId>) {
+        self.send_control(ControlMessage::SetBlacklist(
+            worker_ids.into_iter().collect(),
+        ));
+    }
+
+    /// Route only to `worker_ids`, or to any worker if `None`. The blacklist still applies.
+    pub fn set_allowlist(&self, worker_ids: Option<Vec<WorkerId>>) {
+        self.send_control(ControlMessage::SetAllowlist(
+            worker_ids.map(|ids| ids.into_iter().collect()),
+        ));
+    }
+
+    /// Stop selecting workers, e.g. while worker metrics are known to be unreliable.
+    ///
+    /// Requests keep being accepted and endpoint updates keep being applied, but requests wait
+    /// until [`KvScheduler::resume`], then are routed in arrival order. Requests still held
+    /// when the scheduler shuts down fail with [`KvSchedulerError::SubscriberShutdown`].
+    pub fn pause(&self) {
+        self.send_control(ControlMessage::Pause);
+    }
+
+    /// Resume selecting workers after [`KvScheduler::pause`].
+    pub fn resume(&self) {
+        self.send_control(ControlMessage::Resume);
+    }
+
+    fn send_control(&self, control: ControlMessage) {
+        if self.control_tx.send(control).is_err() {
+            tracing::trace!("failed to send scheduler control; scheduler shut down");
+        }
+    }
+
+    /// Number of selections per worker over the configured distribution window.
+    /// Updates are published at most once every 100ms.
+    pub fn selection_distribution(&self) -> tokio::sync::watch::Receiver<HashMap<WorkerId, usize>> {
+        self.distribution_rx.clone()
+    }
+
+    /// The scheduler's current view of each known worker: its latest reported metrics and the
+    /// load the scheduler has predicted on top of them since. Refreshed at most once every 100ms.
+    pub fn worker_stats(&self) -> HashMap<WorkerId, WorkerStats> {
+        self.stats_rx.borrow().clone()
+    }
+
+    /// Fraction of `worker_id`'s required blocks that were already cached, over the configured
+    /// hit rate window, or `None` if it wasn't selected within the window.
+    /// Updates are published at most once every 100ms.
+    pub fn hit_rate(&self, worker_id: impl Into<WorkerId>) -> Option<f64> {
+        self.hit_rate_rx.borrow().get(&worker_id.into()).copied()
+    }
+
+    /// Load on the scheduler and its workers from 0 (idle) to 1 (saturated), for autoscaling.
+    ///
+    /// Combines queue fill, mean GPU cache usage and mean waiting requests with the
+    /// `pressure_*` weights of [`KvRouterConfig`]. Updated on every metrics poll and at most
+    /// every 100ms in between.
+    pub fn pressure(&self) -> f64 {
+        *self.pressure_rx.borrow()
+    }
+
+    /// Updates to [`KvScheduler::pressure`].
+    pub fn pressure_updates(&self) -> tokio::sync::watch::Receiver<f64> {
+        self.pressure_rx.clone()
+    }
+
+    /// Fraction of the last `oscillation_window` consecutive selections within a request
+    /// stream that switched workers, from 0 (every stream stays put) to 1 (every request moves).
+    /// A steady workload with a rate near 1 is being ping-ponged by mistuned weights. Streams
+    /// are keyed by [`ScheduleOptions::stream_id`]. Updated on every selection.
+    pub fn oscillation_rate(&self) -> f64 {
+        *self.oscillation_rx.borrow()
+    }
+
+    /// [`KvScheduler::hit_rate`] for every worker selected within the window.
+    pub fn hit_rates(&self) -> HashMap<WorkerId, f64> {
+        self.hit_rate_rx.borrow().clone()
+    }
+
+    /// How many selections were made on a logit in each bucket since the scheduler started, as
+    /// `(upper_bound, count)` pairs in ascending order of bound, the last bound infinite. Only
+    /// selections the selector scored are counted, not ones reusing a session, prefix or
+    /// idempotency key assignment. A histogram bunched into one bucket means the weights barely
+    /// tell workers apart.
+    pub fn logit_histogram(&self) -> Vec<(f64, u64)> {
+        self.logit_histogram_rx.borrow().clone()
+    }
+
+    /// The scheduler's workers, configuration, queues and hit rates as last published.
+    pub fn state_snapshot(&self) -> SchedulerStateSnapshot {
+        self.state_source().snapshot()
+    }
+
+    /// Serve [`SchedulerStateSnapshot`]s on the `KV_SCHEDULER_STATE_ENDPOINT` endpoint of
+    /// `namespace`'s `KV_SCHEDULER_COMPONENT` component until the runtime shuts down, so
+    /// operator tools can inspect the scheduler without a server of their own.
+    ///
+    /// The endpoint is read-only. The returned future doesn't borrow the scheduler, so it can be
+    /// spawned alongside it.
+    pub fn serve_state(
+        &self,
+        namespace: &Namespace,
+    ) -> impl std::future::Future<Output = anyhow::Result<()>> + Send + 'static {
+        let handler = Arc::new(StateEndpointHandler {
+            source: self.state_source(),
+        });
+        let component = namespace.component(KV_SCHEDULER_COMPONENT);
+        async move {
+            component?
+                .service_builder()
+                .create()
+                .await?
+                .endpoint(KV_SCHEDULER_STATE_ENDPOINT)
+                .endpoint_builder()
+                .handler(Ingress::for_engine(handler)?)
+                .start()
+                .await
+        }
+    }
+
+    fn state_source(&self) -> StateSource {
+        StateSource {
+            request_tx: self.request_tx.downgrade(),
+            low_priority_tx: self.low_priority_tx.downgrade(),
+            stats_rx: self.stats_rx.clone(),
+            hit_rate_rx: self.hit_rate_rx.clone(),
+            config_rx: self.config_tx.subscribe(),
+            accepted: self.accepted.clone(),
+            dropped_events: self.event_tx.dropped.clone(),
+        }
+    }
+
+    /// Maximum number of requests that can wait in each priority's scheduling queue.
+    pub fn queue_capacity(&self) -> usize {
+        self.request_tx.max_capacity()
+    }
+
+    /// Number of requests currently waiting in the scheduling queues, across priorities.
+    pub fn queue_len(&self) -> usize {
+        [&self.request_tx, &self.low_priority_tx]
+            .into_iter()
+            .map(queued)
+            .sum()
+    }
+
+    /// Schedule a group of requests, returning the selected workers in request order.
+    ///
+    /// All requests are queued before any response is awaited, so they are selected back to back
+    /// and each selection sees the predicted load of the ones before it.
+    pub async fn schedule_batch(
+        &self,
+        requests: Vec<(OverlapScores, usize)>,
+    ) -> Result<Vec<WorkerId>, KvSchedulerError> {
+        let mut pending = Vec::with_capacity(requests.len());
+        for (overlap, isl_tokens) in requests {
+            pending.push(
+                self.submit(overlap, isl_tokens, ScheduleOptions::default())
+                    .await?,
+            );
+        }
+
+        let mut worker_ids = Vec::with_capacity(pending.len());
+        for resp_rx in pending {
+            let scheduled = resp_rx
+                .await
+                .map_err(|_| KvSchedulerError::SubscriberShutdown)??;
+            worker_ids.push(scheduled.worker_id);
+        }
+        Ok(worker_ids)
+    }
+
+    /// A sender on `priority`'s scheduling queue, for callers that queue requests themselves.
+    #[cfg(feature = "tower")]
+    pub(crate) fn request_sender(
+        &self,
+        priority: Priority,
+    ) -> tokio::sync::mpsc::Sender<SchedulingRequest> {
+        self.queue(priority).clone()
+    }
+
+    fn queue(&self, priority: Priority) -> &tokio::sync::mpsc::Sender<SchedulingRequest> {
+        match priority {
+            Priority::High => &self.request_tx,
+            Priority::Low => &self.low_priority_tx,
+        }
+    }
+
+    /// Reject requests the background task could never route.
+    fn check_request(&self, isl_tokens: usize) -> Result<(), KvSchedulerError> {
+        if self.dead.load(Ordering::Relaxed) {
+            return Err(KvSchedulerError::SchedulerDead);
+        }
+        if isl_tokens == 0 {
+            return Err(KvSchedulerError::InvalidRequest(
+                "isl_tokens must be greater than zero".to_string(),
+            ));
+        }
+        let max_isl_tokens = self.config_tx.borrow().max_isl_tokens;
+        match max_isl_tokens {
+            Some(max) if isl_tokens > max => {
+                Err(KvSchedulerError::RequestTooLarge { isl_tokens, max })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Reject a request of `tenant_id` if the tenant is over its rate limit.
+    fn check_rate(&self, tenant_id: Option<&str>) -> Result<(), KvSchedulerError> {
+        let Some(tenant_id) = tenant_id else {
+            return Ok(());
+        };
+        let Some(limit) = self
+            .config_tx
+            .borrow()
+            .tenant_rate_limits
+            .get(tenant_id)
+            .copied()
+        else {
+            return Ok(());
+        };
+        let admitted = self
+            .rate_limiter
+            .lock()
+            .expect("tenant rate limiter lock poisoned")
+            .try_acquire(tenant_id, limit, Instant::now());
+        if !admitted {
+            tracing::debug!("rate limiting request of tenant {tenant_id}");
+            return Err(KvSchedulerError::RateLimited {
+                tenant_id: tenant_id.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Shed a request of `priority` if the scheduler is at its high-water mark.
+    fn check_load(&self, priority: Priority) -> Result<(), KvSchedulerError> {
+        let config = self.config_tx.borrow();
+        let Some(high_water_mark) = config.load_shed_high_water_mark else {
+            return Ok(());
+        };
+        if !config.load_shed_priorities.contains(&priority) {
+            return Ok(());
+        }
+        let load = self.load();
+        if load >= high_water_mark {
+            tracing::debug!("shedding {priority:?} priority request at load {load}");
+            return Err(KvSchedulerError::LoadShed {
+                load,
+                high_water_mark,
+            });
+        }
+        Ok(())
+    }
+
+    /// Events dropped because the publishing task fell behind by more than the event queue's
+    /// capacity.
+    pub fn dropped_events(&self) -> u64 {
+        self.event_tx.dropped()
+    }
+
+    /// Times the background task has read the endpoints, including the initial read. Bounded
+    /// by `endpoints_debounce_ms` when set, however often they change.
+    pub fn endpoint_reads(&self) -> u64 {
+        self.endpoint_reads.load(Ordering::Relaxed)
+    }
+
+    /// Fraction of the scheduling requests that ended within `window` that failed, with any
+    /// error, or because their requester went away before a worker was selected. Windows
+    /// longer than the builder's `rejection_rate_retention` only cover the retention. 0.0 if no
+    /// request ended within the window.
+    pub fn rejection_rate(&self, window: Duration) -> f64 {
+        self.outcomes
+            .lock()
+            .expect("outcome window lock poisoned")
+            .rejection_rate(window, Instant::now())
+    }
+
+    /// The latest scheduling decisions, oldest first, up to the builder's
+    /// `decision_log_capacity`. Empty unless the decision log is enabled.
+    pub fn recent_decisions(&self) -> Vec<DecisionRecord> {
+        self.decisions.as_ref().map_or_else(Vec::new, |decisions| {
+            decisions
+                .lock()
+                .expect("decision log lock poisoned")
+                .records
+                .iter()
+                .cloned()
+                .collect()
+        })
+    }
+
+    /// Wait for every event buffered so far to be published through the sink, for at most
+    /// `timeout` so a stuck sink can't hold up the caller.
+    pub async fn flush_events(&self, timeout: Duration) -> Result<(), KvSchedulerError> {
+        let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+        self.flush_tx
+            .send(done_tx)
+            .map_err(|_| KvSchedulerError::SubscriberShutdown)?;
+        match tokio::time::timeout(timeout, done_rx).await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(_)) => Err(KvSchedulerError::SubscriberShutdown),
+            Err(_) => {
+                tracing::warn!("scheduler events not flushed within {timeout:?}");
+                Err(KvSchedulerError::FlushTimeout(timeout))
+            }
+        }
+    }
+
+    /// Stop the scheduler, even while other handles on it remain, then publish the events still
+    /// buffered, for at most [`DEFAULT_EVENT_FLUSH_TIMEOUT`]. Requests already queued are
+    /// scheduled first, so their events are among those published; requests waiting for
+    /// capacity or held while paused, and any made afterwards, fail with
+    /// [`KvSchedulerError::SchedulerDead`].
+    pub async fn shutdown(&self) -> Result<(), KvSchedulerError> {
+        let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+        self.send_control(ControlMessage::Shutdown(done_tx));
+        // Dropped unanswered if the task had already stopped
+        let _ = done_rx.await;
+        self.flush_events(DEFAULT_EVENT_FLUSH_TIMEOUT).await
+    }
+
+    /// Requests queued for scheduling, held while paused, waiting for capacity, or predicted in
+    /// flight on a worker.
+    pub fn load(&self) -> usize {
+        queued(&self.request_tx)
+            + queued(&self.low_priority_tx)
+            + self.accepted.load(Ordering::Relaxed)
+    }
+
+    /// Blocking variant of [`KvScheduler::schedule`] for synchronous callers.
+    ///
+    /// Waits on the calling thread until a worker is selected. It must not be called from within
+    /// an async runtime, where it would stall the executor; doing so returns
+    /// [`KvSchedulerError::BlockingInAsyncContext`].
+    pub fn schedule_blocking(
+        &self,
+        overlap: OverlapScores,
+        isl_tokens: usize,
+    ) -> Result<WorkerId, KvSchedulerError> {
+        if tokio::runtime::Handle::try_current().is_ok() {
+            return Err(KvSchedulerError::BlockingInAsyncContext);
+        }
+        let (request, resp_rx) =
+            self.new_request(overlap, isl_tokens, ScheduleOptions::default())?;
+        self.queue(request.priority)
+            .blocking_send(request)
+            .map_err(|_| KvSchedulerError::SubscriberShutdown)?;
+        let scheduled = resp_rx
+            .blocking_recv()
+            .map_err(|_| KvSchedulerError::SubscriberShutdown)??;
+        Ok(scheduled.worker_id)
+    }
+
+    /// Queue a request with the background task and return the channel its selection arrives on.
+    async fn submit(
+        &self,
+        overlap: OverlapScores,
+        isl_tokens: usize,
+        options: ScheduleOptions,
+    ) -> Result<ResponseReceiver, KvSchedulerError> {
+        let (request, resp_rx) = self.new_request(overlap, isl_tokens, options)?;
+        self.queue(request.priority)
+            .send(request)
+            .await
+            .map_err(|_| KvSchedulerError::SubscriberShutdown)?;
+        Ok(resp_rx)
+    }
+
+    pub(crate) fn new_request(
+        &self,
+        overlap: OverlapScores,
+        isl_tokens: usize,
+        options: ScheduleOptions,
+    ) -> Result<(SchedulingRequest, ResponseReceiver), KvSchedulerError> {
+        self.check_request(isl_tokens)
+            .and_then(|()| match options.block_size {
+                Some(0) => Err(KvSchedulerError::InvalidRequest(
+                    "block_size must be greater than zero".to_string(),
+                )),
+                _ => Ok(()),
+            })
+            .and_then(|()| self.check_load(options.priority))
+            .and_then(|()| self.check_rate(options.tenant_id.as_deref()))
+            .inspect_err(|e| {
+                emit_rejected(&self.event_tx, e, isl_tokens);
+                record_outcome(Some(&self.outcomes), true);
+            })?;
+        let (resp_tx, resp_rx) = tokio::sync::oneshot::channel();
+        let request = SchedulingRequest {
+            isl_tokens,
+            overlap,
+            role: options.role,
+            prefix_hash: options.prefix_hash,
+            priority: options.priority,
+            tenant_id: options.tenant_id,
+            session_id: options.session_id,
+            idempotency_key: options.idempotency_key,
+            deadline: options.deadline,
+            exclude: options.exclude,
+            zone: options.zone,
+            expected_output_tokens: options.expected_output_tokens,
+            stream_id: options.stream_id,
+            request_id: options.request_id,
+            block_size: options.block_size,
+            #[cfg(feature = "otel")]
+            otel_context: options.otel_context,
+            resp_tx,
+            speculative: false,
+            explain_tx: None,
+            enqueued_at: Instant::now(),
+            outcomes: Some(self.outcomes.clone()),
+        };
+        Ok((request, resp_rx))
+    }
+}
+
+// This becomes the driver function that handles the selection result
+//
+// A request can't have more of its blocks cached than it has, but indexers may report a longer
+// match, such as a cached prefix that runs past the end of the request. `overlap_blocks` is
+// clamped to `required_blocks` so the predicted load and the hit rate event stay consistent
+// with the request, and the hit rate never exceeds 1.
+pub fn process_worker_selection(
+    workers: &mut ProcessedEndpoints,
+    mut selection: WorkerSelectionResult,
+    event_tx: &EventSender,
+    on_selection: Option<&SelectionHook>,
+) -> Result<WorkerId, KvSchedulerError> {
+    selection.overlap_blocks = selection
+        .overlap_blocks
+        .clamp(0.0, selection.required_blocks as f64);
+    let Some(worker) = workers.endpoints.get_mut(&selection.worker_id) else {
+        return Err(KvSchedulerError::WorkerNotFound {
+            worker_id: selection.worker_id,
+        });
+    };
+
+    // Update worker state predictively
+    // Will be overwritten on next polling of metrics
+    worker.data.num_requests_waiting += 1;
+    worker.data.request_active_slots += 1;
+    if let Some(waiting) = workers.waiting_percentile.get_mut(&selection.worker_id) {
+        *waiting += 1.0;
+    }
+    // Assumes radix attention so KV load is only incremented by uncached blocks, plus those
+    // the request is expected to fill while decoding
+    worker.data.kv_active_blocks += selection
+        .required_blocks
+        .saturating_sub(selection.overlap_blocks as u64)
+        + selection.decode_blocks;
+    // Starts the worker's selection cooldown, which unlike the rest outlives the next poll
+    workers
+        .last_selected
+        .insert(selection.worker_id, Instant::now());
+
+    if let Some(on_selection) = on_selection {
+        on_selection(&selection);
+    }
+
+    // Emit event
+    if let Err(e) = event_tx.send(SchedulerEvent::HitRate(KVHitRateEvent {
+        worker_id: selection.worker_id,
+        isl_blocks: selection.required_blocks as usize,
+        overlap_blocks: selection.overlap_blocks.round() as usize,
+    })) {
+        tracing::warn!("Failed to send KV hit rate event: {:?}", e);
+    }
+
+    Ok(selection.worker_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_router::{RoutingMode, TenantRateLimit, TieBreakPolicy, UsagePenaltyCurve};
+
+    // Helper to create a worker endpoint
+    fn create_endpoint(
+        worker_id: i64,
+        gpu_cache_usage_perc: f32,
+        num_requests_waiting: u64,
+    ) -> Endpoint {
+        Endpoint {
+            name: format!("worker-{}", worker_id),
+            subject: format!("worker-subject-{:x}", worker_id),
+            data: ForwardPassMetrics {
+                gpu_cache_usage_perc,
+                num_requests_waiting,
+                // Other fields can be default initialized for this test
+                ..Default::default()
+            },
+        }
+    }
+
+    // Helper to create ProcessedEndpoints
+    struct WorkerInfo {
+        id: i64,
+        usage: f32,
+        waiting: u64,
+    }
+    fn create_workers(workers: Vec<WorkerInfo>) -> ProcessedEndpoints {
+        let mut endpoints = HashMap::new();
+        for worker in workers {
+            endpoints.insert(
+                WorkerId(worker.id),
+                create_endpoint(worker.id, worker.usage, worker.waiting),
+            );
+        }
+        ProcessedEndpoints {
+            endpoints,
+            load_avg: 0.0,
+            load_std: 0.0,
+            throughput: HashMap::new(),
+            warmup: HashMap::new(),
+            fairness: HashMap::new(),
+            waiting_percentile: HashMap::new(),
+            locality: HashMap::new(),
+            quota_overage: 0.0,
+            last_selected: HashMap::new(),
+        }
+    }
+
+    // Helper to create a scheduling request
+    struct WorkerOverlap {
+        worker_id: i64,
+        overlap_blocks: f64,
+    }
+    fn create_request(overlaps: Vec<WorkerOverlap>, isl_tokens: usize) -> SchedulingRequest {
+        SchedulingRequest {
+            isl_tokens,
+            overlap: OverlapScores {
+                scores: overlaps
+                    .into_iter()
+                    .map(|wo| (WorkerId(wo.worker_id), wo.overlap_blocks))
+                    .collect(),
+                ..Default::default()
+            },
+            role: WorkerRole::default(),
+            prefix_hash: None,
+            priority: Priority::default(),
+            tenant_id: None,
+            session_id: None,
+            idempotency_key: None,
+            deadline: None,
+            exclude: Vec::new(),
+            zone: None,
+            expected_output_tokens: None,
+            stream_id: None,
+            request_id: None,
+            block_size: None,
+            #[cfg(feature = "otel")]
+            otel_context: None,
+            resp_tx: tokio::sync::oneshot::channel().0,
+            speculative: false,
+            explain_tx: None,
+            enqueued_at: Instant::now(),
+            outcomes: None,
+        }
+    }
+
+    #[test]
+    fn test_select_worker_basic() {
+        // Setup workers
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.50,
+                waiting: 1,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.80,
+                waiting: 0,
+            },
+        ]);
+
+        // Setup request: 100 tokens, block_size=20 (5 blocks)
+        let request = create_request(
+            vec![
+                WorkerOverlap {
+                    worker_id: 1,
+                    overlap_blocks: 3.0,
+                },
+                WorkerOverlap {
+                    worker_id: 2,
+                    overlap_blocks: 4.0,
+                },
+            ],
+            100,
+        );
+        let selector = DefaultWorkerSelector::new(None);
+        let block_size = 20;
+
+        // Execute selection
+        let result = selector
+            .select_worker(&workers, &request, block_size)
+            .expect("Should select a worker");
+        // Worker 2 should win because:
+        // Worker1: 2.0 * 0.600 - 1.0 * 0.500 - 1.0 * 1.000 = -0.3
+        // Worker2: 2.0 * 0.800 - 1.0 * 0.800 - 1.0 * 0.000 = 0.8
+        assert_eq!(result.worker_id, 2);
+        assert_eq!(result.required_blocks, 5); // 100 tokens / 20 block_size
+        assert_eq!(result.overlap_blocks, 4.0);
+    }
+
+    #[test]
+    fn test_no_endpoints() {
+        let workers = create_workers(vec![]);
+        let request = create_request(vec![], 100);
+        let selector = DefaultWorkerSelector::new(None);
+        let block_size = 20;
+
+        match selector.select_worker(&workers, &request, block_size) {
+            Err(KvSchedulerError::NoEndpoints) => {} // Expected
+            _ => panic!("Should return NoEndpoints error"),
+        }
+    }
+
+    #[test]
+    fn test_no_overlap_scores() {
+        // Workers exist but request has no overlap scores
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.50,
+            waiting: 1,
+        }]);
+        let request = create_request(vec![], 100); // No overlaps
+        let selector = DefaultWorkerSelector::new(None);
+        let block_size = 20;
+
+        let result = selector
+            .select_worker(&workers, &request, block_size)
+            .expect("Should fallback to selecting worker");
+
+        // Worker1 should be selected with 0 overlap
+        assert_eq!(result.worker_id, 1);
+        assert_eq!(result.overlap_blocks, 0.0);
+    }
+
+    #[test]
+    fn test_custom_weights() {
+        // Setup workers
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.50,
+                waiting: 1,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.80,
+                waiting: 0,
+            },
+        ]);
+
+        // Custom config with high priority on GPU usage
+        let config = KvRouterConfig {
+            gpu_cache_usage_weight: 10.0, // Very high weight
+            overlap_score_weight: 2.0,    // just current defaults
+            waiting_requests_weight: 1.0,
+            ..Default::default()
+        };
+        let selector = DefaultWorkerSelector::new(Some(config));
+        let request = create_request(
+            vec![
+                WorkerOverlap {
+                    worker_id: 1,
+                    overlap_blocks: 3.0,
+                },
+                WorkerOverlap {
+                    worker_id: 2,
+                    overlap_blocks: 4.0,
+                },
+            ],
+            100,
+        );
+        let block_size = 20;
+
+        let result = selector
+            .select_worker(&workers, &request, block_size)
+            .expect("Should select worker");
+
+        assert_eq!(result.worker_id, 1);
+    }
+
+    #[test]
+    fn test_fractional_overlap_scores() {
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.1,
+                waiting: 0,
+            },
+        ]);
+        let selector = DefaultWorkerSelector::new(None);
+        let block_size = 20;
+
+        // 100 tokens, block_size=20 (5 blocks)
+        let fractional = create_request(
+            vec![
+                WorkerOverlap {
+                    worker_id: 1,
+                    overlap_blocks: 2.4,
+                },
+                WorkerOverlap {
+                    worker_id: 2,
+                    overlap_blocks: 2.6,
+                },
+            ],
+            100,
+        );
+        // Worker1: 2.0 * 0.48 - 1.0 * 0.0 = 0.96
+        // Worker2: 2.0 * 0.52 - 1.0 * 0.1 = 0.94
+        let result = selector
+            .select_worker(&workers, &fractional, block_size)
+            .expect("Should select a worker");
+        assert_eq!(result.worker_id, 1);
+        assert_eq!(result.overlap_blocks, 2.4);
+
+        // The same request from an integer producer rounds 2.4 -> 2 and 2.6 -> 3
+        let mut rounded = create_request(vec![], 100);
+        rounded.overlap =
+            OverlapScores::from_block_counts(HashMap::from([(WorkerId(1), 2), (WorkerId(2), 3)]));
+        // Worker1: 2.0 * 0.4 - 1.0 * 0.0 = 0.8
+        // Worker2: 2.0 * 0.6 - 1.0 * 0.1 = 1.1
+        let result = selector
+            .select_worker(&workers, &rounded, block_size)
+            .expect("Should select a worker");
+        assert_eq!(result.worker_id, 2);
+        assert_eq!(result.overlap_blocks, 3.0);
+    }
+
+    #[test]
+    fn test_per_worker_block_size() {
+        let mut workers = create_workers(
+            (1..=3)
+                .map(|id| WorkerInfo {
+                    id,
+                    usage: 0.0,
+                    waiting: 0,
+                })
+                .collect(),
+        );
+        // Worker 3 doesn't report a block size and uses the default
+        for (worker_id, block_size) in [(1, 16), (2, 32)] {
+            workers
+                .endpoints
+                .get_mut(&worker_id)
+                .unwrap()
+                .data
+                .kv_block_size = Some(block_size);
+        }
+        // 2 cached blocks of a 128 token request on every worker
+        let request = create_request(
+            (1..=3)
+                .map(|worker_id| WorkerOverlap {
+                    worker_id,
+                    overlap_blocks: 2.0,
+                })
+                .collect(),
+            128,
+        );
+
+        let (selection, explanation) = DefaultWorkerSelector::new(None)
+            .explain_selection(&workers, &request, 20)
+            .unwrap();
+        // 2 of 8, 4 and 7 blocks cached; 128 tokens round up to 7 blocks of 20
+        let scores: Vec<f64> = explanation.candidates.iter().map(|c| c.score).collect();
+        assert_eq!(scores, vec![0.25, 0.5, 2.0 / 7.0]);
+        assert_eq!(selection.worker_id, 2);
+        assert_eq!(selection.required_blocks, 4);
+    }
+
+    #[test]
+    fn test_per_request_block_size() {
+        let mut workers = create_workers(
+            (1..=2)
+                .map(|id| WorkerInfo {
+                    id,
+                    usage: 0.0,
+                    waiting: 0,
+                })
+                .collect(),
+        );
+        workers.endpoints.get_mut(&2).unwrap().data.kv_block_size = Some(32);
+        let selector = DefaultWorkerSelector::new(None);
+        let (event_tx, _event_rx) = EventSender::channel(DEFAULT_EVENT_CHANNEL_CAPACITY);
+        let request = |worker_id, block_size| {
+            let mut request = create_request(
+                vec![WorkerOverlap {
+                    worker_id,
+                    overlap_blocks: 2.0,
+                }],
+                128,
+            );
+            request.block_size = block_size;
+            request
+        };
+
+        // 2 cached blocks of a 128 token request, counted in each request's own blocks
+        for (block_size, blocks) in [(Some(16), 8), (Some(64), 2), (None, 7)] {
+            let request = request(1, block_size);
+            let (selection, explanation) =
+                selector.explain_selection(&workers, &request, 20).unwrap();
+            assert_eq!(explanation.candidates[0].score, 2.0 / blocks as f64);
+            assert_eq!(selection.worker_id, 1);
+            assert_eq!(selection.required_blocks, blocks);
+
+            let mut workers = workers.clone();
+            process_worker_selection(&mut workers, selection, &event_tx, None).unwrap();
+            assert_eq!(workers.endpoints[&1].data.kv_active_blocks, blocks - 2);
+        }
+
+        // A worker reporting its own block size keeps it whatever the request's
+        let selection = selector
+            .select_worker(&workers, &request(2, Some(16)), 20)
+            .unwrap();
+        assert_eq!(selection.worker_id, 2);
+        assert_eq!(selection.required_blocks, 4);
+    }
+
+    #[test]
+    fn test_unaligned_isl_rounds_up_to_blocks() {
+        let selector = DefaultWorkerSelector::new(None);
+        let (event_tx, _event_rx) = EventSender::channel(DEFAULT_EVENT_CHANNEL_CAPACITY);
+        // 100 tokens is exactly 5 blocks of 20; 101 and 119 both need a sixth
+        for (isl_tokens, blocks) in [(100, 5), (101, 6), (119, 6)] {
+            let mut workers = create_workers(vec![WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 0,
+            }]);
+            let request = create_request(
+                vec![WorkerOverlap {
+                    worker_id: 1,
+                    overlap_blocks: 3.0,
+                }],
+                isl_tokens,
+            );
+            let (selection, explanation) =
+                selector.explain_selection(&workers, &request, 20).unwrap();
+            assert_eq!(selection.required_blocks, blocks);
+            assert_eq!(explanation.candidates[0].score, 3.0 / blocks as f64);
+
+            // The uncached rest of the same block count is added predictively
+            let kv_active_blocks = workers.endpoints[&1].data.kv_active_blocks;
+            process_worker_selection(&mut workers, selection, &event_tx, None).unwrap();
+            assert_eq!(
+                workers.endpoints[&1].data.kv_active_blocks,
+                kv_active_blocks + blocks - 3
+            );
+        }
+    }
+
+    #[test]
+    fn test_waiting_normalization_floor() {
+        // Worker 3 has a transient spike of waiting requests
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.0,
+                waiting: 2,
+            },
+            WorkerInfo {
+                id: 3,
+                usage: 0.0,
+                waiting: 8,
+            },
+        ]);
+        let request = create_request(vec![], 100);
+        let normalized_waiting = |floor| {
+            let selector = DefaultWorkerSelector::new(Some(KvRouterConfig {
+                waiting_normalization_floor: floor,
+                ..Default::default()
+            }));
+            let (_, explanation) = selector.explain_selection(&workers, &request, 20).unwrap();
+            explanation
+                .candidates
+                .iter()
+                .map(|candidate| candidate.normalized_waiting)
+                .collect::<Vec<_>>()
+        };
+
+        // Normalized by the spike, worker 2's two waiting requests barely register
+        assert_eq!(normalized_waiting(0.0), vec![0.0, 0.25, 1.0]);
+        // A floor below the spike changes nothing
+        assert_eq!(normalized_waiting(4.0), vec![0.0, 0.25, 1.0]);
+        // Above it, each worker is scaled by the floor alone, whatever the spike
+        assert_eq!(normalized_waiting(16.0), vec![0.0, 0.125, 0.5]);
+    }
+
+    #[test]
+    fn test_min_blocks_for_kv_routing() {
+        // Worker 1 holds the cached prefix but is busier
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.3,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.0,
+                waiting: 0,
+            },
+        ]);
+        let selector = DefaultWorkerSelector::new(Some(KvRouterConfig {
+            min_blocks_for_kv_routing: 4,
+            ..Default::default()
+        }));
+        let block_size = 20;
+
+        // 60 tokens is 3 blocks, below the threshold: overlap is ignored
+        // Worker1: -1.0 * 0.3 = -0.3
+        // Worker2: 0.0
+        let short = create_request(
+            vec![WorkerOverlap {
+                worker_id: 1,
+                overlap_blocks: 3.0,
+            }],
+            60,
+        );
+        let result = selector
+            .select_worker(&workers, &short, block_size)
+            .expect("Should select a worker");
+        assert_eq!(result.worker_id, 2);
+
+        // 80 tokens is 4 blocks, at the threshold: overlap counts as before
+        // Worker1: 2.0 * 0.75 - 1.0 * 0.3 = 1.2
+        // Worker2: 0.0
+        let long = create_request(
+            vec![WorkerOverlap {
+                worker_id: 1,
+                overlap_blocks: 3.0,
+            }],
+            80,
+        );
+        let result = selector
+            .select_worker(&workers, &long, block_size)
+            .expect("Should select a worker");
+        assert_eq!(result.worker_id, 1);
+        assert_eq!(result.overlap_blocks, 3.0);
+
+        // Without a threshold the short request sticks to the cached worker
+        // Worker1: 2.0 * 1.0 - 1.0 * 0.3 = 1.7
+        let result = DefaultWorkerSelector::new(None)
+            .select_worker(&workers, &short, block_size)
+            .expect("Should select a worker");
+        assert_eq!(result.worker_id, 1);
+    }
+
+    #[test]
+    fn test_recency_weighted_overlap() {
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.0,
+                waiting: 0,
+            },
+        ]);
+        let block_size = 20;
+        let select = |recency_weight, recency_scores: HashMap<WorkerId, f64>| {
+            let selector = DefaultWorkerSelector::new(Some(KvRouterConfig {
+                recency_weight,
+                deterministic: true,
+                ..Default::default()
+            }));
+            // Both workers hold 4 of the 5 blocks
+            let mut request = create_request(vec![], 100);
+            request.overlap = OverlapScores {
+                scores: HashMap::from([(WorkerId(1), 4.0), (WorkerId(2), 4.0)]),
+                recency_scores,
+                ..Default::default()
+            };
+            selector
+                .select_worker(&workers, &request, block_size)
+                .expect("Should select a worker")
+        };
+        // Worker 1's blocks have gone cold, worker 2's were just used
+        let profiles = HashMap::from([(WorkerId(1), 0.8), (WorkerId(2), 3.6)]);
+
+        // Flat counts tie, broken by lowest id
+        assert_eq!(select(0.0, profiles.clone()).worker_id, 1);
+        // Worker1: 4.0 - 0.5 * 3.2 = 2.4 blocks
+        // Worker2: 4.0 - 0.5 * 0.4 = 3.8 blocks
+        let result = select(0.5, profiles.clone());
+        assert_eq!(result.worker_id, 2);
+        // The selection still reports the flat overlap
+        assert_eq!(result.overlap_blocks, 4.0);
+
+        // Without recency data worker 1 keeps its flat 4.0 blocks
+        let partial = HashMap::from([(WorkerId(2), 3.6)]);
+        assert_eq!(select(0.5, partial).worker_id, 1);
+    }
+
+    #[test]
+    fn test_throughput_weight() {
+        // Both workers tie on load, worker 2 clears work twice as fast
+        let mut workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.50,
+                waiting: 1,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.50,
+                waiting: 1,
+            },
+        ]);
+        workers.throughput = HashMap::from([(WorkerId(1), 5.0), (WorkerId(2), 10.0)]);
+        let request = create_request(vec![], 100);
+        let block_size = 20;
+
+        let config = KvRouterConfig {
+            throughput_weight: 1.0,
+            ..Default::default()
+        };
+        let selector = DefaultWorkerSelector::new(Some(config));
+        // Worker1: -1.0 * 0.5 - 1.0 * 1.0 + 1.0 * 0.5 = -1.0
+        // Worker2: -1.0 * 0.5 - 1.0 * 1.0 + 1.0 * 1.0 = -0.5
+        for _ in 0..10 {
+            let result = selector
+                .select_worker(&workers, &request, block_size)
+                .expect("Should select a worker");
+            assert_eq!(result.worker_id, 2);
+        }
+    }
+
+    /// An [`EventSink`] that records every event it receives.
+    #[derive(Clone, Default)]
+    struct RecordingSink {
+        events: Arc<std::sync::Mutex<Vec<SchedulerEvent>>>,
+    }
+
+    #[async_trait]
+    impl EventSink for RecordingSink {
+        async fn publish(&self, event: &SchedulerEvent) -> anyhow::Result<()> {
+            self.events.lock().unwrap().push(event.clone());
+            Ok(())
+        }
+    }
+
+    impl RecordingSink {
+        async fn wait_for_events(&self, count: usize) -> Vec<SchedulerEvent> {
+            for _ in 0..100 {
+                {
+                    let events = self.events.lock().unwrap();
+                    if events.len() >= count {
+                        return events.clone();
+                    }
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            self.events.lock().unwrap().clone()
+        }
+    }
+
+    #[test]
+    fn test_event_encoding_round_trip() {
+        let event = KVHitRateEvent {
+            worker_id: WorkerId(0x7f3a_12c4),
+            isl_blocks: 512,
+            overlap_blocks: 384,
+        };
+        let json = EventEncoding::Json.encode(&event).unwrap();
+        let binary = EventEncoding::MessagePack.encode(&event).unwrap();
+        assert!(binary.len() < json.len() / 3);
+
+        let decoded: KVHitRateEvent = EventEncoding::MessagePack.decode(&binary).unwrap();
+        assert_eq!(decoded, event);
+        let decoded: KVHitRateEvent = EventEncoding::Json.decode(&json).unwrap();
+        assert_eq!(decoded, event);
+
+        assert_eq!(EventEncoding::default(), EventEncoding::Json);
+        assert_eq!(EventEncoding::Json.subject_suffix(), "");
+        assert_eq!(EventEncoding::MessagePack.subject_suffix(), ".msgpack");
+    }
+
+    #[tokio::test]
+    async fn test_builder_start_and_schedule() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.50,
+            waiting: 0,
+        }]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let sink = RecordingSink::default();
+
+        let scheduler = KvScheduler::builder()
+            .event_sink(sink.clone())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .selector(Box::new(DefaultWorkerSelector::default()))
+            .channel_capacity(8)
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        let overlap = OverlapScores::from_block_counts(HashMap::from([(WorkerId(1), 2)]));
+        let worker_id = scheduler
+            .schedule(overlap, 100)
+            .await
+            .expect("Should schedule request");
+        assert_eq!(worker_id, 1);
+
+        let events = sink.wait_for_events(1).await;
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            SchedulerEvent::HitRate(event) => {
+                assert_eq!(event.worker_id, 1);
+                assert_eq!(event.isl_blocks, 5);
+                assert_eq!(event.overlap_blocks, 2);
+            }
+            other => panic!("Expected a hit rate event, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_worker_id_round_trip() {
+        // The id parsed from the endpoint subject is the one overlap scores, the selection and
+        // its events refer to
+        let workers = ProcessedEndpoints::new(vec![
+            create_endpoint(0x7f3a, 0.0, 0),
+            create_endpoint(0x7f3b, 0.0, 0),
+        ]);
+        let worker_id = WorkerId::from(0x7f3a);
+        assert!(workers.endpoints.contains_key(&worker_id));
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let sink = RecordingSink::default();
+        let scheduler = KvScheduler::builder()
+            .event_sink(sink.clone())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        let overlap = OverlapScores::from_block_counts(HashMap::from([(worker_id, 5)]));
+        let scheduled = scheduler.schedule(overlap, 100).await.unwrap();
+        assert_eq!(scheduled, worker_id);
+        assert_eq!(i64::from(scheduled), 0x7f3a);
+
+        match &sink.wait_for_events(1).await[0] {
+            SchedulerEvent::HitRate(event) => assert_eq!(event.worker_id, worker_id),
+            other => panic!("Expected a hit rate event, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_builder_missing_fields() {
+        let (_endpoints_tx, endpoints_rx) =
+            tokio::sync::watch::channel(ProcessedEndpoints::default());
+
+        // block_size is required
+        let result = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .endpoints(endpoints_rx.clone())
+            .start()
+            .await;
+        assert!(matches!(result, Err(KvSchedulerError::InvalidConfig(_))));
+
+        // a namespace or event sink is required
+        let result = KvScheduler::builder()
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .start()
+            .await;
+        assert!(matches!(result, Err(KvSchedulerError::InvalidConfig(_))));
+    }
+
+    #[tokio::test]
+    async fn test_schedule_batch_spreads_load() {
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.0,
+                waiting: 0,
+            },
+        ]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        // Identical overlap on both workers, so only the predicted load separates them
+        let overlap =
+            OverlapScores::from_block_counts(HashMap::from([(WorkerId(1), 2), (WorkerId(2), 2)]));
+        let requests = (0..4).map(|_| (overlap.clone(), 100)).collect();
+        let worker_ids = scheduler
+            .schedule_batch(requests)
+            .await
+            .expect("Should schedule batch");
+
+        assert_eq!(worker_ids.len(), 4);
+        assert_ne!(worker_ids[0], worker_ids[1]);
+        assert_eq!(worker_ids.iter().filter(|id| **id == 1).count(), 2);
+        assert_eq!(worker_ids.iter().filter(|id| **id == 2).count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_channel_capacity() {
+        let (_endpoints_tx, endpoints_rx) =
+            tokio::sync::watch::channel(ProcessedEndpoints::default());
+
+        for capacity in [1, 16, 4096] {
+            let scheduler = KvScheduler::builder()
+                .event_sink(RecordingSink::default())
+                .block_size(20)
+                .endpoints(endpoints_rx.clone())
+                .channel_capacity(capacity)
+                .start()
+                .await
+                .expect("Should start scheduler");
+            assert_eq!(scheduler.queue_capacity(), capacity);
+            assert_eq!(scheduler.queue_len(), 0);
+        }
+
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx.clone())
+            .start()
+            .await
+            .expect("Should start scheduler");
+        assert_eq!(scheduler.queue_capacity(), DEFAULT_CHANNEL_CAPACITY);
+
+        let result = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .channel_capacity(0)
+            .start()
+            .await;
+        assert!(matches!(result, Err(KvSchedulerError::InvalidConfig(_))));
+    }
+
+    #[tokio::test]
+    async fn test_worker_draining() {
+        // Worker 2 is nearly full, so worker 1 is preferred whenever it's available
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.9,
+                waiting: 0,
+            },
+        ]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let sink = RecordingSink::default();
+        let scheduler = KvScheduler::builder()
+            .event_sink(sink.clone())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        let overlap = OverlapScores::default();
+        assert_eq!(scheduler.schedule(overlap.clone(), 100).await.unwrap(), 1);
+
+        // Enter drain: new requests avoid worker 1 while its request is in flight
+        scheduler.set_worker_draining(1, true);
+        for _ in 0..3 {
+            assert_eq!(scheduler.schedule(overlap.clone(), 100).await.unwrap(), 2);
+        }
+
+        // The in-flight request on worker 1 completes, so it is drained
+        scheduler.complete(1, 5);
+        let events = sink.wait_for_events(5).await;
+        let drained: Vec<WorkerId> = events
+            .iter()
+            .filter_map(|event| match event {
+                SchedulerEvent::WorkerDrained(event) => Some(event.worker_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(drained, vec![1]);
+
+        // Exit drain: worker 1 is preferred again
+        scheduler.set_worker_draining(1, false);
+        assert_eq!(scheduler.schedule(overlap, 100).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_worker_drained_by_poll() {
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.9,
+                waiting: 0,
+            },
+        ]);
+        let (endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers.clone());
+        let sink = RecordingSink::default();
+        let scheduler = KvScheduler::builder()
+            .event_sink(sink.clone())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .start()
+            .await
+            .expect("Should start scheduler");
+        let drained = |events: &[SchedulerEvent]| -> Vec<WorkerId> {
+            events
+                .iter()
+                .filter_map(|event| match event {
+                    SchedulerEvent::WorkerDrained(event) => Some(event.worker_id),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            scheduler
+                .schedule(OverlapScores::default(), 100)
+                .await
+                .unwrap(),
+            1
+        );
+        scheduler.set_worker_draining(1, true);
+
+        // Worker 1 still reports the request active, so it isn't drained yet
+        let mut busy = workers.clone();
+        busy.endpoints
+            .get_mut(&1)
+            .unwrap()
+            .data
+            .request_active_slots = 1;
+        endpoints_tx.send(busy).unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(drained(&sink.events.lock().unwrap()).is_empty());
+
+        // No completion is reported, but the worker says it's done
+        endpoints_tx.send(workers).unwrap();
+        let events = sink.wait_for_events(2).await;
+        assert_eq!(drained(&events), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_draining_fallback() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let config = KvRouterConfig {
+            allow_draining_fallback: true,
+            ..Default::default()
+        };
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(config)
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        // The only worker is draining, but the config permits falling back to it
+        scheduler.set_worker_draining(1, true);
+        assert_eq!(
+            scheduler
+                .schedule(OverlapScores::default(), 100)
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_selection_distribution() {
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.9,
+                waiting: 0,
+            },
+        ]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        // Without the waiting term worker 1's predicted queue never outweighs worker 2's usage
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                waiting_requests_weight: 0.0,
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+        let mut distribution_rx = scheduler.selection_distribution();
+
+        // Three requests on worker 1, then one on worker 2 while worker 1 drains
+        let overlap = OverlapScores::default();
+        for _ in 0..3 {
+            assert_eq!(scheduler.schedule(overlap.clone(), 100).await.unwrap(), 1);
+        }
+        scheduler.set_worker_draining(1, true);
+        assert_eq!(scheduler.schedule(overlap, 100).await.unwrap(), 2);
+
+        let expected = HashMap::from([(WorkerId(1), 3), (WorkerId(2), 1)]);
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while *distribution_rx.borrow_and_update() != expected {
+                distribution_rx.changed().await.unwrap();
+            }
+        })
+        .await
+        .expect("Distribution should be published");
+    }
+
+    #[tokio::test]
+    async fn test_worker_stats() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 2,
+        }]);
+        let (endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        async fn wait_for_stats(
+            scheduler: &KvScheduler,
+            predicate: impl Fn(&WorkerStats) -> bool,
+        ) -> WorkerStats {
+            tokio::time::timeout(Duration::from_secs(1), async {
+                loop {
+                    if let Some(stats) = scheduler.worker_stats().get(&1) {
+                        if predicate(stats) {
+                            return stats.clone();
+                        }
+                    }
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("Worker stats should be published")
+        }
+
+        // 100 tokens with no overlap: 5 new blocks and one more waiting request
+        assert_eq!(
+            scheduler
+                .schedule(OverlapScores::default(), 100)
+                .await
+                .unwrap(),
+            1
+        );
+        let stats = wait_for_stats(&scheduler, |stats| stats.kv_active_blocks_delta != 0).await;
+        assert_eq!(stats.kv_active_blocks_delta, 5);
+        assert_eq!(stats.num_requests_waiting_delta, 1);
+        assert_eq!(stats.metrics.num_requests_waiting, 2);
+
+        // A fresh poll replaces the predictions
+        endpoints_tx.send_modify(|workers| {
+            workers
+                .endpoints
+                .get_mut(&1)
+                .unwrap()
+                .data
+                .num_requests_waiting = 3;
+        });
+        let stats =
+            wait_for_stats(&scheduler, |stats| stats.metrics.num_requests_waiting == 3).await;
+        assert_eq!(stats.kv_active_blocks_delta, 0);
+        assert_eq!(stats.num_requests_waiting_delta, 0);
+    }
+
+    #[test]
+    fn test_select_worker_zero_isl_tokens() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let request = create_request(vec![], 0);
+        let selector = DefaultWorkerSelector::new(None);
+
+        match selector.select_worker(&workers, &request, 20) {
+            Err(KvSchedulerError::InvalidRequest(_)) => {}
+            other => panic!("Should return InvalidRequest, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_isl_tokens() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                max_isl_tokens: Some(1000),
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        match scheduler.schedule(OverlapScores::default(), 0).await {
+            Err(KvSchedulerError::InvalidRequest(_)) => {}
+            other => panic!("Should return InvalidRequest, got {other:?}"),
+        }
+        match scheduler.schedule(OverlapScores::default(), 1001).await {
+            Err(KvSchedulerError::RequestTooLarge {
+                isl_tokens: 1001,
+                max: 1000,
+            }) => {}
+            other => panic!("Should return RequestTooLarge, got {other:?}"),
+        }
+        assert_eq!(
+            scheduler
+                .schedule(OverlapScores::default(), 1000)
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_schedule_blocking() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = runtime
+            .block_on(
+                KvScheduler::builder()
+                    .event_sink(RecordingSink::default())
+                    .block_size(20)
+                    .endpoints(endpoints_rx)
+                    .start(),
+            )
+            .expect("Should start scheduler");
+        let scheduler = Arc::new(scheduler);
+
+        // From a plain thread
+        let worker_id = std::thread::spawn({
+            let scheduler = scheduler.clone();
+            move || scheduler.schedule_blocking(OverlapScores::default(), 100)
+        })
+        .join()
+        .unwrap()
+        .expect("Should schedule");
+        assert_eq!(worker_id, 1);
+
+        // From within the runtime
+        match runtime.block_on(async { scheduler.schedule_blocking(OverlapScores::default(), 100) })
+        {
+            Err(KvSchedulerError::BlockingInAsyncContext) => {}
+            other => panic!("Should return BlockingInAsyncContext, got {other:?}"),
+        }
+    }
+
+    fn create_role_pool() -> ProcessedEndpoints {
+        // 1: idle prefill, 2: busy unified, 3: idle decode
+        let mut workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.5,
+                waiting: 2,
+            },
+            WorkerInfo {
+                id: 3,
+                usage: 0.0,
+                waiting: 0,
+            },
+        ]);
+        workers.endpoints.get_mut(&1).unwrap().data.role = WorkerRole::Prefill;
+        workers.endpoints.get_mut(&3).unwrap().data.role = WorkerRole::Decode;
+        workers
+    }
+
+    #[test]
+    fn test_select_worker_prefill_role() {
+        let workers = create_role_pool();
+        let selector = DefaultWorkerSelector::new(None);
+
+        // Full overlap on the decode worker is ignored since it can't prefill
+        let mut request = create_request(
+            vec![WorkerOverlap {
+                worker_id: 3,
+                overlap_blocks: 5.0,
+            }],
+            100,
+        );
+        request.role = WorkerRole::Prefill;
+        let result = selector.select_worker(&workers, &request, 20).unwrap();
+        assert_eq!(result.worker_id, 1);
+
+        // Overlap still draws prefill requests to unified workers
+        request.overlap.scores = HashMap::from([(WorkerId(2), 5.0)]);
+        let result = selector.select_worker(&workers, &request, 20).unwrap();
+        assert_eq!(result.worker_id, 2);
+        assert_eq!(result.overlap_blocks, 5.0);
+    }
+
+    #[test]
+    fn test_select_worker_decode_role() {
+        let mut workers = create_role_pool();
+        let selector = DefaultWorkerSelector::new(None);
+
+        // Overlap doesn't count for decode; the idle decode worker wins over the busy unified one
+        let mut request = create_request(
+            vec![WorkerOverlap {
+                worker_id: 2,
+                overlap_blocks: 5.0,
+            }],
+            100,
+        );
+        request.role = WorkerRole::Decode;
+        let result = selector.select_worker(&workers, &request, 20).unwrap();
+        assert_eq!(result.worker_id, 3);
+        assert_eq!(result.overlap_blocks, 0.0);
+
+        // Without decode workers only the unified one is left
+        workers.endpoints.remove(&3);
+        let result = selector.select_worker(&workers, &request, 20).unwrap();
+        assert_eq!(result.worker_id, 2);
+    }
+
+    #[test]
+    fn test_select_worker_unified_role() {
+        let mut workers = create_role_pool();
+        let selector = DefaultWorkerSelector::new(None);
+
+        // Disaggregated workers can't serve a request end to end
+        let request = create_request(vec![], 100);
+        let result = selector.select_worker(&workers, &request, 20).unwrap();
+        assert_eq!(result.worker_id, 2);
+
+        workers.endpoints.remove(&2);
+        match selector.select_worker(&workers, &request, 20) {
+            Err(KvSchedulerError::NoEndpoints) => {}
+            other => panic!("Should return NoEndpoints, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_deterministic_tie_break() {
+        let workers = create_workers(
+            (1..=4)
+                .map(|id| WorkerInfo {
+                    id,
+                    usage: 0.0,
+                    waiting: 0,
+                })
+                .collect(),
+        );
+        let request = create_request(vec![], 100);
+        let selector = DefaultWorkerSelector::new(Some(KvRouterConfig {
+            deterministic: true,
+            ..Default::default()
+        }));
+
+        for _ in 0..50 {
+            let result = selector.select_worker(&workers, &request, 20).unwrap();
+            assert_eq!(result.worker_id, 1);
+        }
+    }
+
+    #[test]
+    fn test_candidate_order_independent_of_map_order() {
+        // Each snapshot gets its own hasher, so its workers iterate in a different order
+        let snapshot = |ids: Vec<i64>| {
+            create_workers(
+                ids.into_iter()
+                    .map(|id| WorkerInfo {
+                        id,
+                        usage: 0.0,
+                        waiting: 0,
+                    })
+                    .collect(),
+            )
+        };
+        let request = create_request(vec![], 100);
+        let picks = |workers: &ProcessedEndpoints| -> Vec<WorkerId> {
+            let selector = DefaultWorkerSelector::with_seed(None, 42);
+            (0..20)
+                .map(|_| {
+                    selector
+                        .select_worker(workers, &request, 20)
+                        .unwrap()
+                        .worker_id
+                })
+                .collect()
+        };
+
+        let expected = picks(&snapshot((1..=32).collect()));
+        for i in 0..10 {
+            let workers = if i % 2 == 0 {
+                snapshot((1..=32).rev().collect())
+            } else {
+                snapshot((1..=32).collect())
+            };
+            assert_eq!(picks(&workers), expected);
+
+            let (_, explanation) = DefaultWorkerSelector::new(Some(KvRouterConfig {
+                deterministic: true,
+                ..Default::default()
+            }))
+            .explain_selection(&workers, &request, 20)
+            .unwrap();
+            let ids: Vec<WorkerId> = explanation.candidates.iter().map(|c| c.worker_id).collect();
+            assert_eq!(ids, (1..=32).map(WorkerId).collect::<Vec<_>>());
+            assert_eq!(explanation.tied, ids);
+        }
+    }
+
+    #[test]
+    fn test_tie_break_policies() {
+        // Equal logits once waiting requests are weighted out, but unequal loads
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 2,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.0,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 3,
+                usage: 0.0,
+                waiting: 1,
+            },
+        ]);
+        let request = create_request(vec![], 100);
+        let selector = |tie_break_policy| {
+            DefaultWorkerSelector::with_seed(
+                Some(KvRouterConfig {
+                    waiting_requests_weight: 0.0,
+                    tie_break_policy,
+                    ..Default::default()
+                }),
+                11,
+            )
+        };
+        let picks = |selector: &DefaultWorkerSelector, count: usize| -> Vec<WorkerId> {
+            (0..count)
+                .map(|_| {
+                    selector
+                        .select_worker(&workers, &request, 20)
+                        .unwrap()
+                        .worker_id
+                })
+                .collect()
+        };
+
+        let random = picks(&selector(TieBreakPolicy::Random), 300);
+        assert_eq!(
+            random.iter().copied().collect::<HashSet<_>>(),
+            HashSet::from([WorkerId(1), WorkerId(2), WorkerId(3)])
+        );
+        assert_eq!(picks(&selector(TieBreakPolicy::LowestId), 10), vec![1; 10]);
+        assert_eq!(
+            picks(&selector(TieBreakPolicy::LeastLoaded), 10),
+            vec![2; 10]
+        );
+        assert_eq!(
+            picks(&selector(TieBreakPolicy::RoundRobin), 7),
+            vec![1, 2, 3, 1, 2, 3, 1]
+        );
+
+        // Round robin resumes after the last pick when the tied set changes
+        let round_robin = selector(TieBreakPolicy::RoundRobin);
+        assert_eq!(picks(&round_robin, 2), vec![1, 2]);
+        let mut without_3 = workers.clone();
+        without_3.endpoints.remove(&3);
+        let (selection, explanation) = round_robin
+            .explain_selection(&without_3, &request, 20)
+            .unwrap();
+        assert_eq!(selection.worker_id, 1);
+        assert!(!explanation.random_tie_break);
+
+        // deterministic still means lowest id, whatever the policy
+        for policy in [
+            TieBreakPolicy::Random,
+            TieBreakPolicy::LowestId,
+            TieBreakPolicy::LeastLoaded,
+            TieBreakPolicy::RoundRobin,
+        ] {
+            let deterministic = DefaultWorkerSelector::new(Some(KvRouterConfig {
+                waiting_requests_weight: 0.0,
+                tie_break_policy: policy,
+                deterministic: true,
+                ..Default::default()
+            }));
+            assert_eq!(picks(&deterministic, 3), vec![1; 3], "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn test_gpu_cache_usage_curves() {
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.5,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.95,
+                waiting: 0,
+            },
+        ]);
+        let request = create_request(vec![], 100);
+        // Usage contribution of each worker and their difference, under `curve`
+        let penalties = |curve| {
+            let selector = DefaultWorkerSelector::new(Some(KvRouterConfig {
+                gpu_cache_usage_curve: curve,
+                ..Default::default()
+            }));
+            let (_, explanation) = selector.explain_selection(&workers, &request, 20).unwrap();
+            let penalty = |worker_id| {
+                let candidate = explanation
+                    .candidates
+                    .iter()
+                    .find(|c| c.worker_id == worker_id)
+                    .unwrap();
+                -candidate.contributions.gpu_cache_usage
+            };
+            (penalty(1), penalty(2))
+        };
+
+        // Linear is today's penalty, the usage itself
+        let (half, full) = penalties(UsagePenaltyCurve::Linear);
+        assert_eq!(half, 0.5f32 as f64);
+        assert_eq!(full, 0.95f32 as f64);
+
+        let (quadratic_half, quadratic_full) = penalties(UsagePenaltyCurve::Quadratic);
+        assert!((quadratic_half - 0.25).abs() < 1e-6);
+        assert!((quadratic_full - 0.9025).abs() < 1e-6);
+
+        let (exponential_half, exponential_full) = penalties(UsagePenaltyCurve::Exponential);
+        assert!((exponential_half - 0.0759).abs() < 1e-3);
+        assert!((exponential_full - 0.7779).abs() < 1e-3);
+
+        // The steeper the curve, the more the fuller worker is penalized relative to the other
+        let linear_ratio = full / half;
+        let quadratic_ratio = quadratic_full / quadratic_half;
+        let exponential_ratio = exponential_full / exponential_half;
+        assert!(linear_ratio < quadratic_ratio && quadratic_ratio < exponential_ratio);
+        for curve in [
+            UsagePenaltyCurve::Linear,
+            UsagePenaltyCurve::Quadratic,
+            UsagePenaltyCurve::Exponential,
+        ] {
+            assert_eq!(curve.apply(0.0), 0.0);
+            assert!((curve.apply(1.0) - 1.0).abs() < 1e-12);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_predictive_waiting_delta() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 2,
+        }]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                max_predictive_waiting_delta: Some(3),
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        for _ in 0..20 {
+            assert_eq!(
+                scheduler
+                    .schedule(OverlapScores::default(), 100)
+                    .await
+                    .unwrap(),
+                1
+            );
+        }
+        // The burst raises the waiting count to at most 3 above the polled 2
+        let stats = tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                if let Some(stats) = scheduler.worker_stats().get(&1) {
+                    if stats.kv_active_blocks_delta == 100 {
+                        return stats.clone();
+                    }
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Worker stats should be published");
+        assert_eq!(stats.num_requests_waiting_delta, 3);
+        assert_eq!(stats.metrics.num_requests_waiting, 2);
+    }
+
+    #[tokio::test]
+    async fn test_logit_histogram() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                waiting_requests_weight: 0.0,
+                ..Default::default()
+            })
+            .logit_histogram_buckets(vec![1.0, 0.0])
+            .start()
+            .await
+            .expect("Should start scheduler");
+        assert_eq!(
+            scheduler.logit_histogram(),
+            vec![(0.0, 0), (1.0, 0), (f64::INFINITY, 0)]
+        );
+
+        // Logits of 2.0 * the cached fraction of the 5 blocks: 0.0, 0.0, 0.8 and 2.0
+        for overlap_blocks in [0, 0, 2, 5] {
+            let overlap =
+                OverlapScores::from_block_counts(HashMap::from([(WorkerId(1), overlap_blocks)]));
+            scheduler.schedule(overlap, 100).await.unwrap();
+        }
+        assert_eq!(
+            scheduler.logit_histogram(),
+            vec![(0.0, 2), (1.0, 1), (f64::INFINITY, 1)]
+        );
+
+        let invalid = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(tokio::sync::watch::channel(ProcessedEndpoints::default()).1)
+            .logit_histogram_buckets(vec![f64::NAN])
+            .start()
+            .await;
+        assert!(matches!(invalid, Err(KvSchedulerError::InvalidConfig(_))));
+    }
+
+    struct FailingSink;
+
+    #[async_trait]
+    impl EventSink for FailingSink {
+        async fn publish(&self, _event: &SchedulerEvent) -> anyhow::Result<()> {
+            anyhow::bail!("sink unavailable")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_event_sinks_fan_out() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let (first, second) = (RecordingSink::default(), RecordingSink::default());
+        let scheduler = KvScheduler::builder()
+            .event_sinks(vec![
+                Box::new(first.clone()),
+                Box::new(FailingSink),
+                Box::new(second.clone()),
+            ])
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        for _ in 0..3 {
+            scheduler
+                .schedule(OverlapScores::default(), 100)
+                .await
+                .unwrap();
+        }
+        // The failing sink neither stops the others nor the events after it
+        for sink in [&first, &second] {
+            let events = sink.wait_for_events(3).await;
+            assert_eq!(events.len(), 3);
+            assert!(events
+                .iter()
+                .all(|event| matches!(event, SchedulerEvent::HitRate(_))));
+        }
+
+        let fan_out = FanOutEventSink::new(vec![Box::new(FailingSink), Box::new(first.clone())]);
+        let event = SchedulerEvent::WorkerDrained(WorkerDrainedEvent {
+            worker_id: WorkerId(1),
+        });
+        let error = fan_out.publish(&event).await.unwrap_err();
+        assert_eq!(error.to_string(), "1 of 2 event sinks failed");
+        assert_eq!(first.events.lock().unwrap().len(), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_tenant_rate_limit() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let sink = RecordingSink::default();
+        let limit = TenantRateLimit {
+            requests_per_second: 2.0,
+            burst: 2,
+        };
+        let scheduler = KvScheduler::builder()
+            .event_sink(sink.clone())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                tenant_rate_limits: HashMap::from([("a".to_string(), limit)]),
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+        let schedule = |tenant: &str| {
+            let options = ScheduleOptions {
+                tenant_id: Some(tenant.to_string()),
+                ..Default::default()
+            };
+            scheduler.schedule_with_options(OverlapScores::default(), 100, options)
+        };
+
+        // The burst is admitted, the request after it isn't
+        assert!(schedule("a").await.is_ok());
+        assert!(schedule("a").await.is_ok());
+        assert!(matches!(
+            schedule("a").await,
+            Err(KvSchedulerError::RateLimited { tenant_id }) if tenant_id == "a"
+        ));
+        // Tenants without a limit are unrestricted
+        for _ in 0..5 {
+            assert!(schedule("b").await.is_ok());
+        }
+
+        // The bucket refills at two requests per second
+        tokio::time::advance(Duration::from_millis(500)).await;
+        assert!(schedule("a").await.is_ok());
+        assert!(schedule("a").await.is_err());
+
+        let events = sink.wait_for_events(10).await;
+        let rejected = events
+            .iter()
+            .filter(|event| {
+                matches!(
+                    event,
+                    SchedulerEvent::SchedulingRejected(SchedulingRejectedEvent {
+                        reason: RejectionReason::RateLimited,
+                        ..
+                    })
+                )
+            })
+            .count();
+        assert_eq!(rejected, 2);
+
+        let invalid = KvRouterConfig {
+            tenant_rate_limits: HashMap::from([(
+                "a".to_string(),
+                TenantRateLimit {
+                    requests_per_second: 0.0,
+                    burst: 1,
+                },
+            )]),
+            ..Default::default()
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_min_blocks_per_request() {
+        let mut workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.1,
+                waiting: 0,
+            },
+        ]);
+        let selector = DefaultWorkerSelector::new(Some(KvRouterConfig {
+            min_blocks_per_request: 4,
+            waiting_requests_weight: 0.0,
+            ..Default::default()
+        }));
+        let (event_tx, _event_rx) = EventSender::channel(DEFAULT_EVENT_CHANNEL_CAPACITY);
+
+        // 30 tokens is two blocks, charged as four: worker 2's cached block is a quarter of it
+        // Worker1: 0.0
+        // Worker2: 2.0 * 0.25 - 1.0 * 0.1 = 0.4
+        let request = create_request(
+            vec![WorkerOverlap {
+                worker_id: 2,
+                overlap_blocks: 1.0,
+            }],
+            30,
+        );
+        let selection = selector
+            .select_worker(&workers, &request, 20)
+            .expect("Should select a worker");
+        assert_eq!(selection.worker_id, 2);
+        assert_eq!(selection.required_blocks, 4);
+        assert!((selection.logit - 0.4).abs() < 1e-6);
+
+        // Only the uncached blocks are added predictively
+        let kv_active_blocks = workers.endpoints[&2].data.kv_active_blocks;
+        process_worker_selection(&mut workers, selection, &event_tx, None).unwrap();
+        assert_eq!(
+            workers.endpoints[&2].data.kv_active_blocks,
+            kv_active_blocks + 3
+        );
+
+        assert!(KvRouterConfig {
+            min_blocks_per_request: 0,
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_stale_overlap_references() {
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.5,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.0,
+                waiting: 0,
+            },
+        ]);
+        let selector = DefaultWorkerSelector::new(None);
+        // Worker 3 has left since the prefix was indexed
+        let request = create_request(
+            vec![
+                WorkerOverlap {
+                    worker_id: 1,
+                    overlap_blocks: 5.0,
+                },
+                WorkerOverlap {
+                    worker_id: 3,
+                    overlap_blocks: 5.0,
+                },
+            ],
+            100,
+        );
+
+        let result = selector
+            .select_worker(&workers, &request, 20)
+            .expect("Should select a worker");
+        assert_eq!(result.worker_id, 1);
+        assert_eq!(selector.stale_overlap_references(), 1);
+
+        selector
+            .select_worker(&workers, &create_request(vec![], 100), 20)
+            .expect("Should select a worker");
+        assert_eq!(selector.stale_overlap_references(), 1);
+        selector
+            .select_worker(&workers, &request, 20)
+            .expect("Should select a worker");
+        assert_eq!(selector.stale_overlap_references(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_first_worker() {
+        let (endpoints_tx, endpoints_rx) =
+            tokio::sync::watch::channel(ProcessedEndpoints::default());
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                wait_for_first_worker: true,
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        let scheduled = scheduler.schedule(OverlapScores::default(), 100);
+        tokio::pin!(scheduled);
+        // Held rather than rejected while the pool is empty
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), &mut scheduled)
+                .await
+                .is_err()
+        );
+
+        endpoints_tx
+            .send(create_workers(vec![WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 0,
+            }]))
+            .unwrap();
+        let worker_id = tokio::time::timeout(Duration::from_secs(1), scheduled)
+            .await
+            .expect("Should route once a worker registers")
+            .unwrap();
+        assert_eq!(worker_id, 1);
+
+        // Without the flag an empty pool still fails fast
+        let (_endpoints_tx, endpoints_rx) =
+            tokio::sync::watch::channel(ProcessedEndpoints::default());
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .start()
+            .await
+            .expect("Should start scheduler");
+        assert!(matches!(
+            scheduler.schedule(OverlapScores::default(), 100).await,
+            Err(KvSchedulerError::NoEndpoints)
+        ));
+    }
+
+    #[test]
+    fn test_waiting_percentile() {
+        let poll = |spiky: u64| {
+            create_workers(vec![
+                WorkerInfo {
+                    id: 1,
+                    usage: 0.0,
+                    waiting: spiky,
+                },
+                WorkerInfo {
+                    id: 2,
+                    usage: 0.0,
+                    waiting: 4,
+                },
+            ])
+        };
+        // Worker 1 spikes on the last of ten polls; worker 2 holds steady
+        let mut samples = WaitingSamples::new(10);
+        for i in 0..10 {
+            samples.observe(&poll(if i == 9 { 20 } else { 1 }));
+        }
+        let p90 = samples.percentiles(0.9);
+        assert_eq!(p90[&1], 1.0);
+        assert_eq!(p90[&2], 4.0);
+        assert_eq!(samples.percentiles(1.0)[&1], 20.0);
+
+        // On the raw counts the spike sends the request to worker 2
+        // Worker1: -1.0 * 20 / 20 = -1.0
+        // Worker2: -1.0 * 4 / 20 = -0.2
+        let selector = DefaultWorkerSelector::new(None);
+        let request = create_request(vec![], 100);
+        let mut workers = poll(20);
+        let result = selector
+            .select_worker(&workers, &request, 20)
+            .expect("Should select a worker");
+        assert_eq!(result.worker_id, 2);
+
+        // Smoothed, worker 1 is the quieter one
+        // Worker1: -1.0 * 1 / 4 = -0.25
+        // Worker2: -1.0 * 4 / 4 = -1.0
+        workers.waiting_percentile = p90;
+        let result = selector
+            .select_worker(&workers, &request, 20)
+            .expect("Should select a worker");
+        assert_eq!(result.worker_id, 1);
+
+        // Predictive updates raise the smoothed count too
+        let (event_tx, _event_rx) = EventSender::channel(DEFAULT_EVENT_CHANNEL_CAPACITY);
+        process_worker_selection(&mut workers, result, &event_tx, None).unwrap();
+        assert_eq!(workers.waiting_percentile[&1], 2.0);
+
+        // A worker that leaves starts afresh on return
+        samples.observe(&create_workers(vec![WorkerInfo {
+            id: 2,
+            usage: 0.0,
+            waiting: 4,
+        }]));
+        samples.observe(&poll(20));
+        assert_eq!(samples.percentiles(0.9)[&1], 20.0);
+    }
+
+    #[tokio::test]
+    async fn test_busy_request_does_not_block_queue() {
+        let pool = |prefill_usage| {
+            let mut workers = create_workers(vec![
+                WorkerInfo {
+                    id: 1,
+                    usage: prefill_usage,
+                    waiting: 0,
+                },
+                WorkerInfo {
+                    id: 2,
+                    usage: 0.0,
+                    waiting: 0,
+                },
+            ]);
+            workers.endpoints.get_mut(&1).unwrap().data.role = WorkerRole::Prefill;
+            workers.endpoints.get_mut(&2).unwrap().data.role = WorkerRole::Decode;
+            workers
+        };
+        let (endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(pool(1.0));
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                gpu_cache_usage_hard_cap: 0.9,
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+        let schedule = |overlap, role| {
+            scheduler.schedule_with_options(
+                overlap,
+                100,
+                ScheduleOptions {
+                    role,
+                    ..Default::default()
+                },
+            )
+        };
+
+        // The only prefill worker, holding the prefix, is full
+        let prefill = schedule(
+            create_request(
+                vec![WorkerOverlap {
+                    worker_id: 1,
+                    overlap_blocks: 5.0,
+                }],
+                100,
+            )
+            .overlap,
+            WorkerRole::Prefill,
+        );
+        tokio::pin!(prefill);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), &mut prefill)
+                .await
+                .is_err()
+        );
+
+        // A later request with a free worker is served while the earlier one waits
+        let decode = tokio::time::timeout(
+            Duration::from_secs(1),
+            schedule(OverlapScores::default(), WorkerRole::Decode),
+        )
+        .await
+        .expect("Should not wait behind the busy request");
+        assert_eq!(decode.unwrap(), 2);
+        assert_eq!(scheduler.load(), 2);
+
+        endpoints_tx.send(pool(0.0)).unwrap();
+        let prefill = tokio::time::timeout(Duration::from_secs(1), prefill)
+            .await
+            .expect("Should route once the prefill worker frees up");
+        assert_eq!(prefill.unwrap(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_no_endpoints_retries() {
+        let (endpoints_tx, endpoints_rx) =
+            tokio::sync::watch::channel(ProcessedEndpoints::default());
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                max_no_endpoints_retries: 3,
+                no_endpoints_retry_backoff_ms: 10,
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        // A pool that stays empty fails once the retries, 10 + 20 + 40ms apart, run out
+        let started = tokio::time::Instant::now();
+        assert!(matches!(
+            scheduler.schedule(OverlapScores::default(), 100).await,
+            Err(KvSchedulerError::NoEndpoints)
+        ));
+        assert!(started.elapsed() >= Duration::from_millis(70));
+
+        // A worker registering between attempts takes the request
+        let (scheduled, _) =
+            tokio::join!(scheduler.schedule(OverlapScores::default(), 100), async {
+                tokio::time::sleep(Duration::from_millis(15)).await;
+                endpoints_tx
+                    .send(create_workers(vec![WorkerInfo {
+                        id: 1,
+                        usage: 0.0,
+                        waiting: 0,
+                    }]))
+                    .unwrap();
+            });
+        assert_eq!(scheduled.unwrap(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_no_endpoints_backoff_does_not_block_queue() {
+        let mut workers = create_workers(vec![WorkerInfo {
+            id: 2,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        workers.endpoints.get_mut(&2).unwrap().data.role = WorkerRole::Decode;
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                max_no_endpoints_retries: 2,
+                no_endpoints_retry_backoff_ms: 1_000,
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+        let schedule = |role| {
+            scheduler.schedule_with_options(
+                OverlapScores::default(),
+                100,
+                ScheduleOptions {
+                    role,
+                    ..Default::default()
+                },
+            )
+        };
+
+        // No worker serves prefill, so that request backs off for a second, then two
+        let started = Instant::now();
+        let prefill = schedule(WorkerRole::Prefill);
+        tokio::pin!(prefill);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), &mut prefill)
+                .await
+                .is_err()
+        );
+
+        // A request the decode worker can serve doesn't wait out the backoff
+        let decode = tokio::time::timeout(Duration::from_millis(10), schedule(WorkerRole::Decode))
+            .await
+            .expect("Should not wait behind the backing off request");
+        assert_eq!(decode.unwrap(), 2);
+
+        assert!(matches!(prefill.await, Err(KvSchedulerError::NoEndpoints)));
+        assert!(started.elapsed() >= Duration::from_secs(3));
+    }
+
+    #[tokio::test]
+    async fn test_locality_weight() {
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.2,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.2,
+                waiting: 0,
+            },
+        ]);
+        let topology = Topology {
+            worker_zones: HashMap::from([
+                (WorkerId(1), "rack-a".to_string()),
+                (WorkerId(2), "rack-b".to_string()),
+            ]),
+            distances: HashMap::from([
+                (
+                    "rack-a".to_string(),
+                    HashMap::from([("rack-b".to_string(), 1.0)]),
+                ),
+                (
+                    "rack-b".to_string(),
+                    HashMap::from([("rack-a".to_string(), 1.0)]),
+                ),
+            ]),
+        };
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                locality_weight: 0.5,
+                // Keep predictive load from evening out the choice
+                waiting_requests_weight: 0.0,
+                ..Default::default()
+            })
+            .topology(topology)
+            .start()
+            .await
+            .expect("Should start scheduler");
+        let schedule = |zone: &str| {
+            scheduler.schedule_with_options(
+                OverlapScores::default(),
+                100,
+                ScheduleOptions {
+                    zone: Some(zone.to_string()),
+                    ..Default::default()
+                },
+            )
+        };
+
+        // Equal loads, so the worker in the request's own zone wins
+        // Nearer: -1.0 * 0.2 = -0.2
+        // Farther: -1.0 * 0.2 - 0.5 * 1.0 = -0.7
+        for _ in 0..3 {
+            assert_eq!(schedule("rack-a").await.unwrap(), 1);
+            assert_eq!(schedule("rack-b").await.unwrap(), 2);
+        }
+
+        // Requests without a zone aren't penalized
+        let (_, explanation) = scheduler
+            .schedule_explained(OverlapScores::default(), 100)
+            .await
+            .expect("Should explain selection");
+        assert!(explanation
+            .candidates
+            .iter()
+            .all(|candidate| candidate.contributions.locality == 0.0));
+    }
+
+    #[test]
+    fn test_into_deterministic_replay() {
+        let workers = create_workers(
+            (1..=4)
+                .map(|id| WorkerInfo {
+                    id,
+                    usage: 0.0,
+                    waiting: 0,
+                })
+                .collect(),
+        );
+        // Captured input: every request ties all four workers, or a near tie of two
+        let requests: Vec<SchedulingRequest> = (0..20)
+            .map(|i| {
+                if i % 3 == 0 {
+                    create_request(
+                        vec![
+                            WorkerOverlap {
+                                worker_id: 2,
+                                overlap_blocks: 2.0,
+                            },
+                            WorkerOverlap {
+                                worker_id: 3,
+                                overlap_blocks: 1.9,
+                            },
+                        ],
+                        100,
+                    )
+                } else {
+                    create_request(vec![], 100)
+                }
+            })
+            .collect();
+        let config = KvRouterConfig {
+            tie_break_epsilon: 0.1,
+            ..Default::default()
+        };
+
+        let replay = |seed| {
+            let selector =
+                DefaultWorkerSelector::new(Some(config.clone())).into_deterministic(seed);
+            let selections: Vec<WorkerId> = requests
+                .iter()
+                .map(|request| {
+                    selector
+                        .select_worker(&workers, request, 20)
+                        .expect("Should select a worker")
+                        .worker_id
+                })
+                .collect();
+            (selections, selector.tie_breaks())
+        };
+        let (selections, tie_breaks) = replay(7);
+        assert_eq!(replay(7), (selections.clone(), tie_breaks.clone()));
+
+        // Every request was a random tie break, recorded in order
+        assert_eq!(tie_breaks.len(), requests.len());
+        for (i, (worker_id, index)) in selections.iter().zip(&tie_breaks).enumerate() {
+            let first_tied = if i % 3 == 0 { 2 } else { 1 };
+            assert_eq!(*worker_id, first_tied + *index as i64);
+        }
+
+        // Selectors that aren't replaying record nothing
+        let selector = DefaultWorkerSelector::with_seed(Some(config), 7);
+        selector
+            .select_worker(&workers, &requests[1], 20)
+            .expect("Should select a worker");
+        assert!(selector.tie_breaks().is_empty());
+    }
+
+    /// Records events, taking `delay` over each.
+    struct SlowSink {
+        inner: RecordingSink,
+        delay: Option<Duration>,
+    }
+
+    #[async_trait]
+    impl EventSink for SlowSink {
+        async fn publish(&self, event: &SchedulerEvent) -> anyhow::Result<()> {
+            match self.delay {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => std::future::pending().await,
+            }
+            self.inner.publish(event).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flush_events() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let start = |delay| {
+            let (endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers.clone());
+            let sink = RecordingSink::default();
+            let scheduler = KvScheduler::builder()
+                .event_sink(SlowSink {
+                    inner: sink.clone(),
+                    delay,
+                })
+                .block_size(20)
+                .endpoints(endpoints_rx)
+                .start();
+            async move {
+                let scheduler = scheduler.await.expect("Should start scheduler");
+                (scheduler, sink, endpoints_tx)
+            }
+        };
+
+        let (scheduler, sink, _endpoints_tx) = start(Some(Duration::from_millis(5))).await;
+        for _ in 0..10 {
+            scheduler
+                .schedule(OverlapScores::default(), 100)
+                .await
+                .unwrap();
+        }
+        // Each hit rate event takes the sink 5ms, so most are still queued
+        scheduler
+            .flush_events(Duration::from_secs(1))
+            .await
+            .expect("Should flush events");
+        assert_eq!(sink.events.lock().unwrap().len(), 10);
+        scheduler.shutdown().await.expect("Should shut down");
+
+        // A stuck sink can't hold up the flush past its timeout
+        let (scheduler, _sink, _endpoints_tx) = start(None).await;
+        scheduler
+            .schedule(OverlapScores::default(), 100)
+            .await
+            .unwrap();
+        let flushed = scheduler.flush_events(Duration::from_millis(50)).await;
+        assert!(matches!(
+            flushed,
+            Err(KvSchedulerError::FlushTimeout(timeout)) if timeout == Duration::from_millis(50)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_schedules_queued_requests() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let sink = RecordingSink::default();
+        let scheduler = Arc::new(
+            KvScheduler::builder()
+                .event_sink(SlowSink {
+                    inner: sink.clone(),
+                    delay: Some(Duration::from_millis(5)),
+                })
+                .block_size(20)
+                .endpoints(endpoints_rx)
+                .start()
+                .await
+                .expect("Should start scheduler"),
+        );
+
+        // Queue the requests, leaving the task no turn to schedule them before the shutdown
+        let requests: Vec<_> = (0..5)
+            .map(|_| {
+                let scheduler = scheduler.clone();
+                tokio::spawn(async move { scheduler.schedule(OverlapScores::default(), 100).await })
+            })
+            .collect();
+        tokio::task::yield_now().await;
+        scheduler.shutdown().await.expect("Should shut down");
+
+        for request in requests {
+            assert_eq!(request.await.unwrap().unwrap(), 1);
+        }
+        let hit_rates = sink
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| matches!(event, SchedulerEvent::HitRate(_)))
+            .count();
+        assert_eq!(hit_rates, 5);
+
+        // The scheduler stays stopped for the handles still on it
+        let result = scheduler.schedule(OverlapScores::default(), 100).await;
+        assert!(matches!(result, Err(KvSchedulerError::SchedulerDead)));
+    }
+
+    #[test]
+    fn test_expected_output_tokens() {
+        let selector = DefaultWorkerSelector::new(None);
+        let (event_tx, mut event_rx) = EventSender::channel(DEFAULT_EVENT_CHANNEL_CAPACITY);
+        // Blocks added to the selected worker's predicted load for a 100 token prompt
+        let mut predicted_growth = |expected_output_tokens| {
+            let mut workers = create_workers(vec![WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 0,
+            }]);
+            let mut request = create_request(vec![], 100);
+            request.expected_output_tokens = expected_output_tokens;
+            let selection = selector
+                .select_worker(&workers, &request, 20)
+                .expect("Should select a worker");
+            process_worker_selection(&mut workers, selection, &event_tx, None).unwrap();
+            // The hit rate still only counts the prompt
+            match event_rx.try_recv() {
+                Ok(SchedulerEvent::HitRate(event)) => assert_eq!(event.isl_blocks, 5),
+                other => panic!("Should emit a hit rate event, got {other:?}"),
+            }
+            workers.endpoints[&1].data.kv_active_blocks
+        };
+
+        assert_eq!(predicted_growth(None), 5);
+        // The prompt fills its 5 blocks, so 10 more tokens start a sixth
+        assert_eq!(predicted_growth(Some(10)), 6);
+        // 500 tokens take 25 blocks by the end of decode
+        assert_eq!(predicted_growth(Some(400)), 25);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rejection_rate() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .start()
+            .await
+            .expect("Should start scheduler");
+        assert_eq!(scheduler.rejection_rate(Duration::from_secs(1)), 0.0);
+
+        for _ in 0..3 {
+            scheduler
+                .schedule(OverlapScores::default(), 100)
+                .await
+                .unwrap();
+        }
+        let invalid = scheduler.schedule(OverlapScores::default(), 0).await;
+        assert!(matches!(invalid, Err(KvSchedulerError::InvalidRequest(_))));
+        assert_eq!(scheduler.rejection_rate(Duration::from_secs(1)), 0.25);
+
+        tokio::time::advance(Duration::from_secs(2)).await;
+        let invalid = scheduler.schedule(OverlapScores::default(), 0).await;
+        assert!(matches!(invalid, Err(KvSchedulerError::InvalidRequest(_))));
+        // Only the latest failure is in the short window; the long one sees all five requests
+        assert_eq!(scheduler.rejection_rate(Duration::from_secs(1)), 1.0);
+        assert_eq!(scheduler.rejection_rate(Duration::from_secs(10)), 0.4);
+    }
+
+    #[test]
+    fn test_max_concurrent_ceiling() {
+        let selector = DefaultWorkerSelector::new(None);
+        let mut workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.5,
+                waiting: 0,
+            },
+        ]);
+        for (worker_id, active) in [(1, 4), (2, 3)] {
+            let data = &mut workers.endpoints.get_mut(&worker_id).unwrap().data;
+            data.request_active_slots = active;
+            data.max_concurrent = Some(4);
+        }
+        let request = create_request(
+            vec![WorkerOverlap {
+                worker_id: 1,
+                overlap_blocks: 5.0,
+            }],
+            100,
+        );
+
+        // Worker 1 wins on overlap and usage, but it is at its ceiling
+        let selection = selector.select_worker(&workers, &request, 20).unwrap();
+        assert_eq!(selection.worker_id, 2);
+
+        // The predictive update takes worker 2 to its ceiling as well
+        let (event_tx, _event_rx) = EventSender::channel(DEFAULT_EVENT_CHANNEL_CAPACITY);
+        process_worker_selection(&mut workers, selection, &event_tx, None).unwrap();
+        assert!(matches!(
+            selector.select_worker(&workers, &request, 20),
+            Err(KvSchedulerError::AllWorkersBusy)
+        ));
+
+        // Workers without a ceiling are never busy on active sequences alone
+        for worker in workers.endpoints.values_mut() {
+            worker.data.max_concurrent = None;
+        }
+        assert_eq!(
+            selector
+                .select_worker(&workers, &request, 20)
+                .unwrap()
+                .worker_id,
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_request_skips_selection() {
+        let (endpoints_tx, endpoints_rx) =
+            tokio::sync::watch::channel(ProcessedEndpoints::default());
+        let sink = RecordingSink::default();
+        let scheduler = KvScheduler::builder()
+            .event_sink(sink.clone())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                wait_for_first_worker: true,
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        // The requester gives up while the request is held for a worker
+        let abandoned = tokio::time::timeout(
+            Duration::from_millis(50),
+            scheduler.schedule(OverlapScores::default(), 100),
+        )
+        .await;
+        assert!(abandoned.is_err());
+
+        endpoints_tx
+            .send(create_workers(vec![WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 0,
+            }]))
+            .unwrap();
+        let worker_id = scheduler
+            .schedule(OverlapScores::default(), 100)
+            .await
+            .unwrap();
+        assert_eq!(worker_id, 1);
+
+        // Only the request still waited on was counted against the worker
+        let state = scheduler.export_state().await.unwrap();
+        assert_eq!(state.predicted[&1].num_requests_waiting_delta, 1);
+        assert_eq!(state.predicted[&1].kv_active_blocks_delta, 5);
+        scheduler
+            .flush_events(Duration::from_secs(1))
+            .await
+            .expect("Should flush events");
+        let hit_rates = sink
+            .events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| matches!(event, SchedulerEvent::HitRate(_)))
+            .count();
+        assert_eq!(hit_rates, 1);
+    }
+
+    #[test]
+    fn test_score_and_select_matches_select_worker() {
+        let config = KvRouterConfig {
+            tie_break_policy: TieBreakPolicy::LowestId,
+            ..Default::default()
+        };
+        let selector = DefaultWorkerSelector::new(Some(config));
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.2,
+                waiting: 3,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.6,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 3,
+                usage: 0.4,
+                waiting: 1,
+            },
+        ]);
+        for (overlaps, isl_tokens) in [
+            (vec![], 100),
+            (vec![(1, 4.0), (3, 2.0)], 100),
+            (vec![(2, 9.0)], 200),
+            (vec![(1, 1.0), (2, 1.0), (3, 1.0)], 40),
+        ] {
+            let request = create_request(
+                overlaps
+                    .into_iter()
+                    .map(|(worker_id, overlap_blocks)| WorkerOverlap {
+                        worker_id,
+                        overlap_blocks,
+                    })
+                    .collect(),
+                isl_tokens,
+            );
+            let expected = selector.select_worker(&workers, &request, 20).unwrap();
+            let selection = selector
+                .score_and_select(&workers, &request.overlap, isl_tokens, 20)
+                .unwrap();
+            assert_eq!(selection, expected);
+        }
+
+        // Errors agree too
+        let empty = ProcessedEndpoints::default();
+        assert!(matches!(
+            selector.score_and_select(&empty, &OverlapScores::default(), 100, 20),
+            Err(KvSchedulerError::NoEndpoints)
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_endpoints_debounce() {
+        let pool = |usage, waiting| {
+            create_workers(vec![WorkerInfo {
+                id: 1,
+                usage,
+                waiting,
+            }])
+        };
+        let start = |debounce_ms| async move {
+            let (endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(pool(0.0, 0));
+            let scheduler = KvScheduler::builder()
+                .event_sink(RecordingSink::default())
+                .block_size(20)
+                .endpoints(endpoints_rx)
+                .config(KvRouterConfig {
+                    endpoints_debounce_ms: debounce_ms,
+                    gpu_cache_usage_hard_cap: 0.9,
+                    ..Default::default()
+                })
+                .start()
+                .await
+                .expect("Should start scheduler");
+            (endpoints_tx, scheduler)
+        };
+        // 50 updates over 500ms
+        let churn = |endpoints_tx: tokio::sync::watch::Sender<ProcessedEndpoints>| async move {
+            for waiting in 0..50 {
+                endpoints_tx.send(pool(0.0, waiting)).unwrap();
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            endpoints_tx
+        };
+
+        let (endpoints_tx, scheduler) = start(None).await;
+        let _endpoints_tx = churn(endpoints_tx).await;
+        assert!(scheduler.endpoint_reads() > 40);
+
+        // One read per debounce interval, plus the initial one and the trailing update
+        let (endpoints_tx, scheduler) = start(Some(100)).await;
+        let endpoints_tx = churn(endpoints_tx).await;
+        let reads = scheduler.endpoint_reads();
+        assert!((2..=7).contains(&reads), "{reads} endpoint reads");
+
+        // A request waiting for capacity sees it as soon as it frees up
+        endpoints_tx.send(pool(1.0, 0)).unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let scheduled = scheduler.schedule(OverlapScores::default(), 100);
+        tokio::pin!(scheduled);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), &mut scheduled)
+                .await
+                .is_err()
+        );
+        let freed_at = Instant::now();
+        endpoints_tx.send(pool(0.0, 0)).unwrap();
+        assert_eq!(scheduled.await.unwrap(), 1);
+        assert!(freed_at.elapsed() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_routing_modes() {
+        // Worker 1 has most of the prefix cached but is busier than worker 2
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.5,
+                waiting: 2,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.1,
+                waiting: 1,
+            },
+        ]);
+        let request = create_request(
+            vec![WorkerOverlap {
+                worker_id: 1,
+                overlap_blocks: 3.0,
+            }],
+            100,
+        );
+        let selected = |config: KvRouterConfig| {
+            DefaultWorkerSelector::new(Some(config))
+                .select_worker(&workers, &request, 20)
+                .unwrap()
+                .worker_id
+        };
+
+        assert_eq!(selected(KvRouterConfig::for_mode(RoutingMode::Balanced)), 1);
+        assert_eq!(
+            selected(KvRouterConfig::for_mode(RoutingMode::ThroughputOptimized)),
+            1
+        );
+        assert_eq!(
+            selected(KvRouterConfig::for_mode(RoutingMode::LatencyOptimized)),
+            2
+        );
+        // Explicit weights override the preset
+        assert_eq!(
+            selected(KvRouterConfig {
+                overlap_score_weight: 10.0,
+                ..KvRouterConfig::for_mode(RoutingMode::LatencyOptimized)
+            }),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oscillation_rate() {
+        // Each poll makes the other worker look idle
+        let pool = |busy: i64| {
+            create_workers(
+                (1..=2)
+                    .map(|id| WorkerInfo {
+                        id,
+                        usage: if id == busy { 0.8 } else { 0.0 },
+                        waiting: 0,
+                    })
+                    .collect(),
+            )
+        };
+        let (endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(pool(1));
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                oscillation_window: 10,
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+        let schedule = |stream_id: Option<&str>| {
+            let options = ScheduleOptions {
+                stream_id: stream_id.map(str::to_string),
+                ..Default::default()
+            };
+            scheduler.schedule_with_options(OverlapScores::default(), 100, options)
+        };
+        assert_eq!(scheduler.oscillation_rate(), 0.0);
+
+        // A steady stream with steady metrics stays put
+        for _ in 0..5 {
+            endpoints_tx.send(pool(1)).unwrap();
+            assert_eq!(schedule(None).await.unwrap(), 2);
+        }
+        assert_eq!(scheduler.oscillation_rate(), 0.0);
+
+        // Alternating metrics ping-pong a second stream between the workers
+        for (i, busy) in [1, 2].repeat(6).into_iter().take(11).enumerate() {
+            endpoints_tx.send(pool(busy)).unwrap();
+            assert_eq!(schedule(Some("ping-pong")).await.unwrap(), 3 - busy);
+            if i == 6 {
+                // Its first request had nothing to switch from, so the window holds the first
+                // stream's four stay-put pairs and six switches
+                let rate = scheduler.oscillation_rate();
+                assert!((rate - 0.6).abs() < 1e-9, "oscillation rate {rate}");
+            }
+        }
+        // and then only switches
+        assert_eq!(scheduler.oscillation_rate(), 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_recent_decisions() {
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.5,
+                waiting: 0,
+            },
+        ]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .decision_log_capacity(3)
+            .start()
+            .await
+            .expect("Should start scheduler");
+        assert!(scheduler.recent_decisions().is_empty());
+
+        for i in 0..5 {
+            let options = ScheduleOptions {
+                request_id: Some(format!("request-{i}")),
+                ..Default::default()
+            };
+            let overlap = OverlapScores {
+                scores: HashMap::from([(WorkerId(2), 5.0)]),
+                ..Default::default()
+            };
+            scheduler
+                .schedule_with_options(overlap, 100 + i, options)
+                .await
+                .unwrap();
+        }
+
+        // Only the last three are kept, in order
+        let decisions = scheduler.recent_decisions();
+        let ids: Vec<_> = decisions
+            .iter()
+            .map(|d| d.request_id.as_deref().unwrap())
+            .collect();
+        assert_eq!(ids, vec!["request-2", "request-3", "request-4"]);
+        for (decision, isl_tokens) in decisions.iter().zip(102..) {
+            assert_eq!(decision.isl_tokens, isl_tokens);
+            assert_eq!(decision.chosen_worker, 2);
+            let candidates: Vec<WorkerId> = decision.candidates.iter().map(|c| c.0).collect();
+            assert_eq!(candidates, vec![WorkerId(1), WorkerId(2)]);
+            assert_eq!(decision.candidates[1].1, decision.logit);
+        }
+        assert!(decisions
+            .windows(2)
+            .all(|pair| pair[0].timestamp <= pair[1].timestamp));
+
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(create_workers(vec![]));
+        let result = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .decision_log_capacity(0)
+            .start()
+            .await;
+        assert!(matches!(result, Err(KvSchedulerError::InvalidConfig(_))));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_schedule_streaming() {
+        let workers = create_workers(
+            (1..=3)
+                .map(|id| WorkerInfo {
+                    id,
+                    usage: 0.0,
+                    waiting: 0,
+                })
+                .collect(),
+        );
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                waiting_requests_weight: 0.0,
+                gpu_cache_usage_weight: 0.0,
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        // Worker 3's full match arrives after the deadline, so only worker 2's partial one counts
+        let (scores_tx, scores_rx) = tokio::sync::mpsc::channel(8);
+        let indexer = tokio::spawn(async move {
+            scores_tx.send((WorkerId(2), 2.0)).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            let _ = scores_tx.send((WorkerId(3), 5.0)).await;
+        });
+        let worker_id = scheduler
+            .schedule_streaming(
+                scores_rx,
+                Duration::from_millis(50),
+                100,
+                ScheduleOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(worker_id, 2);
+        indexer.await.unwrap();
+
+        // A closed channel selects right away, with every score sent
+        let (scores_tx, scores_rx) = tokio::sync::mpsc::channel(8);
+        for score in [(WorkerId(2), 2.0), (WorkerId(3), 5.0), (WorkerId(2), 4.0)] {
+            scores_tx.send(score).await.unwrap();
+        }
+        drop(scores_tx);
+        let start = Instant::now();
+        let worker_id = scheduler
+            .schedule_streaming(
+                scores_rx,
+                Duration::from_secs(10),
+                100,
+                ScheduleOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(worker_id, 3);
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_dead_scheduler_fails_fast() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        // Hooks run on the scheduling task, outside the selector's panic isolation
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .on_selection(|selection| {
+                if selection.required_blocks == 7 {
+                    panic!("hook failed");
+                }
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+        assert_eq!(
+            scheduler
+                .schedule(OverlapScores::default(), 100)
+                .await
+                .unwrap(),
+            1
+        );
+
+        // The request in flight when the task died fails instead of hanging
+        let result = tokio::time::timeout(
+            Duration::from_secs(1),
+            scheduler.schedule(OverlapScores::default(), 140),
+        )
+        .await
+        .expect("Should not hang");
+        assert!(matches!(result, Err(KvSchedulerError::SubscriberShutdown)));
+
+        // and so does everything after it, without queueing
+        for _ in 0..3 {
+            let result = tokio::time::timeout(
+                Duration::from_secs(1),
+                scheduler.schedule(OverlapScores::default(), 100),
+            )
+            .await
+            .expect("Should not hang");
+            assert!(matches!(result, Err(KvSchedulerError::SchedulerDead)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_endpoints_closed_fails_fast() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.95,
+            waiting: 0,
+        }]);
+        let (endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                gpu_cache_usage_hard_cap: 0.9,
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        // The only worker is over the hard cap, so the request waits for capacity
+        let waiting = scheduler.schedule(OverlapScores::default(), 100);
+        tokio::pin!(waiting);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), &mut waiting)
+                .await
+                .is_err()
+        );
+
+        // Capacity can't be reported anymore, so the request fails rather than waiting forever
+        drop(endpoints_tx);
+        let result = tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .expect("Should not hang");
+        assert!(matches!(result, Err(KvSchedulerError::SchedulerDead)));
+
+        let result = tokio::time::timeout(
+            Duration::from_secs(1),
+            scheduler.schedule(OverlapScores::default(), 100),
+        )
+        .await
+        .expect("Should not hang");
+        assert!(matches!(result, Err(KvSchedulerError::SchedulerDead)));
+    }
+
+    #[tokio::test]
+    async fn test_rejection_event() {
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(create_workers(vec![]));
+        let sink = RecordingSink::default();
+        let scheduler = KvScheduler::builder()
+            .event_sink(sink.clone())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        match scheduler.schedule(OverlapScores::default(), 100).await {
+            Err(KvSchedulerError::NoEndpoints) => {}
+            other => panic!("Should return NoEndpoints, got {other:?}"),
+        }
+        // Requests rejected before reaching the background task are reported too
+        assert!(scheduler
+            .schedule(OverlapScores::default(), 0)
+            .await
+            .is_err());
+
+        let events = sink.wait_for_events(2).await;
+        let rejections: Vec<(RejectionReason, usize)> = events
+            .iter()
+            .filter_map(|event| match event {
+                SchedulerEvent::SchedulingRejected(event) => Some((event.reason, event.isl_tokens)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            rejections,
+            vec![
+                (RejectionReason::NoEndpoints, 100),
+                (RejectionReason::InvalidRequest, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gpu_cache_usage_hard_cap() {
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.99,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.5,
+                waiting: 3,
+            },
+        ]);
+        // Worker 1 holds the whole prefix, which would outweigh its usage without the cap
+        let request = create_request(
+            vec![WorkerOverlap {
+                worker_id: 1,
+                overlap_blocks: 5.0,
+            }],
+            100,
+        );
+        let result = DefaultWorkerSelector::new(None)
+            .select_worker(&workers, &request, 20)
+            .unwrap();
+        assert_eq!(result.worker_id, 1);
+
+        let selector = DefaultWorkerSelector::new(Some(KvRouterConfig {
+            gpu_cache_usage_hard_cap: 0.95,
+            ..Default::default()
+        }));
+        let result = selector.select_worker(&workers, &request, 20).unwrap();
+        assert_eq!(result.worker_id, 2);
+
+        // With every worker over the cap, the pool is busy rather than empty
+        let selector = DefaultWorkerSelector::new(Some(KvRouterConfig {
+            gpu_cache_usage_hard_cap: 0.4,
+            ..Default::default()
+        }));
+        match selector.select_worker(&workers, &request, 20) {
+            Err(KvSchedulerError::AllWorkersBusy) => {}
+            other => panic!("Should return AllWorkersBusy, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prefix_coalescing() {
+        let workers = create_workers(
+            (1..=3)
+                .map(|id| WorkerInfo {
+                    id,
+                    usage: 0.0,
+                    waiting: 0,
+                })
+                .collect(),
+        );
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                prefix_coalesce_window_ms: 60_000,
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        // Predicted load alone would spread these across all three workers
+        let options = ScheduleOptions {
+            prefix_hash: Some(42),
+            ..Default::default()
+        };
+        let mut worker_ids = HashSet::new();
+        for _ in 0..3 {
+            let worker_id = scheduler
+                .schedule_with_options(OverlapScores::default(), 100, options.clone())
+                .await
+                .unwrap();
+            worker_ids.insert(worker_id);
+        }
+        assert_eq!(worker_ids.len(), 1);
+
+        // A different prefix is routed on load
+        let coalesced = *worker_ids.iter().next().unwrap();
+        let other = scheduler
+            .schedule_with_options(
+                OverlapScores::default(),
+                100,
+                ScheduleOptions {
+                    prefix_hash: Some(7),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_ne!(other, coalesced);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_metrics_staleness() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let (endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                max_metrics_staleness_ms: Some(1000),
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+        let overlap = OverlapScores::default();
+        assert_eq!(scheduler.schedule(overlap.clone(), 100).await.unwrap(), 1);
+
+        // The metrics publisher goes quiet
+        tokio::time::advance(Duration::from_secs(2)).await;
+        match scheduler.schedule(overlap.clone(), 100).await {
+            Err(KvSchedulerError::StaleMetrics(staleness)) => {
+                assert!(staleness >= Duration::from_secs(2));
+            }
+            other => panic!("Should return StaleMetrics, got {other:?}"),
+        }
+
+        // A fresh poll clears the guard
+        endpoints_tx.send_modify(|_| {});
+        assert_eq!(scheduler.schedule(overlap, 100).await.unwrap(), 1);
+    }
+
+    #[test]
+    fn test_free_blocks_weight() {
+        // Both workers are half full, but worker 2 has ten times the capacity
+        let mut workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.5,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.5,
+                waiting: 0,
+            },
+        ]);
+        for (id, total_blocks) in [(1, 100), (2, 1000)] {
+            let data = &mut workers.endpoints.get_mut(&id).unwrap().data;
+            data.kv_total_blocks = total_blocks;
+            data.kv_active_blocks = total_blocks / 2;
+        }
+        let request = create_request(vec![], 100);
+
+        let selector = DefaultWorkerSelector::new(Some(KvRouterConfig {
+            free_blocks_weight: 1.0,
+            ..Default::default()
+        }));
+        for _ in 0..10 {
+            let result = selector.select_worker(&workers, &request, 20).unwrap();
+            assert_eq!(result.worker_id, 2);
+        }
+    }
+
+    #[test]
+    fn test_queue_age_weight() {
+        // Both workers have two requests waiting, but worker 1's have been stuck for 30s
+        let mut workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 2,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.0,
+                waiting: 2,
+            },
+        ]);
+        for (id, age_ms) in [(1, 30_000), (2, 50)] {
+            workers
+                .endpoints
+                .get_mut(&id)
+                .unwrap()
+                .data
+                .oldest_waiting_age_ms = Some(age_ms);
+        }
+        let request = create_request(vec![], 100);
+
+        let selector = DefaultWorkerSelector::new(Some(KvRouterConfig {
+            queue_age_weight: 1.0,
+            ..Default::default()
+        }));
+        let (selection, explanation) = selector.explain_selection(&workers, &request, 20).unwrap();
+        assert_eq!(selection.worker_id, 2);
+        let penalties: Vec<f64> = explanation
+            .candidates
+            .iter()
+            .map(|c| c.contributions.queue_age)
+            .collect();
+        assert_eq!(penalties, vec![-1.0, -50.0 / 30_000.0]);
+
+        // Disabled by default, leaving the workers tied
+        let (_, explanation) = DefaultWorkerSelector::new(None)
+            .explain_selection(&workers, &request, 20)
+            .unwrap();
+        assert_eq!(explanation.tied, vec![WorkerId(1), WorkerId(2)]);
+    }
+
+    #[test]
+    fn test_selection_cooldown() {
+        // Worker 1 is slightly preferred for every request of the burst
+        let request = create_request(
+            vec![WorkerOverlap {
+                worker_id: 1,
+                overlap_blocks: 1.0,
+            }],
+            100,
+        );
+        let (event_tx, _event_rx) = EventSender::channel(DEFAULT_EVENT_CHANNEL_CAPACITY);
+        let burst = |selection_cooldown_weight| {
+            let mut workers = create_workers(
+                (1..=3)
+                    .map(|id| WorkerInfo {
+                        id,
+                        usage: 0.0,
+                        waiting: 0,
+                    })
+                    .collect(),
+            );
+            let selector = DefaultWorkerSelector::new(Some(KvRouterConfig {
+                waiting_requests_weight: 0.0,
+                selection_cooldown_weight,
+                // Long enough not to decay noticeably during the test
+                selection_cooldown_ms: 60_000,
+                ..Default::default()
+            }));
+            (0..6)
+                .map(|_| {
+                    let selection = selector.select_worker(&workers, &request, 20).unwrap();
+                    process_worker_selection(&mut workers, selection, &event_tx, None).unwrap()
+                })
+                .collect::<Vec<WorkerId>>()
+        };
+
+        // Without a metrics update, the whole burst lands on worker 1
+        assert_eq!(burst(0.0), vec![1; 6]);
+        // The cooldown spreads it over every worker
+        let picks = burst(1.0);
+        assert_eq!(picks[0], 1);
+        assert_eq!(picks.iter().collect::<HashSet<_>>().len(), 3);
+
+        assert!(KvRouterConfig {
+            selection_cooldown_ms: 0,
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_priority_ordering() {
+        // The only worker starts over the hard cap, so requests pile up in the queues
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 1.0,
+            waiting: 0,
+        }]);
+        let (endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let sink = RecordingSink::default();
+        let scheduler = KvScheduler::builder()
+            .event_sink(sink.clone())
+            .block_size(1)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                gpu_cache_usage_hard_cap: 0.9,
+                high_priority_burst: 2,
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        let options = |priority| ScheduleOptions {
+            priority,
+            ..Default::default()
+        };
+        let overlap = OverlapScores::default();
+        // isl_tokens doubles as the request's label in the hit rate events
+        let mut pending = vec![scheduler
+            .submit(overlap.clone(), 50, options(Priority::High))
+            .await
+            .unwrap()];
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        for (isl_tokens, priority) in [
+            (101, Priority::Low),
+            (102, Priority::Low),
+            (1, Priority::High),
+            (2, Priority::High),
+            (3, Priority::High),
+            (4, Priority::High),
+        ] {
+            pending.push(
+                scheduler
+                    .submit(overlap.clone(), isl_tokens, options(priority))
+                    .await
+                    .unwrap(),
+            );
+        }
+        assert_eq!(scheduler.queue_len(), 6);
+
+        endpoints_tx.send_modify(|workers| {
+            workers
+                .endpoints
+                .get_mut(&1)
+                .unwrap()
+                .data
+                .gpu_cache_usage_perc = 0.0;
+        });
+        for resp_rx in pending {
+            assert_eq!(resp_rx.await.unwrap().unwrap().worker_id, 1);
+        }
+
+        // High first, but one low after every two high
+        let order: Vec<usize> = sink
+            .wait_for_events(7)
+            .await
+            .iter()
+            .filter_map(|event| match event {
+                SchedulerEvent::HitRate(event) => Some(event.isl_blocks),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(order, vec![50, 1, 101, 2, 3, 102, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_on_selection_hook() {
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.9,
+                waiting: 0,
+            },
+        ]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let selected = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .on_selection({
+                let selected = selected.clone();
+                move |selection| {
+                    selected
+                        .lock()
+                        .unwrap()
+                        .push((selection.worker_id, selection.required_blocks))
+                }
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        let worker_id = scheduler
+            .schedule(OverlapScores::default(), 100)
+            .await
+            .unwrap();
+        assert_eq!(worker_id, 1);
+        // The hook runs before the response is sent
+        assert_eq!(*selected.lock().unwrap(), vec![(WorkerId(1), 5)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_warmup_penalty() {
+        let warm = || WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 1,
+        };
+        let (endpoints_tx, endpoints_rx) =
+            tokio::sync::watch::channel(create_workers(vec![warm()]));
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                warmup_duration_ms: 10_000,
+                warmup_penalty: 2.0,
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        // Worker 2 joins idle, so once warm it beats worker 1 by the waiting term alone
+        let pool = || {
+            create_workers(vec![
+                warm(),
+                WorkerInfo {
+                    id: 2,
+                    usage: 0.0,
+                    waiting: 0,
+                },
+            ])
+        };
+        endpoints_tx.send(pool()).unwrap();
+        let mut selections = Vec::new();
+        for elapsed_secs in [0, 4, 2, 4] {
+            tokio::time::advance(Duration::from_secs(elapsed_secs)).await;
+            // Reset the predictive updates from the previous selection
+            endpoints_tx.send(pool()).unwrap();
+            let selected = scheduler
+                .schedule(OverlapScores::default(), 100)
+                .await
+                .unwrap();
+            selections.push(selected);
+        }
+        // Penalty at 0s is 2.0 and at 4s 1.2, both worse than worker 1's waiting term of 1.0;
+        // at 6s it is 0.8, and by 10s worker 2 is warm
+        assert_eq!(selections, vec![1, 1, 2, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_update_config() {
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.5,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.0,
+                waiting: 0,
+            },
+        ]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .start()
+            .await
+            .expect("Should start scheduler");
+        let overlap = create_request(
+            vec![WorkerOverlap {
+                worker_id: 1,
+                overlap_blocks: 5.0,
+            }],
+            100,
+        )
+        .overlap;
+
+        // Worker 1's cached prefix outweighs its load
+        assert_eq!(scheduler.schedule(overlap.clone(), 100).await.unwrap(), 1);
+
+        let invalid = KvRouterConfig {
+            overlap_score_weight: -1.0,
+            ..Default::default()
+        };
+        assert!(matches!(
+            scheduler.update_config(invalid),
+            Err(KvSchedulerError::InvalidConfig(_))
+        ));
+        assert_eq!(scheduler.schedule(overlap.clone(), 100).await.unwrap(), 1);
+
+        // Ignoring overlap, the idle worker wins
+        scheduler
+            .update_config(KvRouterConfig {
+                overlap_score_weight: 0.0,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(scheduler.schedule(overlap, 100).await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_tenant_fairness() {
+        // Worker 1 holds the shared prefix, so it is every tenant's best worker
+        let pool = || {
+            create_workers(vec![
+                WorkerInfo {
+                    id: 1,
+                    usage: 0.0,
+                    waiting: 0,
+                },
+                WorkerInfo {
+                    id: 2,
+                    usage: 0.0,
+                    waiting: 0,
+                },
+            ])
+        };
+        let overlap = create_request(
+            vec![WorkerOverlap {
+                worker_id: 1,
+                overlap_blocks: 1.0,
+            }],
+            100,
+        )
+        .overlap;
+
+        let run = |fairness_weight| {
+            let overlap = overlap.clone();
+            async move {
+                let (endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(pool());
+                let scheduler = KvScheduler::builder()
+                    .event_sink(RecordingSink::default())
+                    .block_size(20)
+                    .endpoints(endpoints_rx)
+                    .config(KvRouterConfig {
+                        fairness_weight,
+                        ..Default::default()
+                    })
+                    .start()
+                    .await
+                    .expect("Should start scheduler");
+
+                // Tenant a bursts first, then both tenants submit at the same rate
+                let tenants = std::iter::repeat_n("a", 10).chain(["a", "b"].repeat(10));
+                let mut on_best = HashMap::new();
+                for tenant in tenants {
+                    // Reset the predictive load so only overlap and fairness decide
+                    endpoints_tx.send(pool()).unwrap();
+                    let options = ScheduleOptions {
+                        tenant_id: Some(tenant.to_string()),
+                        ..Default::default()
+                    };
+                    let worker_id = scheduler
+                        .schedule_with_options(overlap.clone(), 100, options)
+                        .await
+                        .unwrap();
+                    if worker_id == 1 {
+                        *on_best.entry(tenant).or_insert(0usize) += 1;
+                    }
+                }
+                (on_best["a"], on_best["b"])
+            }
+        };
+
+        // Without fairness, tenant a keeps the best worker throughout
+        assert_eq!(run(0.0).await, (20, 10));
+
+        // With it, tenant a is pushed off the best worker until tenant b catches up
+        let (a, b) = run(4.0).await;
+        assert_eq!(b, 10);
+        assert!(a.abs_diff(b) <= 3, "best worker split {a} / {b}");
+    }
+
+    #[tokio::test]
+    async fn test_tenant_soft_quota() {
+        // Worker 1 holds the shared prefix, so it is every tenant's best worker
+        let pool = || {
+            create_workers(vec![
+                WorkerInfo {
+                    id: 1,
+                    usage: 0.0,
+                    waiting: 0,
+                },
+                WorkerInfo {
+                    id: 2,
+                    usage: 0.0,
+                    waiting: 0,
+                },
+            ])
+        };
+        let overlap = create_request(
+            vec![WorkerOverlap {
+                worker_id: 1,
+                overlap_blocks: 1.0,
+            }],
+            100,
+        )
+        .overlap;
+        let (endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(pool());
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                tenant_soft_quotas: HashMap::from([("a".to_string(), 3)]),
+                quota_penalty_weight: 4.0,
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+        let schedule = |tenant: &str| {
+            // Reset the predictive load so only overlap and the quota decide
+            endpoints_tx.send(pool()).unwrap();
+            let options = ScheduleOptions {
+                tenant_id: Some(tenant.to_string()),
+                ..Default::default()
+            };
+            scheduler.schedule_with_options(overlap.clone(), 100, options)
+        };
+
+        // Within its quota, tenant a gets the best worker
+        for _ in 0..3 {
+            assert_eq!(schedule("a").await.unwrap(), 1);
+        }
+        // Past it, tenant a is still served, on the worse worker, while tenant b keeps the best
+        for _ in 0..5 {
+            assert_eq!(schedule("a").await.unwrap(), 2);
+            assert_eq!(schedule("b").await.unwrap(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_schedule_speculative() {
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.05,
+                waiting: 0,
+            },
+        ]);
+        let (endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers.clone());
+        let sink = RecordingSink::default();
+        let scheduler = KvScheduler::builder()
+            .event_sink(sink.clone())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                speculative_margin: 0.1,
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        // Worker 2 trails by 0.05, so the request goes to both
+        let (primary, secondary) = scheduler
+            .schedule_speculative(OverlapScores::default(), 100)
+            .await
+            .unwrap();
+        assert_eq!((primary, secondary), (WorkerId(1), Some(WorkerId(2))));
+        // Both selections were applied
+        let selected: Vec<WorkerId> = sink
+            .wait_for_events(2)
+            .await
+            .into_iter()
+            .filter_map(|event| match event {
+                SchedulerEvent::HitRate(event) => Some(event.worker_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(selected, vec![1, 2]);
+
+        // 2 of 5 blocks cached on worker 1 puts it 0.85 ahead
+        endpoints_tx.send(workers).unwrap();
+        let overlap = create_request(
+            vec![WorkerOverlap {
+                worker_id: 1,
+                overlap_blocks: 2.0,
+            }],
+            100,
+        )
+        .overlap;
+        let (primary, secondary) = scheduler.schedule_speculative(overlap, 100).await.unwrap();
+        assert_eq!((primary, secondary), (WorkerId(1), None));
+    }
+
+    #[test]
+    fn test_predictive_decay() {
+        let polled = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let mut predicted = polled.clone();
+        let active_delta = |predicted: &ProcessedEndpoints| {
+            predicted.endpoints[&1].data.kv_active_blocks as i64
+                - polled.endpoints[&1].data.kv_active_blocks as i64
+        };
+        let start = Instant::now();
+
+        // Without a half-life predictions persist
+        predicted
+            .endpoints
+            .get_mut(&1)
+            .unwrap()
+            .data
+            .kv_active_blocks += 5;
+        let mut decay = PredictionDecay::new(None);
+        decay.reset(start);
+        assert!(!decay.apply(&mut predicted, &polled, start + Duration::from_secs(10)));
+        assert_eq!(active_delta(&predicted), 5);
+
+        let mut decay = PredictionDecay::new(Some(Duration::from_secs(1)));
+        decay.reset(start);
+        // 2.5 blocks left after one half-life
+        assert!(decay.apply(&mut predicted, &polled, start + Duration::from_secs(1)));
+        assert_eq!(active_delta(&predicted), 2);
+        // The fractional half block carries over: 1.25 after two
+        decay.apply(&mut predicted, &polled, start + Duration::from_secs(2));
+        assert_eq!(active_delta(&predicted), 1);
+        // Eventually the prediction fades entirely
+        decay.apply(&mut predicted, &polled, start + Duration::from_secs(30));
+        assert_eq!(active_delta(&predicted), 0);
+    }
+
+    #[test]
+    fn test_process_unknown_worker() {
+        let mut workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let (event_tx, mut event_rx) = EventSender::channel(DEFAULT_EVENT_CHANNEL_CAPACITY);
+        let stale = WorkerSelectionResult {
+            worker_id: WorkerId(9),
+            required_blocks: 5,
+            decode_blocks: 0,
+            overlap_blocks: 0.0,
+            margin: 0.0,
+            logit: 0.0,
+        };
+        let result = process_worker_selection(&mut workers, stale, &event_tx, None);
+        assert!(matches!(
+            result,
+            Err(KvSchedulerError::WorkerNotFound {
+                worker_id: WorkerId(9)
+            })
+        ));
+        assert_eq!(workers.endpoints[&1].data.num_requests_waiting, 0);
+        assert!(event_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_process_clamps_overlap() {
+        let mut workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let (event_tx, mut event_rx) = EventSender::channel(DEFAULT_EVENT_CHANNEL_CAPACITY);
+        let selected = Arc::new(Mutex::new(Vec::new()));
+        let on_selection: SelectionHook = {
+            let selected = selected.clone();
+            Box::new(move |selection: &WorkerSelectionResult| {
+                selected.lock().unwrap().push(selection.clone())
+            })
+        };
+        let selection = |required_blocks, overlap_blocks| WorkerSelectionResult {
+            worker_id: WorkerId(1),
+            required_blocks,
+            decode_blocks: 0,
+            overlap_blocks,
+            margin: 0.0,
+            logit: 0.0,
+        };
+
+        // The indexer matched 8 blocks of a 5 block request
+        process_worker_selection(
+            &mut workers,
+            selection(5, 8.0),
+            &event_tx,
+            Some(&on_selection),
+        )
+        .unwrap();
+        assert_eq!(workers.endpoints[&1].data.kv_active_blocks, 0);
+        assert_eq!(selected.lock().unwrap()[0].overlap_blocks, 5.0);
+        let Ok(SchedulerEvent::HitRate(event)) = event_rx.try_recv() else {
+            panic!("Expected a hit rate event");
+        };
+        assert_eq!(event.isl_blocks, 5);
+        assert_eq!(event.overlap_blocks, 5);
+        assert!(event.overlap_blocks as f64 / event.isl_blocks as f64 <= 1.0);
+
+        // Overlap within the request is left alone
+        process_worker_selection(
+            &mut workers,
+            selection(5, 2.0),
+            &event_tx,
+            Some(&on_selection),
+        )
+        .unwrap();
+        assert_eq!(workers.endpoints[&1].data.kv_active_blocks, 3);
+        assert_eq!(selected.lock().unwrap()[1].overlap_blocks, 2.0);
+        let Ok(SchedulerEvent::HitRate(event)) = event_rx.try_recv() else {
+            panic!("Expected a hit rate event");
+        };
+        assert_eq!(event.overlap_blocks, 2);
+    }
+
+    /// Panics when asked to select for a request of `panic_on` tokens.
+    struct PanickingSelector {
+        panic_on: usize,
+    }
+
+    impl WorkerSelector for PanickingSelector {
+        fn select_worker(
+            &self,
+            workers: &ProcessedEndpoints,
+            request: &SchedulingRequest,
+            block_size: usize,
+        ) -> Result<WorkerSelectionResult, KvSchedulerError> {
+            if request.isl_tokens == self.panic_on {
+                panic!("unexpected request of {} tokens", request.isl_tokens);
+            }
+            DefaultWorkerSelector::default().select_worker(workers, request, block_size)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_selector_panic_isolated() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .selector(Box::new(PanickingSelector { panic_on: 13 }))
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        assert_eq!(
+            scheduler
+                .schedule(OverlapScores::default(), 100)
+                .await
+                .unwrap(),
+            1
+        );
+        match scheduler.schedule(OverlapScores::default(), 13).await {
+            Err(KvSchedulerError::SelectorPanicked(message)) => {
+                assert_eq!(message, "unexpected request of 13 tokens")
+            }
+            other => panic!("Should return SelectorPanicked, got {other:?}"),
+        }
+        // The scheduling task survived and keeps routing
+        for _ in 0..3 {
+            assert_eq!(
+                scheduler
+                    .schedule(OverlapScores::default(), 100)
+                    .await
+                    .unwrap(),
+                1
+            );
+        }
+    }
+
+    /// Picks a worker that doesn't exist for the first `stale` selections.
+    struct StaleSelector {
+        stale: usize,
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    impl WorkerSelector for StaleSelector {
+        fn select_worker(
+            &self,
+            workers: &ProcessedEndpoints,
+            request: &SchedulingRequest,
+            block_size: usize,
+        ) -> Result<WorkerSelectionResult, KvSchedulerError> {
+            let mut selection =
+                DefaultWorkerSelector::default().select_worker(workers, request, block_size)?;
+            if self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < self.stale {
+                selection.worker_id = WorkerId(9);
+            }
+            Ok(selection)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stale_selection_retried() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let start = |stale| {
+            KvScheduler::builder()
+                .event_sink(RecordingSink::default())
+                .block_size(20)
+                .endpoints(endpoints_rx.clone())
+                .selector(Box::new(StaleSelector {
+                    stale,
+                    calls: Default::default(),
+                }))
+                .start()
+        };
+
+        // One stale pick is retried
+        let scheduler = start(1).await.expect("Should start scheduler");
+        let result = scheduler.schedule(OverlapScores::default(), 100).await;
+        assert_eq!(result.unwrap(), 1);
+
+        // A selector that never catches up fails the request instead of the scheduler
+        let scheduler = start(usize::MAX).await.expect("Should start scheduler");
+        let result = scheduler.schedule(OverlapScores::default(), 100).await;
+        assert!(matches!(
+            result,
+            Err(KvSchedulerError::WorkerNotFound {
+                worker_id: WorkerId(9)
+            })
+        ));
+        let result = scheduler.schedule(OverlapScores::default(), 100).await;
+        assert!(matches!(
+            result,
+            Err(KvSchedulerError::WorkerNotFound {
+                worker_id: WorkerId(9)
+            })
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_capacity_wait() {
+        let pool = |usage| {
+            create_workers(vec![WorkerInfo {
+                id: 1,
+                usage,
+                waiting: 0,
+            }])
+        };
+        let (endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(pool(1.0));
+        let registry = prometheus::Registry::new();
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                gpu_cache_usage_hard_cap: 0.9,
+                ..Default::default()
+            })
+            .metrics_registry(registry.clone())
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        // The only worker is full until it frees up 50ms in
+        let (scheduled, _) = tokio::join!(
+            scheduler.schedule_timed(OverlapScores::default(), 100, ScheduleOptions::default()),
+            async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                endpoints_tx.send(pool(0.0)).unwrap();
+            }
+        );
+        let scheduled = scheduled.unwrap();
+        assert_eq!(scheduled.worker_id, 1);
+        assert!(scheduled.timing.capacity_wait >= Duration::from_millis(50));
+        assert!(scheduled.timing.queue_wait < Duration::from_millis(50));
+
+        let families = registry.gather();
+        let capacity_wait = families
+            .iter()
+            .find(|family| family.name() == "dynamo_kv_scheduler_capacity_wait_seconds")
+            .expect("capacity wait histogram should be registered");
+        let histogram = capacity_wait.get_metric()[0].get_histogram();
+        assert_eq!(histogram.get_sample_count(), 1);
+        assert!(histogram.get_sample_sum() >= 0.05);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_capacity_wait_across_refreshes() {
+        let pool = |usage| {
+            create_workers(vec![WorkerInfo {
+                id: 1,
+                usage,
+                waiting: 0,
+            }])
+        };
+        let (endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(pool(1.0));
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                gpu_cache_usage_hard_cap: 0.9,
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        // A refresh 30ms in still finds the worker full; it only frees up 50ms after that
+        let (scheduled, _) = tokio::join!(
+            scheduler.schedule_timed(OverlapScores::default(), 100, ScheduleOptions::default()),
+            async {
+                tokio::time::sleep(Duration::from_millis(30)).await;
+                endpoints_tx.send(pool(1.0)).unwrap();
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                endpoints_tx.send(pool(0.0)).unwrap();
+            }
+        );
+        let scheduled = scheduled.unwrap();
+        assert_eq!(scheduled.worker_id, 1);
+        assert!(scheduled.timing.capacity_wait >= Duration::from_millis(80));
+    }
+
+    async fn start_listed_pool() -> (tokio::sync::watch::Sender<ProcessedEndpoints>, KvScheduler) {
+        let workers = create_workers(
+            (1..=3)
+                .map(|id| WorkerInfo {
+                    id,
+                    usage: 0.0,
+                    waiting: 0,
+                })
+                .collect(),
+        );
+        let (endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .start()
+            .await
+            .expect("Should start scheduler");
+        (endpoints_tx, scheduler)
+    }
+
+    async fn selections(scheduler: &KvScheduler, count: usize) -> HashSet<WorkerId> {
+        let mut selected = HashSet::new();
+        for _ in 0..count {
+            selected.insert(
+                scheduler
+                    .schedule(OverlapScores::default(), 100)
+                    .await
+                    .unwrap(),
+            );
+        }
+        selected
+    }
+
+    /// Serves a request in one millisecond per token, however much of it is cached.
+    struct PerTokenModel;
+
+    impl ServiceTimeModel for PerTokenModel {
+        fn estimate(&self, isl_tokens: usize, _overlap_blocks: f64) -> Duration {
+            Duration::from_millis(isl_tokens as u64)
+        }
+    }
+
+    #[test]
+    fn test_linear_service_time_model() {
+        let model = LinearServiceTimeModel::default();
+        assert_eq!(model.estimate(100, 0.0), Duration::from_millis(60));
+        assert_eq!(model.estimate(100, 4.0), Duration::from_millis(56));
+        // Savings never take the estimate below the fixed cost
+        assert_eq!(model.estimate(100, 1000.0), Duration::from_millis(50));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_deadline_uses_service_time_model() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .service_time_model(PerTokenModel)
+            .start()
+            .await
+            .expect("Should start scheduler");
+        let options = ScheduleOptions {
+            deadline: Some(Duration::from_secs(10)),
+            ..Default::default()
+        };
+        let schedule = |isl_tokens| {
+            scheduler.schedule_with_options(OverlapScores::default(), isl_tokens, options.clone())
+        };
+
+        assert_eq!(schedule(6_000).await.unwrap(), 1);
+        // 6s already queued on the worker plus 5s of its own
+        let result = schedule(5_000).await;
+        assert!(matches!(
+            result,
+            Err(KvSchedulerError::DeadlineUnmet { estimated }) if estimated == Duration::from_secs(11)
+        ));
+        assert_eq!(schedule(3_000).await.unwrap(), 1);
+
+        // The completion takes the oldest request's estimate off the worker's queue
+        scheduler.complete(1, 0);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(schedule(5_000).await.unwrap(), 1);
+
+        // Requests without a deadline are never held to one
+        let worker_id = scheduler
+            .schedule(OverlapScores::default(), 20_000)
+            .await
+            .unwrap();
+        assert_eq!(worker_id, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_service_estimates_expire_without_completions() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let (endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .service_time_model(PerTokenModel)
+            .start()
+            .await
+            .expect("Should start scheduler");
+        let options = ScheduleOptions {
+            deadline: Some(Duration::from_secs(10)),
+            ..Default::default()
+        };
+        let schedule = |isl_tokens| {
+            scheduler.schedule_with_options(OverlapScores::default(), isl_tokens, options.clone())
+        };
+
+        for _ in 0..2 {
+            assert_eq!(schedule(4_000).await.unwrap(), 1);
+        }
+        assert!(matches!(
+            schedule(5_000).await,
+            Err(KvSchedulerError::DeadlineUnmet { estimated }) if estimated == Duration::from_secs(13)
+        ));
+
+        // No completion is ever reported, but the first request should be done by now
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert!(matches!(
+            schedule(8_000).await,
+            Err(KvSchedulerError::DeadlineUnmet { estimated }) if estimated == Duration::from_secs(11)
+        ));
+        assert_eq!(schedule(5_000).await.unwrap(), 1);
+
+        // By now all of them should be
+        tokio::time::advance(Duration::from_secs(20)).await;
+        assert_eq!(schedule(9_000).await.unwrap(), 1);
+
+        // A worker that leaves takes its estimates with it
+        endpoints_tx.send(create_workers(vec![])).unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        endpoints_tx
+            .send(create_workers(vec![WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 0,
+            }]))
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(schedule(9_000).await.unwrap(), 1);
+    }
+
+    fn two_workers() -> ProcessedEndpoints {
+        create_workers(
+            (1..=2)
+                .map(|id| WorkerInfo {
+                    id,
+                    usage: 0.0,
+                    waiting: 0,
+                })
+                .collect(),
+        )
+    }
+
+    async fn start_session_pool() -> (tokio::sync::watch::Sender<ProcessedEndpoints>, KvScheduler) {
+        let (endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(two_workers());
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                session_ttl_ms: 1000,
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+        (endpoints_tx, scheduler)
+    }
+
+    async fn schedule_session(scheduler: &KvScheduler, session_id: &str) -> WorkerId {
+        let options = ScheduleOptions {
+            session_id: Some(session_id.to_string()),
+            ..Default::default()
+        };
+        scheduler
+            .schedule_with_options(OverlapScores::default(), 100, options)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_session_affinity() {
+        let (_endpoints_tx, scheduler) = start_session_pool().await;
+        let first = schedule_session(&scheduler, "chat").await;
+        // Later turns stay put even as the worker's predicted queue grows
+        for _ in 0..4 {
+            assert_eq!(schedule_session(&scheduler, "chat").await, first);
+        }
+        let other = scheduler
+            .schedule(OverlapScores::default(), 100)
+            .await
+            .unwrap();
+        assert_ne!(other, first);
+
+        // Each turn renews the session
+        tokio::time::advance(Duration::from_millis(900)).await;
+        assert_eq!(schedule_session(&scheduler, "chat").await, first);
+
+        // Once expired, the session is routed on load and follows its new worker
+        tokio::time::advance(Duration::from_millis(1001)).await;
+        assert_eq!(schedule_session(&scheduler, "chat").await, other);
+        assert_eq!(schedule_session(&scheduler, "chat").await, other);
+    }
+
+    #[tokio::test]
+    async fn test_session_worker_gone() {
+        let (endpoints_tx, scheduler) = start_session_pool().await;
+        let first = schedule_session(&scheduler, "chat").await;
+        let remaining = 3 - first.0;
+
+        // The session's worker leaves the pool
+        let mut workers = two_workers();
+        workers.endpoints.remove(&first);
+        endpoints_tx.send(workers).unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(schedule_session(&scheduler, "chat").await, remaining);
+
+        // and the session stays with its replacement when it returns
+        endpoints_tx.send(two_workers()).unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        for _ in 0..3 {
+            assert_eq!(schedule_session(&scheduler, "chat").await, remaining);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_rebalancing() {
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(two_workers());
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                session_ttl_ms: 60_000,
+                session_rebalance_factor: Some(1.5),
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        // Pure stickiness would put all ten turns on one worker
+        let mut routed = HashMap::<WorkerId, usize>::new();
+        let mut last = WorkerId(0);
+        for _ in 0..10 {
+            last = schedule_session(&scheduler, "chat").await;
+            *routed.entry(last).or_default() += 1;
+        }
+        assert_eq!(routed.len(), 2);
+        assert!(routed.values().all(|&count| count < 10));
+
+        // The session follows the worker it was moved to
+        let state = scheduler.export_state().await.unwrap();
+        assert_eq!(state.sessions["chat"].worker_id, last);
+        let predicted = |worker_id: i64| state.predicted[&worker_id].kv_active_blocks_delta;
+        assert!(predicted(1).abs_diff(predicted(2)) <= 15);
+    }
+
+    #[test]
+    fn test_adaptive_overlap_weight() {
+        let mut config = KvRouterConfig {
+            adaptive_target_hit_rate: Some(0.8),
+            adaptive_overlap_step: 0.5,
+            adaptive_overlap_weight_max: 4.0,
+            ..Default::default()
+        };
+
+        // A persistently low hit rate raises the weight step by step, up to the maximum
+        let mut weights = vec![];
+        while let Some(weight) = tuned_overlap_weight(&config, Some(0.2), 0.3) {
+            config.overlap_score_weight = weight;
+            weights.push(weight);
+        }
+        assert_eq!(weights, vec![2.5, 3.0, 3.5, 4.0]);
+
+        // On target, or with nothing selected, it stays put
+        assert_eq!(tuned_overlap_weight(&config, Some(0.85), 0.3), None);
+        assert_eq!(tuned_overlap_weight(&config, None, 0.3), None);
+
+        // Overloaded workers lower it, even with a low hit rate
+        assert_eq!(tuned_overlap_weight(&config, Some(0.2), 0.95), Some(3.5));
+
+        // Disabled, the weight never moves
+        let fixed = KvRouterConfig::default();
+        assert_eq!(tuned_overlap_weight(&fixed, Some(0.0), 1.0), None);
+    }
+
+    #[tokio::test]
+    async fn test_schedule_explained() {
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.25,
+                waiting: 4,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.5,
+                waiting: 2,
+            },
+            WorkerInfo {
+                id: 3,
+                usage: 0.75,
+                waiting: 0,
+            },
+        ]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .start()
+            .await
+            .expect("Should start scheduler");
+        let overlap = OverlapScores {
+            scores: HashMap::from([(WorkerId(2), 3.0), (WorkerId(3), 5.0)]),
+            ..Default::default()
+        };
+
+        let (worker_id, explanation) = scheduler.schedule_explained(overlap, 100).await.unwrap();
+        let ids: Vec<WorkerId> = explanation.candidates.iter().map(|c| c.worker_id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+
+        // (score, usage, normalized waiting) with overlap weight 2 and the others 1
+        let expected = [(0.0, 0.25, 1.0), (0.6, 0.5, 0.5), (1.0, 0.75, 0.0)];
+        for (candidate, (score, usage, waiting)) in explanation.candidates.iter().zip(expected) {
+            assert!((candidate.score - score).abs() < 1e-9);
+            assert!((candidate.gpu_cache_usage - usage).abs() < 1e-6);
+            assert!((candidate.normalized_waiting - waiting).abs() < 1e-9);
+            let contributions = candidate.contributions;
+            assert!((contributions.overlap - 2.0 * score).abs() < 1e-9);
+            assert!((contributions.gpu_cache_usage + usage).abs() < 1e-6);
+            assert!((contributions.waiting + waiting).abs() < 1e-9);
+            assert_eq!(candidate.logit, contributions.total());
+            assert!(!candidate.capped);
+        }
+        // Logits -1.25, 0.2 and 1.25
+        assert_eq!(worker_id, 3);
+        assert_eq!(explanation.tied, vec![3]);
+        assert!(!explanation.random_tie_break);
+    }
+
+    #[tokio::test]
+    async fn test_schedule_dry_run() {
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.2,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.5,
+                waiting: 0,
+            },
+        ]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let sink = RecordingSink::default();
+        let scheduler = KvScheduler::builder()
+            .event_sink(sink.clone())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .start()
+            .await
+            .expect("Should start scheduler");
+        let overlap = OverlapScores {
+            scores: HashMap::from([(WorkerId(2), 2.0)]),
+            ..Default::default()
+        };
+
+        let first = scheduler
+            .schedule_dry_run(overlap.clone(), 100)
+            .await
+            .unwrap();
+        let second = scheduler
+            .schedule_dry_run(overlap.clone(), 100)
+            .await
+            .unwrap();
+        assert_eq!(first, second);
+        assert_eq!(first.worker_id, 2);
+        assert_eq!(first.overlap_blocks, 2.0);
+
+        // Neither run was applied or reported
+        let selected = scheduler.schedule(overlap, 100).await.unwrap();
+        assert_eq!(selected, first.worker_id);
+        sink.wait_for_events(1).await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(sink.events.lock().unwrap().len(), 1);
+
+        let invalid = scheduler
+            .schedule_dry_run(OverlapScores::default(), 0)
+            .await;
+        assert!(matches!(invalid, Err(KvSchedulerError::InvalidRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn test_load_shedding() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let sink = RecordingSink::default();
+        let scheduler = KvScheduler::builder()
+            .event_sink(sink.clone())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                load_shed_high_water_mark: Some(2),
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+        let schedule = |priority| {
+            scheduler.schedule_with_options(
+                OverlapScores::default(),
+                100,
+                ScheduleOptions {
+                    priority,
+                    ..Default::default()
+                },
+            )
+        };
+
+        assert_eq!(schedule(Priority::Low).await.unwrap(), 1);
+        assert_eq!(schedule(Priority::High).await.unwrap(), 1);
+        assert_eq!(scheduler.load(), 2);
+
+        // At the mark, low priority requests are shed while high priority ones still go through
+        let shed = schedule(Priority::Low).await;
+        assert!(matches!(
+            shed,
+            Err(KvSchedulerError::LoadShed {
+                load: 2,
+                high_water_mark: 2
+            })
+        ));
+        assert_eq!(schedule(Priority::High).await.unwrap(), 1);
+        assert_eq!(scheduler.load(), 3);
+
+        let events = sink.wait_for_events(4).await;
+        assert!(events.iter().any(|event| matches!(
+            event,
+            SchedulerEvent::SchedulingRejected(SchedulingRejectedEvent {
+                reason: RejectionReason::LoadShed,
+                ..
+            })
+        )));
+
+        // Completions bring the load back under the mark
+        scheduler.complete(1, 0);
+        scheduler.complete(1, 0);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(scheduler.load(), 1);
+        assert_eq!(schedule(Priority::Low).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_load_reconciled_with_polls() {
+        let pool = |waiting| {
+            create_workers(vec![WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting,
+            }])
+        };
+        let (endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(pool(0));
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(KvRouterConfig {
+                load_shed_high_water_mark: Some(2),
+                ..Default::default()
+            })
+            .start()
+            .await
+            .expect("Should start scheduler");
+        let schedule = || {
+            scheduler.schedule_with_options(
+                OverlapScores::default(),
+                100,
+                ScheduleOptions {
+                    priority: Priority::Low,
+                    ..Default::default()
+                },
+            )
+        };
+
+        assert_eq!(schedule().await.unwrap(), 1);
+        assert_eq!(schedule().await.unwrap(), 1);
+        assert!(matches!(
+            schedule().await,
+            Err(KvSchedulerError::LoadShed { load: 2, .. })
+        ));
+
+        // No completion is reported, but the worker says one of the two is still around
+        endpoints_tx.send(pool(1)).unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(scheduler.load(), 1);
+        assert_eq!(schedule().await.unwrap(), 1);
+
+        // And later that it's done with all of them
+        endpoints_tx.send(pool(0)).unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(scheduler.load(), 0);
+
+        // A worker that leaves the pool takes its in-flight requests with it
+        assert_eq!(schedule().await.unwrap(), 1);
+        endpoints_tx.send(create_workers(vec![])).unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(scheduler.load(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume() {
+        let (endpoints_tx, scheduler) = start_listed_pool().await;
+        let scheduler = Arc::new(scheduler);
+        scheduler.pause();
+
+        let pending = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.schedule(OverlapScores::default(), 100).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!pending.is_finished());
+
+        // Endpoint updates still apply while paused
+        endpoints_tx
+            .send(create_workers(vec![WorkerInfo {
+                id: 4,
+                usage: 0.0,
+                waiting: 0,
+            }]))
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        scheduler.resume();
+        assert_eq!(pending.await.unwrap().unwrap(), 4);
+        assert_eq!(
+            selections(&scheduler, 3).await,
+            HashSet::from([WorkerId(4)])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_health_probe() {
+        let workers = create_workers(
+            (1..=3)
+                .map(|id| WorkerInfo {
+                    id,
+                    usage: 0.0,
+                    waiting: 0,
+                })
+                .collect(),
+        );
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let (health_tx, health_rx) =
+            tokio::sync::watch::channel(HashMap::from([(WorkerId(3), false)]));
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .health(health_rx)
+            .start()
+            .await
+            .expect("Should start scheduler");
+        assert_eq!(
+            selections(&scheduler, 12).await,
+            HashSet::from([WorkerId(1), WorkerId(2)])
+        );
+
+        // Worker 2 fails its probe while worker 3 recovers
+        health_tx.send_replace(HashMap::from([(WorkerId(2), false), (WorkerId(3), true)]));
+        assert_eq!(
+            selections(&scheduler, 12).await,
+            HashSet::from([WorkerId(1), WorkerId(3)])
+        );
+
+        // Every worker unhealthy leaves nothing to route to
+        health_tx.send_replace(HashMap::from([
+            (WorkerId(1), false),
+            (WorkerId(2), false),
+            (WorkerId(3), false),
+        ]));
+        let result = scheduler.schedule(OverlapScores::default(), 100).await;
+        assert!(matches!(result, Err(KvSchedulerError::NoEndpoints)));
+    }
+
+    #[tokio::test]
+    async fn test_blacklist() {
+        let (_endpoints_tx, scheduler) = start_listed_pool().await;
+        scheduler.set_blacklist(vec![WorkerId(2)]);
+        assert_eq!(
+            selections(&scheduler, 12).await,
+            HashSet::from([WorkerId(1), WorkerId(3)])
+        );
+
+        // Clearing the blacklist restores the worker
+        scheduler.set_blacklist(vec![]);
+        assert!(selections(&scheduler, 12).await.contains(&2));
+    }
+
+    #[tokio::test]
+    async fn test_allowlist() {
+        let (_endpoints_tx, scheduler) = start_listed_pool().await;
+        scheduler.set_allowlist(Some(vec![WorkerId(2), WorkerId(3)]));
+        assert_eq!(
+            selections(&scheduler, 12).await,
+            HashSet::from([WorkerId(2), WorkerId(3)])
+        );
+
+        // The blacklist applies within the allowlist
+        scheduler.set_blacklist(vec![WorkerId(3)]);
+        assert_eq!(
+            selections(&scheduler, 4).await,
+            HashSet::from([WorkerId(2)])
+        );
     }
 
-    // Helper to create a scheduling request
-    struct WorkerOverlap {
-        worker_id: i64,
-        overlap_blocks: u32,
+    #[tokio::test]
+    async fn test_lists_exclude_every_worker() {
+        let (_endpoints_tx, scheduler) = start_listed_pool().await;
+        scheduler.set_allowlist(Some(vec![WorkerId(1)]));
+        scheduler.set_blacklist(vec![WorkerId(1)]);
+        let result = scheduler.schedule(OverlapScores::default(), 100).await;
+        assert!(matches!(result, Err(KvSchedulerError::NoEndpoints)));
+
+        // An allowlist naming no known worker empties the pool too
+        scheduler.set_blacklist(vec![]);
+        scheduler.set_allowlist(Some(vec![WorkerId(7)]));
+        let result = scheduler.schedule(OverlapScores::default(), 100).await;
+        assert!(matches!(result, Err(KvSchedulerError::NoEndpoints)));
     }
-    fn create_request(overlaps: Vec<WorkerOverlap>, isl_tokens: usize) -> SchedulingRequest {
-        SchedulingRequest {
-            isl_tokens,
-            overlap: OverlapScores {
-                scores: overlaps
-                    .into_iter()
-                    .map(|wo| (wo.worker_id, wo.overlap_blocks))
-                    .collect(),
-                frequencies: vec![],
-            },
-            resp_tx: tokio::sync::oneshot::channel().0,
+
+    fn overlap_on(worker_id: i64) -> OverlapScores {
+        OverlapScores {
+            scores: HashMap::from([(WorkerId(worker_id), 1.0)]),
+            ..Default::default()
         }
     }
 
+    fn cached_worker(cache: &mut OverlapCache, prefix_hash: u64, now: Instant) -> Option<WorkerId> {
+        cache
+            .get(prefix_hash, now)
+            .map(|scores| *scores.scores.keys().next().unwrap())
+    }
+
     #[test]
-    fn test_select_worker_basic() {
-        // Setup workers
+    fn test_overlap_cache_hit_and_miss() {
+        let mut cache = OverlapCache::new(4, Duration::from_secs(10));
+        let now = Instant::now();
+
+        // Miss: the request's own scores are cached
+        let mut request = create_request(vec![], 100);
+        request.prefix_hash = Some(1);
+        request.overlap = overlap_on(7);
+        cache.resolve(&mut request, now);
+        assert_eq!(cached_worker(&mut cache, 1, now), Some(WorkerId(7)));
+
+        // Hit: the cached scores replace the request's
+        let mut request = create_request(vec![], 100);
+        request.prefix_hash = Some(1);
+        request.overlap = overlap_on(8);
+        cache.resolve(&mut request, now);
+        assert_eq!(request.overlap.scores, overlap_on(7).scores);
+
+        // Requests without a prefix hash bypass the cache
+        let mut request = create_request(vec![], 100);
+        request.overlap = overlap_on(9);
+        cache.resolve(&mut request, now);
+        assert_eq!(request.overlap.scores, overlap_on(9).scores);
+        assert_eq!(cache.entries.len(), 1);
+    }
+
+    #[test]
+    fn test_overlap_cache_eviction() {
+        let mut cache = OverlapCache::new(2, Duration::from_secs(10));
+        let now = Instant::now();
+
+        cache.insert(1, overlap_on(1), now);
+        cache.insert(2, overlap_on(2), now);
+        // Touch 1 so 2 is the least recently used
+        assert_eq!(cached_worker(&mut cache, 1, now), Some(WorkerId(1)));
+        cache.insert(3, overlap_on(3), now);
+
+        assert_eq!(cached_worker(&mut cache, 2, now), None);
+        assert_eq!(cached_worker(&mut cache, 1, now), Some(WorkerId(1)));
+        assert_eq!(cached_worker(&mut cache, 3, now), Some(WorkerId(3)));
+        assert_eq!(cache.entries.len(), 2);
+    }
+
+    #[test]
+    fn test_overlap_cache_ttl() {
+        let mut cache = OverlapCache::new(2, Duration::from_secs(10));
+        let start = Instant::now();
+
+        cache.insert(1, overlap_on(1), start);
+        // Hits don't extend the TTL
+        assert_eq!(
+            cached_worker(&mut cache, 1, start + Duration::from_secs(9)),
+            Some(WorkerId(1))
+        );
+        assert_eq!(
+            cached_worker(&mut cache, 1, start + Duration::from_secs(10)),
+            None
+        );
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn test_selection_margin() {
         let workers = create_workers(vec![
             WorkerInfo {
                 id: 1,
-                usage: 0.50,
-                waiting: 1,
+                usage: 0.0,
+                waiting: 0,
             },
             WorkerInfo {
                 id: 2,
-                usage: 0.80,
+                usage: 0.0,
                 waiting: 0,
             },
         ]);
+        let selector = DefaultWorkerSelector::default();
 
-        // Setup request: 100 tokens, block_size=20 (5 blocks)
+        // 2 of 5 blocks cached on worker 1: logit 2.0 * 0.4 against 0.0
         let request = create_request(
-            vec![
-                WorkerOverlap {
-                    worker_id: 1,
-                    overlap_blocks: 3,
-                },
-                WorkerOverlap {
-                    worker_id: 2,
-                    overlap_blocks: 4,
-                },
-            ],
+            vec![WorkerOverlap {
+                worker_id: 1,
+                overlap_blocks: 2.0,
+            }],
             100,
         );
-        let selector = DefaultWorkerSelector::new(None);
-        let block_size = 20;
+        let result = selector.select_worker(&workers, &request, 20).unwrap();
+        assert_eq!(result.worker_id, 1);
+        assert!((result.margin - 0.8).abs() < 1e-9);
 
-        // Execute selection
-        let result = selector
-            .select_worker(&workers, &request, block_size)
-            .expect("Should select a worker");
-        // Worker 2 should win because:
-        // Worker1: 2.0 * 0.600 - 1.0 * 0.500 - 1.0 * 1.000 = -0.3
-        // Worker2: 2.0 * 0.800 - 1.0 * 0.800 - 1.0 * 0.000 = 0.8
-        assert_eq!(result.worker_id, 2);
-        assert_eq!(result.required_blocks, 5); // 100 tokens / 20 block_size
-        assert_eq!(result.overlap_blocks, 4);
+        // Identical workers tie
+        let request = create_request(vec![], 100);
+        let result = selector.select_worker(&workers, &request, 20).unwrap();
+        assert_eq!(result.margin, 0.0);
+
+        // A lone worker has no runner-up
+        let mut single = workers.clone();
+        single.endpoints.remove(&2);
+        let result = selector.select_worker(&single, &request, 20).unwrap();
+        assert_eq!(result.margin, 0.0);
     }
 
     #[test]
-    fn test_no_endpoints() {
-        let workers = create_workers(vec![]);
+    fn test_tie_break_epsilon() {
+        // Logits: worker1 0.0, worker2 -0.01, worker3 -0.02, worker4 -0.5
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.01,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 3,
+                usage: 0.02,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 4,
+                usage: 0.5,
+                waiting: 0,
+            },
+        ]);
         let request = create_request(vec![], 100);
-        let selector = DefaultWorkerSelector::new(None);
         let block_size = 20;
 
-        match selector.select_worker(&workers, &request, block_size) {
-            Err(KvSchedulerError::NoEndpoints) => {} // Expected
-            _ => panic!("Should return NoEndpoints error"),
+        // Without epsilon the marginally better worker always wins
+        let selector = DefaultWorkerSelector::new(None);
+        for _ in 0..50 {
+            let result = selector
+                .select_worker(&workers, &request, block_size)
+                .unwrap();
+            assert_eq!(result.worker_id, 1);
+        }
+
+        // With epsilon the three near-best workers share the traffic
+        let selector = DefaultWorkerSelector::new(Some(KvRouterConfig {
+            tie_break_epsilon: 0.05,
+            ..Default::default()
+        }));
+        let mut counts = HashMap::new();
+        for _ in 0..1000 {
+            let result = selector
+                .select_worker(&workers, &request, block_size)
+                .unwrap();
+            *counts.entry(result.worker_id).or_insert(0) += 1;
         }
+        assert!(!counts.contains_key(&4));
+        // Weights are 0.05, 0.04 and 0.03, so the closer a worker is to the best the more it wins
+        assert!(counts[&1] > counts[&3]);
+        assert!(counts[&2] > 0);
+        assert!(counts[&3] > 0);
     }
 
-    #[test]
-    fn test_no_overlap_scores() {
-        // Workers exist but request has no overlap scores
+    /// A sink whose publisher never gets past the first event.
+    #[derive(Clone, Default)]
+    struct StalledSink {
+        published: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl EventSink for StalledSink {
+        async fn publish(&self, _event: &SchedulerEvent) -> anyhow::Result<()> {
+            self.published.fetch_add(1, Ordering::SeqCst);
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_event_channel_overflow() {
         let workers = create_workers(vec![WorkerInfo {
             id: 1,
-            usage: 0.50,
-            waiting: 1,
+            usage: 0.0,
+            waiting: 0,
         }]);
-        let request = create_request(vec![], 100); // No overlaps
-        let selector = DefaultWorkerSelector::new(None);
-        let block_size = 20;
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let sink = StalledSink::default();
+        let scheduler = KvScheduler::builder()
+            .event_sink(sink.clone())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .event_channel_capacity(2)
+            .start()
+            .await
+            .expect("Should start scheduler");
 
-        let result = selector
-            .select_worker(&workers, &request, block_size)
-            .expect("Should fallback to selecting worker");
+        // Routing carries on while the publisher is stuck
+        for _ in 0..10 {
+            let worker_id = scheduler
+                .schedule(OverlapScores::default(), 100)
+                .await
+                .unwrap();
+            assert_eq!(worker_id, 1);
+        }
 
-        // Worker1 should be selected with 0 overlap
-        assert_eq!(result.worker_id, 1);
-        assert_eq!(result.overlap_blocks, 0);
+        // At most one event reached the sink and two are queued behind it
+        assert!(sink.published.load(Ordering::SeqCst) <= 1);
+        assert!(scheduler.dropped_events() >= 7);
+        assert!(scheduler.dropped_events() <= 8);
+
+        let (_endpoints_tx, endpoints_rx) =
+            tokio::sync::watch::channel(ProcessedEndpoints::default());
+        let result = KvScheduler::builder()
+            .event_sink(StalledSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .event_channel_capacity(0)
+            .start()
+            .await;
+        assert!(matches!(result, Err(KvSchedulerError::InvalidConfig(_))));
     }
 
-    #[test]
-    fn test_custom_weights() {
-        // Setup workers
+    #[tokio::test]
+    async fn test_state_endpoint() {
+        use dynamo_runtime::pipeline::Context;
+        use futures::StreamExt;
+
         let workers = create_workers(vec![
             WorkerInfo {
                 id: 1,
-                usage: 0.50,
-                waiting: 1,
+                usage: 0.0,
+                waiting: 0,
             },
             WorkerInfo {
                 id: 2,
-                usage: 0.80,
-                waiting: 0,
+                usage: 0.5,
+                waiting: 1,
             },
         ]);
-
-        // Custom config with high priority on GPU usage
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
         let config = KvRouterConfig {
-            gpu_cache_usage_weight: 10.0, // Very high weight
-            overlap_score_weight: 2.0,    // just current defaults
-            waiting_requests_weight: 1.0,
+            overlap_score_weight: 3.0,
+            ..Default::default()
         };
-        let selector = DefaultWorkerSelector::new(Some(config));
-        let request = create_request(
-            vec![
-                WorkerOverlap {
-                    worker_id: 1,
-                    overlap_blocks: 3,
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(config.clone())
+            .channel_capacity(16)
+            .start()
+            .await
+            .expect("Should start scheduler");
+        let handler = StateEndpointHandler {
+            source: scheduler.state_source(),
+        };
+        let query = || async {
+            let responses: Vec<_> = handler
+                .generate(Context::new(()))
+                .await
+                .unwrap()
+                .collect()
+                .await;
+            assert_eq!(responses.len(), 1);
+            // The endpoint carries snapshots as JSON
+            let json = serde_json::to_value(responses[0].data.as_ref().unwrap()).unwrap();
+            serde_json::from_value::<SchedulerStateSnapshot>(json).unwrap()
+        };
+
+        // 3 of 5 blocks cached on worker 1
+        let overlap = OverlapScores::from_block_counts(HashMap::from([(WorkerId(1), 3)]));
+        assert_eq!(scheduler.schedule(overlap, 100).await.unwrap(), 1);
+
+        let snapshot = tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                let snapshot = query().await;
+                let predicted = snapshot
+                    .workers
+                    .get(&1)
+                    .is_some_and(|stats| stats.kv_active_blocks_delta != 0);
+                if predicted && snapshot.hit_rates.contains_key(&1) {
+                    return snapshot;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("State should be published");
+
+        assert_eq!(snapshot.config.overlap_score_weight, 3.0);
+        assert_eq!(snapshot.queue_len, 0);
+        assert_eq!(snapshot.queue_capacity, 16);
+        assert_eq!(snapshot.load, 1);
+        assert_eq!(snapshot.dropped_events, 0);
+        assert!((snapshot.hit_rates[&1] - 0.6).abs() < 1e-9);
+        assert_eq!(snapshot.workers.len(), 2);
+        assert_eq!(snapshot.workers[&1].kv_active_blocks_delta, 2);
+        assert_eq!(snapshot.workers[&1].num_requests_waiting_delta, 1);
+        assert_eq!(snapshot.workers[&2].metrics.num_requests_waiting, 1);
+        assert_eq!(snapshot.workers[&2].kv_active_blocks_delta, 0);
+
+        // The snapshot agrees with the scheduler's own accessors
+        let local = scheduler.state_snapshot();
+        assert_eq!(local.load, scheduler.load());
+        assert_eq!(local.hit_rates, scheduler.hit_rates());
+    }
+
+    #[tokio::test]
+    async fn test_pressure() {
+        let idle = || {
+            create_workers(vec![
+                WorkerInfo {
+                    id: 1,
+                    usage: 0.0,
+                    waiting: 0,
                 },
-                WorkerOverlap {
-                    worker_id: 2,
-                    overlap_blocks: 4,
+                WorkerInfo {
+                    id: 2,
+                    usage: 0.0,
+                    waiting: 0,
                 },
-            ],
-            100,
+            ])
+        };
+        let (endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(idle());
+        let scheduler = Arc::new(
+            KvScheduler::builder()
+                .event_sink(RecordingSink::default())
+                .block_size(20)
+                .endpoints(endpoints_rx)
+                .channel_capacity(2)
+                .start()
+                .await
+                .expect("Should start scheduler"),
         );
-        let block_size = 20;
+        assert_eq!(scheduler.pressure(), 0.0);
 
-        let result = selector
-            .select_worker(&workers, &request, block_size)
-            .expect("Should select worker");
+        let updates = scheduler.pressure_updates();
+        let wait_for_pressure = |predicate: fn(f64) -> bool| {
+            let mut updates = updates.clone();
+            async move {
+                let pressure = tokio::time::timeout(
+                    Duration::from_secs(1),
+                    updates.wait_for(|pressure| predicate(*pressure)),
+                )
+                .await
+                .expect("Pressure should be published")
+                .unwrap();
+                *pressure
+            }
+        };
 
-        assert_eq!(result.worker_id, 1);
+        // Full caches and saturated waiting queues on every worker
+        endpoints_tx
+            .send(create_workers(vec![
+                WorkerInfo {
+                    id: 1,
+                    usage: 1.0,
+                    waiting: 8,
+                },
+                WorkerInfo {
+                    id: 2,
+                    usage: 0.9,
+                    waiting: 20,
+                },
+            ]))
+            .unwrap();
+        // (1.0 * 0.0 + 1.0 * 0.95 + 1.0 * 1.0) / 3
+        let pressure = wait_for_pressure(|pressure| pressure > 0.5).await;
+        assert!((pressure - 0.65).abs() < 1e-6);
+
+        // A full scheduling queue saturates the remaining term
+        scheduler.pause();
+        let pending: Vec<_> = (0..2)
+            .map(|_| {
+                let scheduler = scheduler.clone();
+                tokio::spawn(async move { scheduler.schedule(OverlapScores::default(), 100).await })
+            })
+            .collect();
+        let pressure = wait_for_pressure(|pressure| pressure > 0.95).await;
+        assert!(pressure > 0.95 && pressure <= 1.0);
+
+        // Once the queue drains and the workers go idle, pressure falls back to zero
+        scheduler.resume();
+        for pending in pending {
+            pending.await.unwrap().unwrap();
+        }
+        endpoints_tx.send(idle()).unwrap();
+        assert_eq!(wait_for_pressure(|pressure| pressure < 0.05).await, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_schedule_excluding() {
+        let (_endpoints_tx, scheduler) = start_listed_pool().await;
+        let mut selected = HashSet::new();
+        for _ in 0..12 {
+            selected.insert(
+                scheduler
+                    .schedule_excluding(OverlapScores::default(), 100, &[WorkerId(1)])
+                    .await
+                    .unwrap(),
+            );
+        }
+        assert_eq!(selected, HashSet::from([WorkerId(2), WorkerId(3)]));
+
+        // The exclusion only applied to those attempts
+        assert!(selections(&scheduler, 12).await.contains(&1));
+    }
+
+    #[tokio::test]
+    async fn test_schedule_excluding_everyone() {
+        let (_endpoints_tx, scheduler) = start_listed_pool().await;
+
+        // Excluding every worker falls back to all of them
+        let worker_id = scheduler
+            .schedule_excluding(
+                OverlapScores::default(),
+                100,
+                &[WorkerId(1), WorkerId(2), WorkerId(3)],
+            )
+            .await
+            .unwrap();
+        assert!((1..=3).contains(&worker_id.0));
+
+        // With the others blacklisted, the excluded worker is the only one left
+        scheduler.set_blacklist(vec![WorkerId(2), WorkerId(3)]);
+        for _ in 0..3 {
+            let worker_id = scheduler
+                .schedule_excluding(OverlapScores::default(), 100, &[WorkerId(1)])
+                .await
+                .unwrap();
+            assert_eq!(worker_id, 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_completion_stream() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let (completions_tx, completions_rx) = tokio::sync::mpsc::channel(16);
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .completions(completions_rx)
+            .start()
+            .await
+            .expect("Should start scheduler");
+        let scheduler = &scheduler;
+        let wait_for_blocks = |blocks: i64| async move {
+            tokio::time::timeout(Duration::from_secs(1), async {
+                loop {
+                    let delta = scheduler.worker_stats()[&1].kv_active_blocks_delta;
+                    if delta == blocks {
+                        return;
+                    }
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("Predicted load should be published");
+        };
+
+        // Two requests of 5 new blocks each
+        for _ in 0..2 {
+            scheduler
+                .schedule(OverlapScores::default(), 100)
+                .await
+                .unwrap();
+        }
+        wait_for_blocks(10).await;
+
+        // Unknown workers are ignored, and completions apply in order of arrival
+        completions_tx.send((WorkerId(7), 4)).await.unwrap();
+        completions_tx.send((WorkerId(1), 4)).await.unwrap();
+        wait_for_blocks(6).await;
+        assert!(!scheduler.worker_stats().contains_key(&7));
+
+        // Freeing more than is predicted stops at zero
+        completions_tx.send((WorkerId(1), 100)).await.unwrap();
+        wait_for_blocks(0).await;
+
+        // The scheduler keeps running after the stream closes
+        drop(completions_tx);
+        assert_eq!(
+            scheduler
+                .schedule(OverlapScores::default(), 100)
+                .await
+                .unwrap(),
+            1
+        );
+        wait_for_blocks(5).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idempotent_retry() {
+        let (_endpoints_tx, scheduler) = start_listed_pool().await;
+        let schedule = |key: &str| {
+            let options = ScheduleOptions {
+                idempotency_key: Some(key.to_string()),
+                ..Default::default()
+            };
+            scheduler.schedule_with_options(OverlapScores::default(), 100, options)
+        };
+
+        let first = schedule("request-1").await.unwrap();
+        let waiting = |stats: HashMap<WorkerId, WorkerStats>| -> i64 {
+            stats
+                .values()
+                .map(|stats| stats.num_requests_waiting_delta)
+                .sum()
+        };
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(waiting(scheduler.worker_stats()), 1);
+
+        // Retries within the window get the same worker and add no predicted load, even
+        // though the worker is now the busiest
+        for _ in 0..3 {
+            assert_eq!(schedule("request-1").await.unwrap(), first);
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(waiting(scheduler.worker_stats()), 1);
+
+        // Other keys are scheduled as usual
+        let second = schedule("request-2").await.unwrap();
+        assert_ne!(second, first);
+
+        // After the window the key is scheduled afresh, away from the two loaded workers
+        tokio::time::advance(Duration::from_millis(5_000)).await;
+        let retried = schedule("request-1").await.unwrap();
+        assert_ne!(retried, first);
+        assert_ne!(retried, second);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(waiting(scheduler.worker_stats()), 3);
+    }
+
+    /// Selects like the default selector, but hangs on requests longer than 1000 tokens.
+    struct SlowSelector;
+
+    #[async_trait]
+    impl AsyncWorkerSelector for SlowSelector {
+        async fn select_worker(
+            &self,
+            workers: &ProcessedEndpoints,
+            request: &SchedulingRequest,
+            block_size: usize,
+        ) -> Result<WorkerSelectionResult, KvSchedulerError> {
+            if request.isl_tokens > 1000 {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+            }
+            DefaultWorkerSelector::new(None).select_worker(workers, request, block_size)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_selection_timeout() {
+        let workers = create_workers(vec![WorkerInfo {
+            id: 1,
+            usage: 0.0,
+            waiting: 0,
+        }]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let config = KvRouterConfig {
+            selection_timeout_ms: Some(100),
+            ..Default::default()
+        };
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(config)
+            .async_selector(SlowSelector)
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        let started = Instant::now();
+        let result = scheduler.schedule(OverlapScores::default(), 2000).await;
+        assert!(matches!(
+            result,
+            Err(KvSchedulerError::SelectionTimeout(timeout)) if timeout == Duration::from_millis(100)
+        ));
+        assert!(started.elapsed() < Duration::from_secs(60));
+
+        // The timed out selection didn't wedge the loop
+        for _ in 0..3 {
+            assert_eq!(
+                scheduler
+                    .schedule(OverlapScores::default(), 100)
+                    .await
+                    .unwrap(),
+                1
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_export_and_restore_state() {
+        let workers = |ids: &[i64]| {
+            create_workers(
+                ids.iter()
+                    .map(|&id| WorkerInfo {
+                        id,
+                        usage: 0.0,
+                        waiting: 0,
+                    })
+                    .collect(),
+            )
+        };
+        let config = KvRouterConfig {
+            session_ttl_ms: 60_000,
+            ..Default::default()
+        };
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers(&[1, 2, 3]));
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(config.clone())
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        scheduler.set_blacklist(vec![WorkerId(3)]);
+        let session_options = || ScheduleOptions {
+            session_id: Some("session-1".to_string()),
+            ..Default::default()
+        };
+        let session = scheduler
+            .schedule_with_options(OverlapScores::default(), 100, session_options())
+            .await
+            .unwrap();
+        let other = scheduler
+            .schedule(OverlapScores::default(), 100)
+            .await
+            .unwrap();
+        assert_eq!(
+            HashSet::from([session, other]),
+            HashSet::from([WorkerId(1), WorkerId(2)])
+        );
+
+        let state = scheduler.export_state().await.unwrap();
+        let loaded = PredictedLoad {
+            kv_active_blocks_delta: 5,
+            num_requests_waiting_delta: 1,
+        };
+        assert_eq!(state.predicted[&1], loaded);
+        assert_eq!(state.predicted[&2], loaded);
+        assert_eq!(state.predicted[&3], PredictedLoad::default());
+        assert_eq!(state.sessions["session-1"].worker_id, session);
+        let json = serde_json::to_string(&state).unwrap();
+        assert_eq!(
+            serde_json::from_str::<SchedulerState>(&json).unwrap(),
+            state
+        );
+        drop(scheduler);
+
+        // Restart with worker 1 kept, worker 4 new and workers 2 and 3 gone
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers(&[1, 4]));
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .start_with_state(state)
+            .await
+            .expect("Should start scheduler");
+
+        let stats = scheduler.worker_stats();
+        assert_eq!(stats[&1].kv_active_blocks_delta, 5);
+        assert_eq!(stats[&1].num_requests_waiting_delta, 1);
+        assert_eq!(stats[&4].kv_active_blocks_delta, 0);
+        assert_eq!(stats[&4].num_requests_waiting_delta, 0);
+
+        let restored = scheduler.export_state().await.unwrap();
+        assert_eq!(restored.config, config);
+        assert_eq!(restored.blacklist, HashSet::from([WorkerId(3)]));
+        assert!(!restored.predicted.contains_key(&2));
+        assert_eq!(
+            restored.sessions.get("session-1").map(|s| s.worker_id),
+            (session == 1).then_some(WorkerId(1))
+        );
+
+        // The carried over load steers new requests to the fresh worker
+        assert_eq!(
+            scheduler
+                .schedule(OverlapScores::default(), 100)
+                .await
+                .unwrap(),
+            4
+        );
+    }
+
+    #[tokio::test]
+    async fn test_probe() {
+        let workers = create_workers(vec![
+            WorkerInfo {
+                id: 1,
+                usage: 0.0,
+                waiting: 0,
+            },
+            WorkerInfo {
+                id: 2,
+                usage: 0.9,
+                waiting: 0,
+            },
+        ]);
+        let (endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let config = KvRouterConfig {
+            gpu_cache_usage_hard_cap: 0.8,
+            ..Default::default()
+        };
+        let scheduler = KvScheduler::builder()
+            .event_sink(RecordingSink::default())
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .config(config)
+            .start()
+            .await
+            .expect("Should start scheduler");
+
+        for _ in 0..3 {
+            match scheduler.probe(OverlapScores::default(), 100).await {
+                Ok(ProbeResult::Available(selection)) => assert_eq!(selection.worker_id, 1),
+                other => panic!("Should report worker 1 available, got {other:?}"),
+            }
+        }
+        // Probing applied nothing
+        assert_eq!(scheduler.load(), 0);
+        assert!(scheduler
+            .worker_stats()
+            .values()
+            .all(|stats| stats.num_requests_waiting_delta == 0));
+
+        endpoints_tx.send_modify(|workers| {
+            workers
+                .endpoints
+                .get_mut(&1)
+                .unwrap()
+                .data
+                .gpu_cache_usage_perc = 0.9;
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        // With nothing outstanding, the wait is one service time of the probed request
+        let service_time = LinearServiceTimeModel::default().estimate(100, 0.0);
+        match scheduler.probe(OverlapScores::default(), 100).await {
+            Ok(ProbeResult::Wait(wait)) => assert_eq!(wait, service_time),
+            other => panic!("Should report a wait, got {other:?}"),
+        }
+        assert!(matches!(
+            scheduler.probe(OverlapScores::default(), 0).await,
+            Err(KvSchedulerError::InvalidRequest(_))
+        ));
     }
 }
@@ -0,0 +1,746 @@
+// SPDX-FileCopyrightText: Copyright (c) 2024-2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The default worker selector: a weighted cost over each worker's cached overlap and load.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::time::Instant;
+
+use super::{Endpoint, KvSchedulerError, SchedulingRequest};
+use crate::kv_router::indexer::OverlapScores;
+use crate::kv_router::protocols::{
+    ForwardPassMetrics, WorkerId, WorkerRole, WorkerSelectionResult,
+};
+use crate::kv_router::scoring::ProcessedEndpoints;
+use crate::kv_router::{KvRouterConfig, TieBreakPolicy, WorkerSelector};
+
+/// Why [`WorkerSelector::explain_selection`] routed a request where it did.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SelectionExplanation {
+    /// Every eligible worker, in worker id order
+    pub candidates: Vec<CandidateExplanation>,
+    /// Workers within `tie_break_epsilon` of the best logit, in worker id order. The selected
+    /// worker is one of them.
+    pub tied: Vec<WorkerId>,
+    /// Whether the selected worker was drawn at random among `tied`, rather than being the only
+    /// one or picked by a non-random tie break policy
+    pub random_tie_break: bool,
+}
+
+/// How one candidate worker was scored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CandidateExplanation {
+    pub worker_id: WorkerId,
+    /// Cached blocks as a fraction of the request's blocks
+    pub score: f64,
+    pub gpu_cache_usage: f64,
+    /// Waiting requests relative to the busiest candidate
+    pub normalized_waiting: f64,
+    /// Throughput relative to the fastest candidate
+    pub normalized_throughput: f64,
+    /// Free blocks relative to the emptiest candidate
+    pub normalized_free_blocks: f64,
+    /// Age of the oldest waiting request relative to the stalest candidate's
+    pub normalized_queue_age: f64,
+    /// Remaining fraction of the worker's warmup
+    pub warmup: f64,
+    /// What is left of the worker's selection cooldown, from 1.0 just after it was selected
+    pub cooldown: f64,
+    /// Fairness penalty for the request's tenant
+    pub fairness: f64,
+    /// Topology distance from the request's zone
+    pub locality: f64,
+    /// Soft quota penalty for the request's tenant: its overage, scaled by how strongly the
+    /// worker is otherwise preferred
+    pub quota: f64,
+    pub contributions: LogitContributions,
+    /// Sum of the contributions
+    pub logit: f64,
+    /// Whether the worker was excluded by `gpu_cache_usage_hard_cap` or by running its
+    /// `max_concurrent` sequences, whatever its logit
+    pub capped: bool,
+}
+
+/// Each term's weighted, signed contribution to a worker's logit.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LogitContributions {
+    pub overlap: f64,
+    pub gpu_cache_usage: f64,
+    pub waiting: f64,
+    pub throughput: f64,
+    pub free_blocks: f64,
+    pub queue_age: f64,
+    pub warmup: f64,
+    pub cooldown: f64,
+    pub fairness: f64,
+    pub locality: f64,
+    pub quota: f64,
+}
+
+impl LogitContributions {
+    pub fn total(&self) -> f64 {
+        self.overlap
+            + self.gpu_cache_usage
+            + self.waiting
+            + self.throughput
+            + self.free_blocks
+            + self.queue_age
+            + self.warmup
+            + self.cooldown
+            + self.fairness
+            + self.locality
+            + self.quota
+    }
+}
+
+// Default implementation matching the Python _cost_function
+#[derive(Debug, Clone, Default)]
+pub struct DefaultWorkerSelector {
+    pub kv_router_config: KvRouterConfig,
+    /// Seeded source for tie breaking; the thread RNG is used when unset.
+    rng: Option<Arc<Mutex<StdRng>>>,
+    /// Worker last picked by [`TieBreakPolicy::RoundRobin`]
+    round_robin: Arc<Mutex<Option<WorkerId>>>,
+    /// Overlap scores seen for workers missing from the endpoints
+    stale_overlaps: Arc<AtomicU64>,
+    /// Index among the tied workers of each random tie break, if recording
+    tie_breaks: Option<Arc<Mutex<Vec<usize>>>>,
+}
+
+impl DefaultWorkerSelector {
+    pub fn new(kv_router_config: Option<KvRouterConfig>) -> Self {
+        Self {
+            kv_router_config: kv_router_config.unwrap_or_default(),
+            rng: None,
+            round_robin: Arc::default(),
+            stale_overlaps: Arc::default(),
+            tie_breaks: None,
+        }
+    }
+
+    /// Like [`DefaultWorkerSelector::new`], but ties are broken with an RNG seeded from `seed`,
+    /// so the same inputs always produce the same selections.
+    pub fn with_seed(kv_router_config: Option<KvRouterConfig>, seed: u64) -> Self {
+        Self {
+            kv_router_config: kv_router_config.unwrap_or_default(),
+            rng: Some(Arc::new(Mutex::new(StdRng::seed_from_u64(seed)))),
+            round_robin: Arc::default(),
+            stale_overlaps: Arc::default(),
+            tie_breaks: None,
+        }
+    }
+
+    /// This selector with its tie breaking pinned for a replay session: a fresh RNG seeded from
+    /// `seed`, the round robin position reset, and every random tie break recorded in
+    /// [`DefaultWorkerSelector::tie_breaks`]. Replaying the same captured input through it, as
+    /// with [`crate::kv_router::simulation::simulate`], reproduces the same selections.
+    pub fn into_deterministic(self, seed: u64) -> Self {
+        Self {
+            rng: Some(Arc::new(Mutex::new(StdRng::seed_from_u64(seed)))),
+            round_robin: Arc::default(),
+            tie_breaks: Some(Arc::default()),
+            ..self
+        }
+    }
+
+    /// Index into the tied workers, in worker id order, of each random tie break made since
+    /// [`DefaultWorkerSelector::into_deterministic`]. Empty unless recording.
+    pub fn tie_breaks(&self) -> Vec<usize> {
+        self.tie_breaks
+            .as_ref()
+            .map_or_else(Vec::new, |tie_breaks| {
+                tie_breaks
+                    .lock()
+                    .expect("tie break log lock poisoned")
+                    .clone()
+            })
+    }
+
+    /// Overlap scores that referenced a worker absent from the endpoints, over all selections
+    /// so far. A steadily rising count means the indexer and the endpoint view are diverging.
+    pub fn stale_overlap_references(&self) -> u64 {
+        self.stale_overlaps.load(Ordering::Relaxed)
+    }
+
+    /// Select a worker for a request of `isl_tokens` with `overlap`, as
+    /// [`WorkerSelector::select_worker`] would for a request with default
+    /// [`ScheduleOptions`](super::ScheduleOptions), without a scheduler or any channels.
+    pub fn score_and_select(
+        &self,
+        workers: &ProcessedEndpoints,
+        overlap: &OverlapScores,
+        isl_tokens: usize,
+        block_size: usize,
+    ) -> Result<WorkerSelectionResult, KvSchedulerError> {
+        let request = SchedulingRequest::detached(overlap.clone(), isl_tokens);
+        self.select(workers, &request, block_size)
+    }
+
+    fn select(
+        &self,
+        workers: &ProcessedEndpoints,
+        request: &SchedulingRequest,
+        block_size: usize,
+    ) -> Result<WorkerSelectionResult, KvSchedulerError> {
+        self.count_stale_overlaps(workers, request);
+        let logits = self.logits(workers, request, block_size)?;
+        let (selection, _, _) = self.choose(workers, &logits, request, block_size)?;
+        Ok(selection)
+    }
+
+    /// Log and count the overlap scores of `request` for workers that aren't in `workers`.
+    /// They score nothing either way; this only makes them visible.
+    fn count_stale_overlaps(&self, workers: &ProcessedEndpoints, request: &SchedulingRequest) {
+        let stale: Vec<WorkerId> = request
+            .overlap
+            .scores
+            .keys()
+            .filter(|worker_id| !workers.endpoints.contains_key(*worker_id))
+            .copied()
+            .collect();
+        if stale.is_empty() {
+            return;
+        }
+        tracing::debug!("ignoring overlap scores for unknown workers {stale:?}");
+        self.stale_overlaps
+            .fetch_add(stale.len() as u64, Ordering::Relaxed);
+    }
+
+    /// How every eligible worker scores for `request`, capped ones included, in worker id order.
+    fn score_workers(
+        &self,
+        workers: &ProcessedEndpoints,
+        request: &SchedulingRequest,
+        block_size: usize,
+    ) -> Result<Vec<CandidateExplanation>, KvSchedulerError> {
+        if request.isl_tokens == 0 {
+            return Err(KvSchedulerError::InvalidRequest(
+                "isl_tokens must be greater than zero".to_string(),
+            ));
+        }
+        let block_size = request.block_size.unwrap_or(block_size);
+
+        // Only workers serving the requested role are candidates
+        let mut eligible: Vec<(&WorkerId, &Endpoint)> = workers
+            .endpoints
+            .iter()
+            .filter(|(_, ep)| ep.data.role.serves(request.role))
+            .collect();
+        if eligible.is_empty() {
+            return Err(KvSchedulerError::NoEndpoints);
+        }
+        eligible.sort_unstable_by_key(|(worker_id, _)| **worker_id);
+        // Decode workers don't prefill, so cached blocks don't matter; route on load alone.
+        // The same goes for requests too short for their overlap to mean much.
+        let use_overlap = request.role != WorkerRole::Decode
+            && request.isl_tokens / block_size >= self.kv_router_config.min_blocks_for_kv_routing;
+
+        let mut worker_scores = HashMap::new();
+        let mut max_waiting = 0.0;
+        let mut max_throughput = 0.0;
+        let mut max_free_blocks = 0.0;
+        let mut max_queue_age = 0.0;
+
+        // Calculate worker scores and find max waiting requests
+        for (worker_id, ep) in eligible.iter().copied() {
+            // Calculate score similar to Python version
+            if let Some(score) = request
+                .overlap
+                .weighted_score(worker_id, self.kv_router_config.recency_weight)
+                .filter(|_| use_overlap)
+            {
+                let block_size = worker_block_size(ep, block_size);
+                let request_blocks = required_blocks(
+                    request.isl_tokens,
+                    block_size,
+                    self.kv_router_config.min_blocks_per_request,
+                ) as f64;
+                let score = score / request_blocks;
+                worker_scores.insert(worker_id, score);
+            }
+
+            // Track max waiting requests
+            max_waiting = f64::max(max_waiting, waiting_requests(workers, *worker_id, ep));
+
+            // Track max throughput
+            let throughput = workers.throughput.get(worker_id).copied().unwrap_or(0.0);
+            max_throughput = f64::max(max_throughput, throughput);
+
+            // Track max free blocks
+            max_free_blocks = f64::max(max_free_blocks, free_blocks(&ep.data));
+
+            // Track the stalest queue
+            max_queue_age = f64::max(max_queue_age, queue_age(&ep.data));
+        }
+
+        // make immutable
+        let worker_scores = worker_scores;
+        let max_waiting = max_waiting;
+        let max_throughput = max_throughput;
+        let max_free_blocks = max_free_blocks;
+        let max_queue_age = max_queue_age;
+        let config = &self.kv_router_config;
+        let waiting_scale = f64::max(max_waiting, config.waiting_normalization_floor);
+        let now = Instant::now();
+
+        // Calculate logits for each worker
+        let mut candidates = Vec::with_capacity(eligible.len());
+
+        for (worker_id, ep) in eligible.iter().copied() {
+            let worker_id = *worker_id;
+
+            // Get score or default to 0.0
+            let score = worker_scores.get(&worker_id).copied().unwrap_or(0.0);
+
+            // Calculate normalized metrics
+            let gpu_cache_usage = ep.data.gpu_cache_usage_perc as f64;
+            let usage_penalty = config.gpu_cache_usage_curve.apply(gpu_cache_usage);
+            let normalized_waiting = if waiting_scale > 0.0 {
+                waiting_requests(workers, worker_id, ep) / waiting_scale
+            } else {
+                0.0
+            };
+            let normalized_throughput = if max_throughput > 0.0 {
+                workers.throughput.get(&worker_id).copied().unwrap_or(0.0) / max_throughput
+            } else {
+                0.0
+            };
+            let normalized_free_blocks = if max_free_blocks > 0.0 {
+                free_blocks(&ep.data) / max_free_blocks
+            } else {
+                0.0
+            };
+            let normalized_queue_age = if max_queue_age > 0.0 {
+                queue_age(&ep.data) / max_queue_age
+            } else {
+                0.0
+            };
+            let warmup = workers.warmup.get(&worker_id).copied().unwrap_or(0.0);
+            let cooldown = workers
+                .last_selected
+                .get(&worker_id)
+                .filter(|_| config.selection_cooldown_weight > 0.0)
+                .map_or(0.0, |selected_at| {
+                    let elapsed_ms = now.duration_since(*selected_at).as_secs_f64() * 1000.0;
+                    (-elapsed_ms / config.selection_cooldown_ms as f64).exp()
+                });
+            let fairness = workers.fairness.get(&worker_id).copied().unwrap_or(0.0);
+            let locality = workers.locality.get(&worker_id).copied().unwrap_or(0.0);
+
+            // Calculate logit using same formula as Python, plus the optional throughput, free
+            // blocks, queue age, warmup, cooldown, fairness and locality terms
+            let contributions = LogitContributions {
+                overlap: config.overlap_score_weight * score,
+                gpu_cache_usage: -(config.gpu_cache_usage_weight * usage_penalty),
+                waiting: -(config.waiting_requests_weight * normalized_waiting),
+                throughput: config.throughput_weight * normalized_throughput,
+                free_blocks: config.free_blocks_weight * normalized_free_blocks,
+                queue_age: -(config.queue_age_weight * normalized_queue_age),
+                warmup: -(config.warmup_penalty * warmup),
+                cooldown: -(config.selection_cooldown_weight * cooldown),
+                fairness: -(config.fairness_weight * fairness),
+                locality: -(config.locality_weight * locality),
+                quota: 0.0,
+            };
+            let logit = contributions.total();
+
+            tracing::trace!(
+                "Formula for {worker_id}: {logit:.3} = {:.1} * {score:.3} - {:.1} * {usage_penalty:.3} - {:.1} * {normalized_waiting:.3} + {:.1} * {normalized_throughput:.3} + {:.1} * {normalized_free_blocks:.3} - {:.1} * {normalized_queue_age:.3} - {:.1} * {warmup:.3} - {:.1} * {cooldown:.3} - {:.1} * {fairness:.3} - {:.1} * {locality:.3}",
+                config.overlap_score_weight,
+                config.gpu_cache_usage_weight,
+                config.waiting_requests_weight,
+                config.throughput_weight,
+                config.free_blocks_weight,
+                config.queue_age_weight,
+                config.warmup_penalty,
+                config.selection_cooldown_weight,
+                config.fairness_weight,
+                config.locality_weight,
+            );
+
+            // The hard cap applies after the overlap bonus, so no overlap can override it
+            let over_cache_cap = gpu_cache_usage > config.gpu_cache_usage_hard_cap;
+            if over_cache_cap {
+                tracing::debug!(
+                    "Skipping worker {worker_id}: gpu cache usage {gpu_cache_usage:.3} exceeds hard cap {:.3}",
+                    config.gpu_cache_usage_hard_cap,
+                );
+            }
+            // Past its ceiling the engine queues internally, so treat it as busy too
+            let at_ceiling = ep
+                .data
+                .max_concurrent
+                .is_some_and(|max| ep.data.request_active_slots >= max);
+            if at_ceiling {
+                tracing::debug!(
+                    "Skipping worker {worker_id}: {} active sequences reach its ceiling of {}",
+                    ep.data.request_active_slots,
+                    ep.data.max_concurrent.unwrap_or_default(),
+                );
+            }
+            let capped = over_cache_cap || at_ceiling;
+
+            candidates.push(CandidateExplanation {
+                worker_id,
+                score,
+                gpu_cache_usage,
+                normalized_waiting,
+                normalized_throughput,
+                normalized_free_blocks,
+                normalized_queue_age,
+                warmup,
+                cooldown,
+                fairness,
+                locality,
+                quota: 0.0,
+                contributions,
+                logit,
+                capped,
+            });
+        }
+        if workers.quota_overage > 0.0 && config.quota_penalty_weight > 0.0 {
+            apply_quota_penalty(&mut candidates, workers.quota_overage, config);
+        }
+        Ok(candidates)
+    }
+
+    /// The logit of every eligible worker under the hard cap, in worker id order.
+    fn logits(
+        &self,
+        workers: &ProcessedEndpoints,
+        request: &SchedulingRequest,
+        block_size: usize,
+    ) -> Result<Vec<(WorkerId, f64)>, KvSchedulerError> {
+        uncapped_logits(&self.score_workers(workers, request, block_size)?)
+    }
+
+    /// Pick among `logits`, breaking ties within `tie_break_epsilon`. Also returns the tied
+    /// workers and whether the pick among them was random.
+    fn choose(
+        &self,
+        workers: &ProcessedEndpoints,
+        logits: &[(WorkerId, f64)],
+        request: &SchedulingRequest,
+        block_size: usize,
+    ) -> Result<(WorkerSelectionResult, Vec<WorkerId>, bool), KvSchedulerError> {
+        let use_overlap = request.role != WorkerRole::Decode;
+        let best_logit = logits
+            .iter()
+            .map(|(_, logit)| *logit)
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        // Workers within epsilon of the best logit are treated as tied
+        let epsilon = self.kv_router_config.tie_break_epsilon;
+        let mut best_workers: Vec<(WorkerId, f64)> = logits
+            .iter()
+            .copied()
+            .filter(|(_, logit)| *logit >= best_logit - epsilon)
+            .collect();
+        // Fixed order so a seeded RNG gives reproducible picks regardless of map iteration order
+        best_workers.sort_unstable_by_key(|(worker_id, _)| *worker_id);
+
+        // Return early if no valid workers found
+        if best_workers.is_empty() {
+            return Err(KvSchedulerError::NoEndpoints);
+        } else if best_logit == 0.0 {
+            tracing::debug!("best worker logit is 0");
+        }
+
+        let policy = if self.kv_router_config.deterministic {
+            TieBreakPolicy::LowestId
+        } else {
+            self.kv_router_config.tie_break_policy
+        };
+        let random = best_workers.len() > 1 && policy == TieBreakPolicy::Random;
+        let worker_id = if best_workers.len() == 1 {
+            best_workers[0].0
+        } else {
+            match policy {
+                TieBreakPolicy::Random => {
+                    let worker_id = match &self.rng {
+                        Some(rng) => {
+                            let mut rng = rng.lock().expect("tie break rng lock poisoned");
+                            break_tie(&best_workers, best_logit - epsilon, &mut *rng)
+                        }
+                        None => break_tie(&best_workers, best_logit - epsilon, &mut rand::rng()),
+                    };
+                    if let Some(tie_breaks) = &self.tie_breaks {
+                        let index = best_workers
+                            .iter()
+                            .position(|(id, _)| *id == worker_id)
+                            .expect("tie break picks a tied worker");
+                        tie_breaks
+                            .lock()
+                            .expect("tie break log lock poisoned")
+                            .push(index);
+                    }
+                    worker_id
+                }
+                TieBreakPolicy::LowestId => best_workers[0].0,
+                TieBreakPolicy::LeastLoaded => least_loaded(workers, &best_workers),
+                TieBreakPolicy::RoundRobin => {
+                    let mut last = self.round_robin.lock().expect("round robin lock poisoned");
+                    let next = best_workers
+                        .iter()
+                        .map(|(worker_id, _)| *worker_id)
+                        .find(|worker_id| last.is_none_or(|last| *worker_id > last))
+                        .unwrap_or(best_workers[0].0);
+                    *last = Some(next);
+                    next
+                }
+            }
+        };
+
+        // Lower to trace level eventually. Nice to see KV routing working for now.
+        tracing::debug!("Selected worker: {worker_id}, logit: {best_logit:.3}");
+
+        let selected_logit = best_workers
+            .iter()
+            .find(|(id, _)| *id == worker_id)
+            .map_or(best_logit, |(_, logit)| *logit);
+        let runner_up = logits
+            .iter()
+            .filter(|(id, _)| *id != worker_id)
+            .map(|(_, logit)| *logit)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let margin = if runner_up.is_finite() {
+            f64::max(selected_logit - runner_up, 0.0)
+        } else {
+            0.0
+        };
+
+        // Log selection metrics
+        let block_size = request.block_size.unwrap_or(block_size);
+        let block_size = workers
+            .endpoints
+            .get(&worker_id)
+            .map_or(block_size, |ep| worker_block_size(ep, block_size));
+        let total_blocks = required_blocks(
+            request.isl_tokens,
+            block_size,
+            self.kv_router_config.min_blocks_per_request,
+        );
+        let overlap_blocks = request
+            .overlap
+            .scores
+            .get(&worker_id)
+            .copied()
+            .filter(|_| use_overlap)
+            .unwrap_or(0.0);
+
+        let selection = WorkerSelectionResult {
+            worker_id,
+            required_blocks: total_blocks,
+            decode_blocks: decode_blocks(
+                request,
+                block_size,
+                self.kv_router_config.min_blocks_per_request,
+            ),
+            overlap_blocks,
+            margin,
+            logit: selected_logit,
+        };
+        let tied = best_workers
+            .iter()
+            .map(|(worker_id, _)| *worker_id)
+            .collect();
+        Ok((selection, tied, random))
+    }
+}
+
+/// Penalize each candidate in proportion to `overage` and where its logit sits between the
+/// worst and best candidate's, so a tenant over its soft quota is steered off the workers it
+/// would otherwise prefer.
+fn apply_quota_penalty(
+    candidates: &mut [CandidateExplanation],
+    overage: f64,
+    config: &KvRouterConfig,
+) {
+    let (worst, best) = candidates
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), candidate| {
+            (f64::min(lo, candidate.logit), f64::max(hi, candidate.logit))
+        });
+    if best <= worst {
+        return;
+    }
+    for candidate in candidates {
+        let preference = (candidate.logit - worst) / (best - worst);
+        candidate.quota = overage * preference;
+        candidate.contributions.quota = -(config.quota_penalty_weight * candidate.quota);
+        candidate.logit = candidate.contributions.total();
+        tracing::trace!(
+            "Quota penalty for {}: {:.1} * {:.3}",
+            candidate.worker_id,
+            config.quota_penalty_weight,
+            candidate.quota,
+        );
+    }
+}
+
+/// The logits of the candidates under the hard cap, or [`KvSchedulerError::AllWorkersBusy`] if
+/// every one is over it.
+fn uncapped_logits(
+    candidates: &[CandidateExplanation],
+) -> Result<Vec<(WorkerId, f64)>, KvSchedulerError> {
+    let logits: Vec<(WorkerId, f64)> = candidates
+        .iter()
+        .filter(|candidate| !candidate.capped)
+        .map(|candidate| (candidate.worker_id, candidate.logit))
+        .collect();
+    // Every eligible worker is over the cap; wait for one to free up
+    if logits.is_empty() && !candidates.is_empty() {
+        return Err(KvSchedulerError::AllWorkersBusy);
+    }
+    Ok(logits)
+}
+
+impl WorkerSelector for DefaultWorkerSelector {
+    fn kv_router_config(&self) -> Option<KvRouterConfig> {
+        Some(self.kv_router_config.clone())
+    }
+
+    fn set_kv_router_config(&mut self, config: KvRouterConfig) {
+        self.kv_router_config = config;
+    }
+
+    fn rank_workers(
+        &self,
+        workers: &ProcessedEndpoints,
+        request: &SchedulingRequest,
+        block_size: usize,
+    ) -> Result<Vec<(WorkerId, f64)>, KvSchedulerError> {
+        let mut ranking = self.logits(workers, request, block_size)?;
+        ranking.sort_unstable_by(|(a_id, a), (b_id, b)| b.total_cmp(a).then(a_id.cmp(b_id)));
+        Ok(ranking)
+    }
+
+    fn select_worker(
+        &self,
+        workers: &ProcessedEndpoints,
+        request: &SchedulingRequest,
+        block_size: usize,
+    ) -> Result<WorkerSelectionResult, KvSchedulerError> {
+        self.select(workers, request, block_size)
+    }
+
+    fn explain_selection(
+        &self,
+        workers: &ProcessedEndpoints,
+        request: &SchedulingRequest,
+        block_size: usize,
+    ) -> Result<(WorkerSelectionResult, SelectionExplanation), KvSchedulerError> {
+        let candidates = self.score_workers(workers, request, block_size)?;
+        let logits = uncapped_logits(&candidates)?;
+        let (selection, tied, random_tie_break) =
+            self.choose(workers, &logits, request, block_size)?;
+        let explanation = SelectionExplanation {
+            candidates,
+            tied,
+            random_tie_break,
+        };
+        Ok((selection, explanation))
+    }
+}
+
+/// Tokens per KV block on `endpoint`: the size it reports, or `default` if it reports none.
+pub(super) fn worker_block_size(endpoint: &Endpoint, default: usize) -> usize {
+    match endpoint.data.kv_block_size {
+        Some(size) if size > 0 => size as usize,
+        _ => default,
+    }
+}
+
+/// How long the oldest request waiting on a worker has been queued, in milliseconds, or 0.0 if
+/// the worker doesn't report it.
+fn queue_age(metrics: &ForwardPassMetrics) -> f64 {
+    metrics.oldest_waiting_age_ms.unwrap_or(0) as f64
+}
+
+/// KV blocks `request` is expected to add while decoding its expected output, beyond those its
+/// prompt occupies.
+pub(super) fn decode_blocks(
+    request: &SchedulingRequest,
+    block_size: usize,
+    min_blocks: u64,
+) -> u64 {
+    request.expected_output_tokens.map_or(0, |output_tokens| {
+        let total_tokens = request.isl_tokens.saturating_add(output_tokens);
+        required_blocks(total_tokens, block_size, min_blocks)
+            - required_blocks(request.isl_tokens, block_size, min_blocks)
+    })
+}
+
+/// Waiting requests `worker_id` is scored on: the percentile of its recent samples if one is
+/// tracked, otherwise its latest count.
+fn waiting_requests(workers: &ProcessedEndpoints, worker_id: WorkerId, endpoint: &Endpoint) -> f64 {
+    workers
+        .waiting_percentile
+        .get(&worker_id)
+        .copied()
+        .unwrap_or(endpoint.data.num_requests_waiting as f64)
+}
+
+/// KV blocks a request of `isl_tokens` occupies, at least `min_blocks`. A trailing partial
+/// block counts as a whole one, the way engines allocate, so overlap scores and predictive load
+/// share one block count whether or not the request is block aligned.
+pub(super) fn required_blocks(isl_tokens: usize, block_size: usize, min_blocks: u64) -> u64 {
+    std::cmp::max(isl_tokens.div_ceil(block_size) as u64, min_blocks)
+}
+
+/// KV blocks a worker has left, per its latest metrics and the scheduler's predictions.
+fn free_blocks(metrics: &ForwardPassMetrics) -> f64 {
+    metrics
+        .kv_total_blocks
+        .saturating_sub(metrics.kv_active_blocks) as f64
+}
+
+/// The tied worker with the fewest waiting requests, then the fewest active KV blocks, then the
+/// lowest id.
+fn least_loaded(workers: &ProcessedEndpoints, tied: &[(WorkerId, f64)]) -> WorkerId {
+    tied.iter()
+        .map(|(worker_id, _)| *worker_id)
+        .min_by_key(|worker_id| {
+            let load = workers.endpoints.get(worker_id).map_or((0, 0), |ep| {
+                (ep.data.num_requests_waiting, ep.data.kv_active_blocks)
+            });
+            (load, *worker_id)
+        })
+        .unwrap_or(tied[0].0)
+}
+
+/// Randomly pick one of the `tied` workers, weighted by how far each logit sits above `floor`.
+/// Exact ties (all weights zero) are picked uniformly.
+fn break_tie(tied: &[(WorkerId, f64)], floor: f64, rng: &mut impl Rng) -> WorkerId {
+    let weights: Vec<f64> = tied.iter().map(|(_, logit)| logit - floor).collect();
+    let total: f64 = weights.iter().sum();
+    if total.is_nan() || total <= 0.0 {
+        return tied[rng.random_range(0..tied.len())].0;
+    }
+
+    let mut target = rng.random::<f64>() * total;
+    for ((worker_id, _), weight) in tied.iter().zip(weights) {
+        if target < weight {
+            return *worker_id;
+        }
+        target -= weight;
+    }
+    // Floating point rounding can leave a sliver past the last weight
+    tied[tied.len() - 1].0
+}
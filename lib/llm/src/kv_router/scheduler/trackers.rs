@@ -0,0 +1,1056 @@
+// SPDX-FileCopyrightText: Copyright (c) 2024-2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Time-windowed bookkeeping the scheduling task keeps beside its view of the workers: what it
+//! routed where, per tenant and per session, and what it has predicted of each worker's load.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use super::{DecisionRecord, SchedulingRequest, SessionAssignment};
+use crate::kv_router::indexer::OverlapScores;
+use crate::kv_router::protocols::WorkerId;
+use crate::kv_router::scoring::ProcessedEndpoints;
+use crate::kv_router::TenantRateLimit;
+
+/// Width of the time buckets [`OutcomeWindow`] counts outcomes in.
+pub(super) const OUTCOME_BUCKET: Duration = Duration::from_millis(100);
+
+/// Estimated service of the requests routed to one worker, served one after another.
+///
+/// Each request is expected to finish one service time after the one before it, or after it
+/// was routed if the worker was idle. Requests are forgotten once they are expected to have
+/// finished, so the estimates don't depend on completions being reported.
+#[derive(Debug, Default)]
+pub(super) struct ServiceQueue {
+    /// Expected finish and service time of each request, oldest first
+    pub(super) requests: VecDeque<(Instant, Duration)>,
+}
+
+impl ServiceQueue {
+    pub(super) fn push(&mut self, service_time: Duration, now: Instant) {
+        let start = self
+            .requests
+            .back()
+            .map_or(now, |(finish, _)| (*finish).max(now));
+        self.requests
+            .push_back((start + service_time, service_time));
+    }
+
+    /// The oldest request finished, so the rest are served from `now` on.
+    pub(super) fn complete(&mut self, now: Instant) {
+        self.requests.pop_front();
+        let mut start = now;
+        for (finish, service_time) in &mut self.requests {
+            *finish = start + *service_time;
+            start = *finish;
+        }
+    }
+
+    pub(super) fn expire(&mut self, now: Instant) {
+        while self
+            .requests
+            .front()
+            .is_some_and(|(finish, _)| *finish <= now)
+        {
+            self.requests.pop_front();
+        }
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    /// Time until every request is expected to have finished.
+    pub(super) fn queue_time(&self, now: Instant) -> Duration {
+        self.requests.back().map_or(Duration::ZERO, |(finish, _)| {
+            finish.saturating_duration_since(now)
+        })
+    }
+
+    /// Time until the oldest unfinished request is expected to finish, if any is left.
+    pub(super) fn first_free(&self, now: Instant) -> Option<Duration> {
+        self.requests
+            .iter()
+            .find(|(finish, _)| *finish > now)
+            .map(|(finish, _)| finish.duration_since(now))
+    }
+}
+
+/// Successful and failed scheduling requests, counted in short time buckets.
+#[derive(Debug)]
+pub(super) struct OutcomeWindow {
+    pub(super) retention: Duration,
+    /// Start of each bucket with its successes and failures, oldest first
+    pub(super) buckets: VecDeque<(Instant, u64, u64)>,
+}
+
+impl OutcomeWindow {
+    pub(super) fn new(retention: Duration) -> Self {
+        Self {
+            retention,
+            buckets: VecDeque::new(),
+        }
+    }
+
+    pub(super) fn record(&mut self, failed: bool, now: Instant) {
+        while self
+            .buckets
+            .front()
+            .is_some_and(|(start, _, _)| now.duration_since(*start) > self.retention)
+        {
+            self.buckets.pop_front();
+        }
+        let in_last_bucket = self
+            .buckets
+            .back()
+            .is_some_and(|(start, _, _)| now.duration_since(*start) < OUTCOME_BUCKET);
+        if !in_last_bucket {
+            self.buckets.push_back((now, 0, 0));
+        }
+        let (_, succeeded, rejected) = self.buckets.back_mut().expect("bucket just ensured");
+        if failed {
+            *rejected += 1;
+        } else {
+            *succeeded += 1;
+        }
+    }
+
+    /// Fraction of the requests that ended within `window` of `now` that failed, or 0.0 if none
+    /// ended.
+    pub(super) fn rejection_rate(&self, window: Duration, now: Instant) -> f64 {
+        let (succeeded, rejected) = self
+            .buckets
+            .iter()
+            .rev()
+            .take_while(|(start, _, _)| now.duration_since(*start) <= window)
+            .fold((0, 0), |(succeeded, rejected), (_, s, r)| {
+                (succeeded + s, rejected + r)
+            });
+        let total = succeeded + rejected;
+        if total == 0 {
+            return 0.0;
+        }
+        rejected as f64 / total as f64
+    }
+}
+
+/// The latest scheduling decisions, oldest first, up to a fixed capacity.
+#[derive(Debug)]
+pub(super) struct DecisionLog {
+    pub(super) capacity: usize,
+    pub(super) records: VecDeque<DecisionRecord>,
+}
+
+impl DecisionLog {
+    pub(super) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            records: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub(super) fn record(&mut self, record: DecisionRecord) {
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+}
+
+/// Smoothing factor for the per-worker completions-per-second moving average.
+pub(super) const THROUGHPUT_EMA_ALPHA: f64 = 0.2;
+
+/// Exponential moving average of completions per second for each worker.
+#[derive(Debug, Default)]
+pub(super) struct ThroughputTracker {
+    pub(super) last_completion: HashMap<WorkerId, Instant>,
+    pub(super) rates: HashMap<WorkerId, f64>,
+}
+
+impl ThroughputTracker {
+    pub(super) fn record(&mut self, worker_id: WorkerId, now: Instant) {
+        let Some(last) = self.last_completion.insert(worker_id, now) else {
+            return;
+        };
+        let elapsed = now.duration_since(last).as_secs_f64();
+        if elapsed <= 0.0 {
+            return;
+        }
+        let sample = 1.0 / elapsed;
+        let rate = self.rates.entry(worker_id).or_insert(sample);
+        *rate = THROUGHPUT_EMA_ALPHA * sample + (1.0 - THROUGHPUT_EMA_ALPHA) * *rate;
+    }
+}
+
+/// Count of the logits selections were made on, per bucket since the scheduler started.
+pub(super) struct LogitHistogram {
+    /// Upper bound of each bucket, ascending, the last one unbounded
+    pub(super) bounds: Vec<f64>,
+    pub(super) tx: tokio::sync::watch::Sender<Vec<(f64, u64)>>,
+}
+
+impl LogitHistogram {
+    pub(super) fn new(mut bounds: Vec<f64>) -> Self {
+        bounds.sort_by(f64::total_cmp);
+        bounds.dedup();
+        bounds.push(f64::INFINITY);
+        let (tx, _) = tokio::sync::watch::channel(bounds.iter().map(|&b| (b, 0)).collect());
+        Self { bounds, tx }
+    }
+
+    pub(super) fn record(&self, logit: f64) {
+        let bucket = self.bounds.partition_point(|&bound| bound < logit);
+        self.tx.send_modify(|buckets| buckets[bucket].1 += 1);
+    }
+}
+
+/// Token buckets of the tenants with a [`TenantRateLimit`], created full on a tenant's first
+/// request.
+#[derive(Default)]
+pub(super) struct TenantRateLimiter {
+    /// Tokens left in each tenant's bucket and when they were last refilled
+    pub(super) buckets: HashMap<String, (f64, Instant)>,
+}
+
+impl TenantRateLimiter {
+    /// Take a token from `tenant_id`'s bucket, returning whether there was one.
+    pub(super) fn try_acquire(
+        &mut self,
+        tenant_id: &str,
+        limit: TenantRateLimit,
+        now: Instant,
+    ) -> bool {
+        let burst = f64::from(limit.burst);
+        let (tokens, refilled_at) = self
+            .buckets
+            .entry(tenant_id.to_string())
+            .or_insert((burst, now));
+        let elapsed = now.duration_since(*refilled_at).as_secs_f64();
+        *tokens = (*tokens + elapsed * limit.requests_per_second).min(burst);
+        *refilled_at = now;
+        if *tokens < 1.0 {
+            return false;
+        }
+        *tokens -= 1.0;
+        true
+    }
+}
+
+/// Rolling count of selections per worker over a time window.
+pub(super) struct SelectionDistribution {
+    pub(super) window: Duration,
+    pub(super) selections: VecDeque<(Instant, WorkerId)>,
+    pub(super) counts: HashMap<WorkerId, usize>,
+    pub(super) dirty: bool,
+    pub(super) tx: tokio::sync::watch::Sender<HashMap<WorkerId, usize>>,
+}
+
+impl SelectionDistribution {
+    pub(super) fn new(
+        window: Duration,
+        tx: tokio::sync::watch::Sender<HashMap<WorkerId, usize>>,
+    ) -> Self {
+        Self {
+            window,
+            selections: VecDeque::new(),
+            counts: HashMap::new(),
+            dirty: false,
+            tx,
+        }
+    }
+
+    pub(super) fn record(&mut self, worker_id: WorkerId, now: Instant) {
+        self.selections.push_back((now, worker_id));
+        *self.counts.entry(worker_id).or_insert(0) += 1;
+        self.dirty = true;
+    }
+
+    pub(super) fn expire(&mut self, now: Instant) {
+        while let Some((at, worker_id)) = self.selections.front().copied() {
+            if now.duration_since(at) < self.window {
+                break;
+            }
+            self.selections.pop_front();
+            if let Some(count) = self.counts.get_mut(&worker_id) {
+                *count -= 1;
+                if *count == 0 {
+                    self.counts.remove(&worker_id);
+                }
+            }
+            self.dirty = true;
+        }
+    }
+
+    /// Publish the current counts if they changed since the last call.
+    pub(super) fn publish(&mut self, now: Instant) {
+        self.expire(now);
+        if self.dirty {
+            self.tx.send_replace(self.counts.clone());
+            self.dirty = false;
+        }
+    }
+}
+
+/// Rolling per-worker KV hit rate (overlap blocks / required blocks) over a time window.
+pub(super) struct HitRateTracker {
+    pub(super) window: Duration,
+    /// (selected at, worker, required blocks, overlap blocks)
+    pub(super) samples: VecDeque<(Instant, WorkerId, u64, f64)>,
+    /// Per-worker sums of required and overlap blocks within the window
+    pub(super) totals: HashMap<WorkerId, (u64, f64)>,
+    pub(super) dirty: bool,
+    pub(super) tx: tokio::sync::watch::Sender<HashMap<WorkerId, f64>>,
+}
+
+impl HitRateTracker {
+    pub(super) fn new(
+        window: Duration,
+        tx: tokio::sync::watch::Sender<HashMap<WorkerId, f64>>,
+    ) -> Self {
+        Self {
+            window,
+            samples: VecDeque::new(),
+            totals: HashMap::new(),
+            dirty: false,
+            tx,
+        }
+    }
+
+    pub(super) fn record(
+        &mut self,
+        worker_id: WorkerId,
+        isl_blocks: u64,
+        overlap_blocks: f64,
+        now: Instant,
+    ) {
+        // The overlap estimate can exceed the request's own blocks
+        let overlap_blocks = overlap_blocks.min(isl_blocks as f64);
+        self.samples
+            .push_back((now, worker_id, isl_blocks, overlap_blocks));
+        let totals = self.totals.entry(worker_id).or_insert((0, 0.0));
+        totals.0 += isl_blocks;
+        totals.1 += overlap_blocks;
+        self.dirty = true;
+    }
+
+    pub(super) fn expire(&mut self, now: Instant) {
+        while let Some((at, worker_id, isl_blocks, overlap_blocks)) = self.samples.front().copied()
+        {
+            if now.duration_since(at) < self.window {
+                break;
+            }
+            self.samples.pop_front();
+            if let Some(totals) = self.totals.get_mut(&worker_id) {
+                totals.0 -= isl_blocks;
+                totals.1 -= overlap_blocks;
+                if totals.0 == 0 {
+                    self.totals.remove(&worker_id);
+                }
+            }
+            self.dirty = true;
+        }
+    }
+
+    /// Hit rate across all workers within the window, if anything was selected in it.
+    pub(super) fn overall(&self) -> Option<f64> {
+        let (isl_blocks, overlap_blocks) =
+            self.totals
+                .values()
+                .fold((0, 0.0), |(isl, overlap), (worker_isl, worker_overlap)| {
+                    (isl + worker_isl, overlap + worker_overlap)
+                });
+        (isl_blocks > 0).then(|| overlap_blocks / isl_blocks as f64)
+    }
+
+    /// Publish the current rates if they changed since the last call.
+    pub(super) fn publish(&mut self, now: Instant) {
+        self.expire(now);
+        if self.dirty {
+            let rates = self
+                .totals
+                .iter()
+                .map(|(worker_id, (isl_blocks, overlap_blocks))| {
+                    (*worker_id, overlap_blocks / *isl_blocks as f64)
+                })
+                .collect();
+            self.tx.send_replace(rates);
+            self.dirty = false;
+        }
+    }
+}
+
+/// Remembers which worker each recently seen prefix hash was routed to.
+pub(super) struct PrefixCoalescer {
+    pub(super) window: Duration,
+    pub(super) assignments: HashMap<u64, (WorkerId, Instant)>,
+}
+
+impl PrefixCoalescer {
+    pub(super) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            assignments: HashMap::new(),
+        }
+    }
+
+    /// The worker `prefix_hash` was first routed to, if that was within the window.
+    pub(super) fn lookup(&self, prefix_hash: u64, now: Instant) -> Option<WorkerId> {
+        let (worker_id, at) = self.assignments.get(&prefix_hash)?;
+        (now.duration_since(*at) < self.window).then_some(*worker_id)
+    }
+
+    pub(super) fn record(&mut self, prefix_hash: u64, worker_id: WorkerId, now: Instant) {
+        if !self.window.is_zero() {
+            self.assignments.insert(prefix_hash, (worker_id, now));
+        }
+    }
+
+    pub(super) fn expire(&mut self, now: Instant) {
+        self.assignments
+            .retain(|_, (_, at)| now.duration_since(*at) < self.window);
+    }
+}
+
+/// Remembers which worker each recently active session was last routed to.
+pub(super) struct SessionAffinity {
+    pub(super) ttl: Duration,
+    pub(super) sessions: HashMap<String, (WorkerId, Instant)>,
+}
+
+impl SessionAffinity {
+    pub(super) fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            sessions: HashMap::new(),
+        }
+    }
+
+    /// The worker `session_id` was last routed to, if that was within the TTL.
+    pub(super) fn lookup(&self, session_id: &str, now: Instant) -> Option<WorkerId> {
+        let (worker_id, at) = self.sessions.get(session_id)?;
+        (now.duration_since(*at) < self.ttl).then_some(*worker_id)
+    }
+
+    pub(super) fn record(&mut self, session_id: &str, worker_id: WorkerId, now: Instant) {
+        if !self.ttl.is_zero() {
+            self.sessions
+                .insert(session_id.to_string(), (worker_id, now));
+        }
+    }
+
+    pub(super) fn expire(&mut self, now: Instant) {
+        self.sessions
+            .retain(|_, (_, at)| now.duration_since(*at) < self.ttl);
+    }
+
+    /// The sessions still within the TTL.
+    pub(super) fn export(&self, now: Instant) -> HashMap<String, SessionAssignment> {
+        self.sessions
+            .iter()
+            .filter(|(_, (_, at))| now.duration_since(*at) < self.ttl)
+            .map(|(session_id, (worker_id, at))| {
+                let assignment = SessionAssignment {
+                    worker_id: *worker_id,
+                    idle_ms: now.duration_since(*at).as_millis() as u64,
+                };
+                (session_id.clone(), assignment)
+            })
+            .collect()
+    }
+
+    /// Resume a session exported by [`SessionAffinity::export`].
+    pub(super) fn restore(
+        &mut self,
+        session_id: String,
+        assignment: SessionAssignment,
+        now: Instant,
+    ) {
+        let at = now
+            .checked_sub(Duration::from_millis(assignment.idle_ms))
+            .unwrap_or(now);
+        self.sessions.insert(session_id, (assignment.worker_id, at));
+    }
+}
+
+/// Selections by idempotency key, so a retried request gets the workers of its first attempt.
+pub(super) struct IdempotencyCache {
+    pub(super) window: Duration,
+    pub(super) keys: HashMap<String, (WorkerId, Option<WorkerId>, Instant)>,
+}
+
+impl IdempotencyCache {
+    pub(super) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            keys: HashMap::new(),
+        }
+    }
+
+    /// The workers `key` was scheduled on, if that was within the window.
+    pub(super) fn lookup(&self, key: &str, now: Instant) -> Option<(WorkerId, Option<WorkerId>)> {
+        let (worker_id, secondary, at) = self.keys.get(key)?;
+        (now.duration_since(*at) < self.window).then_some((*worker_id, *secondary))
+    }
+
+    pub(super) fn record(
+        &mut self,
+        key: &str,
+        worker_id: WorkerId,
+        secondary: Option<WorkerId>,
+        now: Instant,
+    ) {
+        if !self.window.is_zero() {
+            self.keys
+                .insert(key.to_string(), (worker_id, secondary, now));
+        }
+    }
+
+    pub(super) fn expire(&mut self, now: Instant) {
+        self.keys
+            .retain(|_, (_, _, at)| now.duration_since(*at) < self.window);
+    }
+}
+
+/// When each worker joined, for the warmup penalty.
+pub(super) struct WarmupTracker {
+    pub(super) duration: Duration,
+    /// `None` for workers already present when the scheduler started, which are never warming up
+    pub(super) first_seen: HashMap<WorkerId, Option<Instant>>,
+}
+
+impl WarmupTracker {
+    pub(super) fn new(duration: Duration, initial: &ProcessedEndpoints) -> Self {
+        Self {
+            duration,
+            first_seen: initial.endpoints.keys().map(|id| (*id, None)).collect(),
+        }
+    }
+
+    /// Note workers that joined, and forget those that left so they warm up again on return.
+    pub(super) fn observe(&mut self, endpoints: &ProcessedEndpoints, now: Instant) {
+        self.first_seen
+            .retain(|worker_id, _| endpoints.endpoints.contains_key(worker_id));
+        for worker_id in endpoints.endpoints.keys() {
+            self.first_seen.entry(*worker_id).or_insert(Some(now));
+        }
+    }
+
+    /// Fraction of warmup left for each worker still warming up.
+    pub(super) fn remaining(&self, now: Instant) -> HashMap<WorkerId, f64> {
+        if self.duration.is_zero() {
+            return HashMap::new();
+        }
+        self.first_seen
+            .iter()
+            .filter_map(|(worker_id, first_seen)| {
+                let elapsed = now.duration_since((*first_seen)?);
+                (elapsed < self.duration).then(|| {
+                    (
+                        *worker_id,
+                        1.0 - elapsed.as_secs_f64() / self.duration.as_secs_f64(),
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+/// Recent polled waiting counts per worker, for [`KvRouterConfig::waiting_percentile`].
+///
+/// [`KvRouterConfig::waiting_percentile`]: crate::kv_router::KvRouterConfig::waiting_percentile
+pub(super) struct WaitingSamples {
+    pub(super) window: usize,
+    pub(super) samples: HashMap<WorkerId, VecDeque<u64>>,
+}
+
+impl WaitingSamples {
+    pub(super) fn new(window: usize) -> Self {
+        Self {
+            window,
+            samples: HashMap::new(),
+        }
+    }
+
+    /// Record a poll's waiting counts, and forget workers that left so they start afresh on
+    /// return.
+    pub(super) fn observe(&mut self, endpoints: &ProcessedEndpoints) {
+        self.samples
+            .retain(|worker_id, _| endpoints.endpoints.contains_key(worker_id));
+        for (worker_id, ep) in &endpoints.endpoints {
+            let samples = self.samples.entry(*worker_id).or_default();
+            samples.push_back(ep.data.num_requests_waiting);
+            while samples.len() > self.window {
+                samples.pop_front();
+            }
+        }
+    }
+
+    /// The nearest-rank `percentile` of each worker's samples.
+    pub(super) fn percentiles(&self, percentile: f64) -> HashMap<WorkerId, f64> {
+        self.samples
+            .iter()
+            .map(|(worker_id, samples)| {
+                let mut sorted: Vec<u64> = samples.iter().copied().collect();
+                sorted.sort_unstable();
+                let rank =
+                    ((percentile * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len());
+                (*worker_id, sorted[rank - 1] as f64)
+            })
+            .collect()
+    }
+}
+
+/// Recent selections per tenant, for the fairness penalty.
+pub(super) struct FairnessTracker {
+    pub(super) window: Duration,
+    pub(super) selections: VecDeque<(Instant, String, WorkerId)>,
+    pub(super) per_tenant: HashMap<String, usize>,
+    pub(super) per_worker: HashMap<WorkerId, usize>,
+}
+
+impl FairnessTracker {
+    pub(super) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            selections: VecDeque::new(),
+            per_tenant: HashMap::new(),
+            per_worker: HashMap::new(),
+        }
+    }
+
+    pub(super) fn record(&mut self, tenant_id: &str, worker_id: WorkerId, now: Instant) {
+        self.selections
+            .push_back((now, tenant_id.to_string(), worker_id));
+        *self.per_tenant.entry(tenant_id.to_string()).or_insert(0) += 1;
+        *self.per_worker.entry(worker_id).or_insert(0) += 1;
+    }
+
+    pub(super) fn expire(&mut self, now: Instant) {
+        while let Some((at, _, _)) = self.selections.front() {
+            if now.duration_since(*at) < self.window {
+                break;
+            }
+            let (_, tenant_id, worker_id) = self.selections.pop_front().unwrap();
+            decrement(&mut self.per_tenant, tenant_id);
+            decrement(&mut self.per_worker, worker_id);
+        }
+    }
+
+    /// Penalty on each worker for `tenant_id`: how far the tenant is over an equal share of
+    /// recent selections, scaled by each worker's share of them.
+    pub(super) fn penalties(&mut self, tenant_id: &str, now: Instant) -> HashMap<WorkerId, f64> {
+        self.expire(now);
+        let own = self.per_tenant.get(tenant_id).copied().unwrap_or(0);
+        if own == 0 {
+            return HashMap::new();
+        }
+        let total = self.selections.len() as f64;
+        let fair_share = 1.0 / self.per_tenant.len() as f64;
+        let excess = own as f64 / total - fair_share;
+        if excess <= 0.0 {
+            return HashMap::new();
+        }
+        self.per_worker
+            .iter()
+            .map(|(worker_id, count)| (*worker_id, excess * *count as f64 / total))
+            .collect()
+    }
+}
+
+/// Most streams [`OscillationTracker`] remembers the last worker of. It forgets them all when
+/// more are seen.
+pub(super) const MAX_OSCILLATION_STREAMS: usize = 4096;
+
+/// How often consecutive selections of a request stream switch workers.
+pub(super) struct OscillationTracker {
+    pub(super) window: usize,
+    /// Worker each stream was last routed to. Requests without a stream id share `None`.
+    pub(super) last: HashMap<Option<String>, WorkerId>,
+    /// Whether each recent consecutive pair of a stream's selections switched workers, oldest
+    /// first
+    pub(super) pairs: VecDeque<bool>,
+    pub(super) switches: usize,
+    /// Whether the rate is above the warning threshold, so it is only logged on crossing it
+    pub(super) warned: bool,
+    pub(super) rate_tx: tokio::sync::watch::Sender<f64>,
+}
+
+impl OscillationTracker {
+    pub(super) fn new(window: usize, rate_tx: tokio::sync::watch::Sender<f64>) -> Self {
+        Self {
+            window,
+            last: HashMap::new(),
+            pairs: VecDeque::new(),
+            switches: 0,
+            warned: false,
+            rate_tx,
+        }
+    }
+
+    /// Record that the next request of `stream_id` went to `worker_id`, and publish the rate.
+    pub(super) fn record(
+        &mut self,
+        stream_id: Option<&str>,
+        worker_id: WorkerId,
+        warn_threshold: f64,
+    ) {
+        let stream_id = stream_id.map(str::to_string);
+        if self.last.len() >= MAX_OSCILLATION_STREAMS && !self.last.contains_key(&stream_id) {
+            self.last.clear();
+        }
+        let Some(previous) = self.last.insert(stream_id, worker_id) else {
+            return;
+        };
+        let switched = previous != worker_id;
+        self.pairs.push_back(switched);
+        self.switches += switched as usize;
+        while self.pairs.len() > self.window {
+            if self.pairs.pop_front() == Some(true) {
+                self.switches -= 1;
+            }
+        }
+
+        let rate = self.switches as f64 / self.pairs.len() as f64;
+        let above = self.pairs.len() == self.window && rate > warn_threshold;
+        if above && !self.warned {
+            tracing::warn!(
+                "routing is oscillating: {:.0}% of the last {} consecutive selections of a stream switched workers",
+                rate * 100.0,
+                self.window,
+            );
+        }
+        self.warned = above;
+        self.rate_tx.send_if_modified(|current| {
+            let changed = *current != rate;
+            *current = rate;
+            changed
+        });
+    }
+}
+
+/// Recent selections per tenant with a soft quota.
+pub(super) struct QuotaTracker {
+    pub(super) window: Duration,
+    pub(super) selections: VecDeque<(Instant, String)>,
+    pub(super) per_tenant: HashMap<String, u64>,
+}
+
+impl QuotaTracker {
+    pub(super) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            selections: VecDeque::new(),
+            per_tenant: HashMap::new(),
+        }
+    }
+
+    pub(super) fn record(&mut self, tenant_id: &str, now: Instant) {
+        self.selections.push_back((now, tenant_id.to_string()));
+        *self.per_tenant.entry(tenant_id.to_string()).or_insert(0) += 1;
+    }
+
+    pub(super) fn expire(&mut self, now: Instant) {
+        while let Some((at, _)) = self.selections.front() {
+            if now.duration_since(*at) < self.window {
+                break;
+            }
+            let (_, tenant_id) = self.selections.pop_front().unwrap();
+            if let Some(count) = self.per_tenant.get_mut(&tenant_id) {
+                *count -= 1;
+                if *count == 0 {
+                    self.per_tenant.remove(&tenant_id);
+                }
+            }
+        }
+    }
+
+    /// How far one more selection would take `tenant_id` past `quota`, as a fraction of it.
+    ///
+    /// [`KvRouterConfig::validate`] rejects a zero quota, but a config handed straight to the
+    /// builder or a selector isn't validated, so one is counted as a quota of one.
+    ///
+    /// [`KvRouterConfig::validate`]: crate::kv_router::KvRouterConfig::validate
+    pub(super) fn overage(&mut self, tenant_id: &str, quota: u64, now: Instant) -> f64 {
+        self.expire(now);
+        let used = self.per_tenant.get(tenant_id).copied().unwrap_or(0);
+        (used + 1).saturating_sub(quota) as f64 / quota.max(1) as f64
+    }
+}
+
+pub(super) fn decrement<K: std::hash::Hash + Eq>(counts: &mut HashMap<K, usize>, key: K) {
+    if let Some(count) = counts.get_mut(&key) {
+        *count -= 1;
+        if *count == 0 {
+            counts.remove(&key);
+        }
+    }
+}
+
+/// LRU cache of overlap scores by prefix hash, with entries expiring a fixed time after insertion.
+pub(super) struct OverlapCache {
+    pub(super) capacity: usize,
+    pub(super) ttl: Duration,
+    pub(super) entries: HashMap<u64, CachedOverlap>,
+    /// Access order, oldest first. Entries superseded by a later access are skipped lazily.
+    pub(super) order: VecDeque<(u64, u64)>,
+    pub(super) tick: u64,
+}
+
+pub(super) struct CachedOverlap {
+    pub(super) scores: OverlapScores,
+    pub(super) cached_at: Instant,
+    pub(super) last_used: u64,
+}
+
+impl OverlapCache {
+    pub(super) fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            tick: 0,
+        }
+    }
+
+    /// Replace the request's overlap with the cached one for its prefix, or cache the overlap it
+    /// carries if there is none.
+    pub(super) fn resolve(&mut self, request: &mut SchedulingRequest, now: Instant) {
+        let Some(prefix_hash) = request.prefix_hash else {
+            return;
+        };
+        if self.capacity == 0 {
+            return;
+        }
+        match self.get(prefix_hash, now) {
+            Some(scores) => request.overlap = scores.clone(),
+            None => self.insert(prefix_hash, request.overlap.clone(), now),
+        }
+    }
+
+    pub(super) fn get(&mut self, prefix_hash: u64, now: Instant) -> Option<&OverlapScores> {
+        let expired = now.duration_since(self.entries.get(&prefix_hash)?.cached_at) >= self.ttl;
+        if expired {
+            self.entries.remove(&prefix_hash);
+            return None;
+        }
+        let tick = self.next_tick();
+        self.order.push_back((prefix_hash, tick));
+        let entry = self.entries.get_mut(&prefix_hash)?;
+        entry.last_used = tick;
+        Some(&entry.scores)
+    }
+
+    pub(super) fn insert(&mut self, prefix_hash: u64, scores: OverlapScores, now: Instant) {
+        while self.entries.len() >= self.capacity && !self.entries.contains_key(&prefix_hash) {
+            if !self.evict() {
+                break;
+            }
+        }
+        let tick = self.next_tick();
+        self.order.push_back((prefix_hash, tick));
+        self.entries.insert(
+            prefix_hash,
+            CachedOverlap {
+                scores,
+                cached_at: now,
+                last_used: tick,
+            },
+        );
+        // Drop superseded order entries so the queue stays proportional to the cache
+        if self.order.len() > 2 * self.capacity {
+            let entries = &self.entries;
+            self.order.retain(|(prefix_hash, tick)| {
+                entries
+                    .get(prefix_hash)
+                    .is_some_and(|entry| entry.last_used == *tick)
+            });
+        }
+    }
+
+    /// Evict the least recently used entry, returning whether there was one.
+    pub(super) fn evict(&mut self) -> bool {
+        while let Some((prefix_hash, tick)) = self.order.pop_front() {
+            if self
+                .entries
+                .get(&prefix_hash)
+                .is_some_and(|entry| entry.last_used == tick)
+            {
+                self.entries.remove(&prefix_hash);
+                return true;
+            }
+        }
+        false
+    }
+
+    pub(super) fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+/// Exponential decay of the predicted KV block load toward the last polled value.
+pub(super) struct PredictionDecay {
+    pub(super) half_life: Option<Duration>,
+    pub(super) last_applied: Instant,
+    /// Fractional blocks carried between applications, so small deltas still decay
+    pub(super) residual: HashMap<WorkerId, f64>,
+}
+
+impl PredictionDecay {
+    pub(super) fn new(half_life: Option<Duration>) -> Self {
+        Self {
+            half_life,
+            last_applied: Instant::now(),
+            residual: HashMap::new(),
+        }
+    }
+
+    /// Start over from freshly polled metrics.
+    pub(super) fn reset(&mut self, now: Instant) {
+        self.last_applied = now;
+        self.residual.clear();
+    }
+
+    /// Decay each worker's predicted delta over the time since the last call, returning whether
+    /// any changed.
+    pub(super) fn apply(
+        &mut self,
+        predicted: &mut ProcessedEndpoints,
+        polled: &ProcessedEndpoints,
+        now: Instant,
+    ) -> bool {
+        let Some(half_life) = self.half_life else {
+            return false;
+        };
+        let elapsed = now.duration_since(self.last_applied);
+        self.last_applied = now;
+        let factor = 0.5f64.powf(elapsed.as_secs_f64() / half_life.as_secs_f64());
+
+        let mut changed = false;
+        for (worker_id, endpoint) in predicted.endpoints.iter_mut() {
+            let Some(polled) = polled.endpoints.get(worker_id) else {
+                continue;
+            };
+            let residual = self.residual.remove(worker_id).unwrap_or(0.0);
+            let delta = endpoint.data.kv_active_blocks as f64 - polled.data.kv_active_blocks as f64
+                + residual;
+            if delta == 0.0 {
+                continue;
+            }
+            let decayed = delta * factor;
+            let whole = decayed.trunc();
+            let kv_active_blocks = (polled.data.kv_active_blocks as f64 + whole).max(0.0) as u64;
+            changed |= kv_active_blocks != endpoint.data.kv_active_blocks;
+            endpoint.data.kv_active_blocks = kv_active_blocks;
+            if decayed != whole {
+                self.residual.insert(*worker_id, decayed - whole);
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throughput_tracker_ema() {
+        let mut tracker = ThroughputTracker::default();
+        let start = Instant::now();
+
+        // A single completion has no rate yet
+        tracker.record(WorkerId(1), start);
+        assert!(tracker.rates.is_empty());
+
+        // Completions every 100ms -> 10 per second
+        tracker.record(WorkerId(1), start + std::time::Duration::from_millis(100));
+        assert!((tracker.rates[&1] - 10.0).abs() < 1e-6);
+        tracker.record(WorkerId(1), start + std::time::Duration::from_millis(200));
+        assert!((tracker.rates[&1] - 10.0).abs() < 1e-6);
+
+        // A slower completion pulls the average down, but only by alpha
+        tracker.record(WorkerId(1), start + std::time::Duration::from_millis(400));
+        let expected = THROUGHPUT_EMA_ALPHA * 5.0 + (1.0 - THROUGHPUT_EMA_ALPHA) * 10.0;
+        assert!((tracker.rates[&1] - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_quota_overage() {
+        let start = Instant::now();
+        let mut tracker = QuotaTracker::new(Duration::from_secs(60));
+        assert_eq!(tracker.overage("tenant", 2, start), 0.0);
+        for _ in 0..3 {
+            tracker.record("tenant", start);
+        }
+        assert_eq!(tracker.overage("tenant", 2, start), 1.0);
+
+        // A zero quota doesn't divide by zero
+        assert_eq!(tracker.overage("tenant", 0, start), 4.0);
+        assert_eq!(tracker.overage("other", 0, start), 1.0);
+
+        // Selections older than the window no longer count
+        let later = start + Duration::from_secs(60);
+        assert_eq!(tracker.overage("tenant", 2, later), 0.0);
+    }
+
+    #[test]
+    fn test_selection_distribution_window() {
+        let (tx, rx) = tokio::sync::watch::channel(HashMap::new());
+        let mut distribution = SelectionDistribution::new(Duration::from_secs(10), tx);
+        let start = Instant::now();
+
+        distribution.record(WorkerId(1), start);
+        distribution.record(WorkerId(2), start + Duration::from_secs(5));
+        distribution.publish(start + Duration::from_secs(5));
+        assert_eq!(
+            *rx.borrow(),
+            HashMap::from([(WorkerId(1), 1), (WorkerId(2), 1)])
+        );
+
+        // The first selection falls out of the window
+        distribution.publish(start + Duration::from_secs(12));
+        assert_eq!(*rx.borrow(), HashMap::from([(WorkerId(2), 1)]));
+    }
+
+    #[test]
+    fn test_hit_rate_tracker() {
+        let (tx, rx) = tokio::sync::watch::channel(HashMap::new());
+        let mut hit_rates = HitRateTracker::new(Duration::from_secs(10), tx);
+        let start = Instant::now();
+
+        hit_rates.record(WorkerId(1), 10, 5.0, start);
+        hit_rates.record(WorkerId(1), 10, 10.0, start + Duration::from_secs(5));
+        hit_rates.record(WorkerId(2), 4, 1.0, start + Duration::from_secs(5));
+        // Overlap is capped at the request's own blocks
+        hit_rates.record(WorkerId(3), 4, 6.0, start + Duration::from_secs(5));
+        hit_rates.publish(start + Duration::from_secs(5));
+        assert_eq!(
+            *rx.borrow(),
+            HashMap::from([(WorkerId(1), 0.75), (WorkerId(2), 0.25), (WorkerId(3), 1.0)])
+        );
+
+        // The first sample falls out of the window
+        hit_rates.publish(start + Duration::from_secs(12));
+        assert_eq!(rx.borrow()[&1], 1.0);
+
+        // So does everything else
+        hit_rates.publish(start + Duration::from_secs(20));
+        assert!(rx.borrow().is_empty());
+    }
+}
@@ -17,21 +17,93 @@
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use tokio::time::Instant;
 
-use crate::kv_router::scheduler::Endpoint;
+use crate::kv_router::protocols::WorkerId;
+use crate::kv_router::scheduler::{Endpoint, KvSchedulerError};
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct ProcessedEndpoints {
-    pub endpoints: HashMap<i64, Endpoint>,
+    pub endpoints: HashMap<WorkerId, Endpoint>,
     pub load_avg: f64,
     pub load_std: f64,
+    /// Completions per second for each worker, as observed by the scheduler.
+    /// Workers without any reported completions are absent.
+    #[serde(default)]
+    pub throughput: HashMap<WorkerId, f64>,
+    /// Fraction of its warmup each worker has left, from 1.0 when it joined down to 0.0.
+    /// Warm workers are absent.
+    #[serde(default)]
+    pub warmup: HashMap<WorkerId, f64>,
+    /// Unweighted fairness penalty on each worker for the tenant of the request being scheduled.
+    /// Workers without a penalty are absent.
+    #[serde(default)]
+    pub fairness: HashMap<WorkerId, f64>,
+    /// Percentile of each worker's recent polled waiting counts, raised by predictive updates
+    /// like `num_requests_waiting`. Scored in place of `num_requests_waiting` where present.
+    #[serde(default)]
+    pub waiting_percentile: HashMap<WorkerId, f64>,
+    /// Topology distance from the zone of the request being scheduled to each worker.
+    /// Workers without a known distance are absent.
+    #[serde(default)]
+    pub locality: HashMap<WorkerId, f64>,
+    /// How far the current request's tenant is over its soft quota, as a fraction of the quota.
+    /// 0 within the quota.
+    #[serde(default)]
+    pub quota_overage: f64,
+    /// When each worker was last selected, set by predictive updates for the selection
+    /// cooldown. Workers not selected since the scheduler started are absent.
+    #[serde(skip)]
+    pub last_selected: HashMap<WorkerId, Instant>,
 }
 
 impl ProcessedEndpoints {
+    /// Index `endpoints` by worker id.
+    ///
+    /// Worker ids come from the last segment of the subject, so distinct subjects can collide.
+    /// The endpoint with the lowest subject keeps the id and the others are dropped with a
+    /// warning rather than merged into it.
     pub fn new(endpoints: Vec<Endpoint>) -> Self {
+        let (processed, collisions) = Self::build(endpoints);
+        for collision in collisions {
+            tracing::warn!("dropping endpoint: {collision}");
+        }
+        processed
+    }
+
+    /// Like [`ProcessedEndpoints::new`], but fails on the first worker id collision.
+    pub fn try_new(endpoints: Vec<Endpoint>) -> Result<Self, KvSchedulerError> {
+        let (processed, collisions) = Self::build(endpoints);
+        match collisions.into_iter().next() {
+            Some(collision) => Err(collision),
+            None => Ok(processed),
+        }
+    }
+
+    fn build(mut endpoints: Vec<Endpoint>) -> (Self, Vec<KvSchedulerError>) {
+        // Stable order so the same endpoint wins a collision on every poll
+        endpoints.sort_by(|a, b| a.subject.cmp(&b.subject));
+        let mut collisions = Vec::new();
+        let mut by_id: HashMap<WorkerId, Endpoint> = HashMap::with_capacity(endpoints.len());
+        for endpoint in endpoints {
+            let worker_id = endpoint.worker_id();
+            match by_id.get(&worker_id) {
+                Some(kept) if kept.subject != endpoint.subject => {
+                    collisions.push(KvSchedulerError::WorkerIdCollision {
+                        worker_id,
+                        kept: kept.subject.clone(),
+                        dropped: endpoint.subject,
+                    });
+                }
+                _ => {
+                    by_id.insert(worker_id, endpoint);
+                }
+            }
+        }
+
         // compute some basic statistics
-        let load_values: Vec<f64> = endpoints
-            .iter()
+        let load_values: Vec<f64> = by_id
+            .values()
             .map(|x| x.data.kv_active_blocks as f64)
             .collect();
         let load_avg = load_values.iter().copied().sum::<f64>() / load_values.len() as f64;
@@ -42,12 +114,76 @@ impl ProcessedEndpoints {
             / load_values.len() as f64;
         let load_std = variance.sqrt();
 
-        let endpoints = endpoints.into_iter().map(|e| (e.worker_id(), e)).collect();
-
-        ProcessedEndpoints {
-            endpoints,
+        let processed = ProcessedEndpoints {
+            endpoints: by_id,
             load_avg,
             load_std,
+            throughput: HashMap::new(),
+            warmup: HashMap::new(),
+            fairness: HashMap::new(),
+            waiting_percentile: HashMap::new(),
+            locality: HashMap::new(),
+            quota_overage: 0.0,
+            last_selected: HashMap::new(),
+        };
+        (processed, collisions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_router::protocols::ForwardPassMetrics;
+
+    fn endpoint(subject: &str, kv_active_blocks: u64) -> Endpoint {
+        Endpoint {
+            name: subject.to_string(),
+            subject: subject.to_string(),
+            data: ForwardPassMetrics {
+                kv_active_blocks,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_worker_id_collision() {
+        // Both subjects end in the same hex segment
+        let endpoints = vec![
+            endpoint("ns.component.generate-b", 20),
+            endpoint("ns.other.generate-b", 10),
+            endpoint("ns.component.generate-c", 30),
+        ];
+
+        let processed = ProcessedEndpoints::new(endpoints.clone());
+        assert_eq!(processed.endpoints.len(), 2);
+        let kept = &processed.endpoints[&0xb];
+        assert_eq!(kept.subject, "ns.component.generate-b");
+        // The dropped endpoint's metrics weren't merged in, nor counted in the load statistics
+        assert_eq!(kept.data.kv_active_blocks, 20);
+        assert_eq!(processed.load_avg, 25.0);
+
+        match ProcessedEndpoints::try_new(endpoints) {
+            Err(KvSchedulerError::WorkerIdCollision {
+                worker_id,
+                kept,
+                dropped,
+            }) => {
+                assert_eq!(worker_id, 0xb);
+                assert_eq!(kept, "ns.component.generate-b");
+                assert_eq!(dropped, "ns.other.generate-b");
+            }
+            other => panic!("Should return WorkerIdCollision, got {other:?}"),
         }
     }
+
+    #[test]
+    fn test_repeated_subject_is_not_a_collision() {
+        let endpoints = vec![
+            endpoint("ns.component.generate-b", 20),
+            endpoint("ns.component.generate-b", 10),
+        ];
+        let processed = ProcessedEndpoints::try_new(endpoints).unwrap();
+        assert_eq!(processed.endpoints[&0xb].data.kv_active_blocks, 10);
+    }
 }
@@ -0,0 +1,161 @@
+// SPDX-FileCopyrightText: Copyright (c) 2024-2025 NVIDIA CORPORATION & AFFILIATES. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! [`tower::Service`] adapter for [`KvScheduler`], so it can be composed with tower middleware
+//! such as timeouts, concurrency limits and load shedding.
+
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::future::BoxFuture;
+use tokio_util::sync::PollSender;
+
+use super::indexer::OverlapScores;
+use super::protocols::WorkerId;
+use super::scheduler::{
+    KvScheduler, KvSchedulerError, Priority, ScheduleOptions, SchedulingRequest,
+};
+
+/// A request to [`KvSchedulerService`].
+#[derive(Debug, Clone, Default)]
+pub struct ScheduleRequest {
+    pub overlap: OverlapScores,
+    pub isl_tokens: usize,
+    pub options: ScheduleOptions,
+}
+
+/// A [`tower::Service`] that schedules requests on a [`KvScheduler`] and resolves to the
+/// selected worker id.
+///
+/// The service is ready only while the high priority scheduling queue has a free slot, which
+/// reserves that slot for the next call. Low priority requests release the reservation and wait
+/// for room in their own queue instead.
+#[derive(Clone)]
+pub struct KvSchedulerService {
+    scheduler: Arc<KvScheduler>,
+    sender: PollSender<SchedulingRequest>,
+    low_priority_sender: tokio::sync::mpsc::Sender<SchedulingRequest>,
+}
+
+impl KvSchedulerService {
+    pub fn new(scheduler: Arc<KvScheduler>) -> Self {
+        let sender = PollSender::new(scheduler.request_sender(Priority::High));
+        let low_priority_sender = scheduler.request_sender(Priority::Low);
+        Self {
+            scheduler,
+            sender,
+            low_priority_sender,
+        }
+    }
+}
+
+impl tower::Service<ScheduleRequest> for KvSchedulerService {
+    type Response = WorkerId;
+    type Error = KvSchedulerError;
+    type Future = BoxFuture<'static, Result<WorkerId, KvSchedulerError>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.sender
+            .poll_reserve(cx)
+            .map_err(|_| KvSchedulerError::SubscriberShutdown)
+    }
+
+    fn call(&mut self, req: ScheduleRequest) -> Self::Future {
+        let (request, resp_rx) =
+            match self
+                .scheduler
+                .new_request(req.overlap, req.isl_tokens, req.options)
+            {
+                Ok(pending) => pending,
+                Err(e) => {
+                    // Release the slot reserved by poll_ready
+                    self.sender.abort_send();
+                    return Box::pin(async move { Err(e) });
+                }
+            };
+        let low_priority_sender = match request.priority {
+            Priority::High => {
+                if self.sender.send_item(request).is_err() {
+                    return Box::pin(async { Err(KvSchedulerError::SubscriberShutdown) });
+                }
+                None
+            }
+            Priority::Low => {
+                self.sender.abort_send();
+                Some((self.low_priority_sender.clone(), request))
+            }
+        };
+
+        Box::pin(async move {
+            if let Some((sender, request)) = low_priority_sender {
+                sender
+                    .send(request)
+                    .await
+                    .map_err(|_| KvSchedulerError::SubscriberShutdown)?;
+            }
+            let scheduled = resp_rx
+                .await
+                .map_err(|_| KvSchedulerError::SubscriberShutdown)??;
+            Ok(scheduled.worker_id)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv_router::scheduler::{Endpoint, EventSink, ForwardPassMetrics, SchedulerEvent};
+    use crate::kv_router::scoring::ProcessedEndpoints;
+    use async_trait::async_trait;
+    use tower::ServiceExt;
+
+    struct NullSink;
+
+    #[async_trait]
+    impl EventSink for NullSink {
+        async fn publish(&self, _event: &SchedulerEvent) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_service_oneshot() {
+        let workers = ProcessedEndpoints::new(vec![Endpoint {
+            name: "worker-1".to_string(),
+            subject: "worker-subject-1".to_string(),
+            data: ForwardPassMetrics::default(),
+        }]);
+        let (_endpoints_tx, endpoints_rx) = tokio::sync::watch::channel(workers);
+        let scheduler = KvScheduler::builder()
+            .event_sink(NullSink)
+            .block_size(20)
+            .endpoints(endpoints_rx)
+            .start()
+            .await
+            .expect("Should start scheduler");
+        let service = KvSchedulerService::new(Arc::new(scheduler));
+
+        let request = ScheduleRequest {
+            isl_tokens: 100,
+            ..Default::default()
+        };
+        let worker_id = service.clone().oneshot(request).await.unwrap();
+        assert_eq!(worker_id, 1);
+
+        // Invalid requests fail without consuming the queue
+        let result = service.oneshot(ScheduleRequest::default()).await;
+        assert!(matches!(result, Err(KvSchedulerError::InvalidRequest(_))));
+    }
+}
@@ -40,7 +40,7 @@
 //! ## NOTE
 //! The current prefill and decoding time simulations are not scientific at all and are WIP
 
-use crate::kv_router::protocols::ForwardPassMetrics;
+use crate::kv_router::protocols::{ForwardPassMetrics, WorkerRole};
 use crate::mocker::evictor::LRUEvictor;
 use crate::mocker::kv_manager::KvManager;
 use crate::mocker::protocols::DirectRequest;
@@ -401,6 +401,10 @@ impl Scheduler {
             num_requests_waiting: state.waiting.len() as u64,
             gpu_cache_usage_perc,
             gpu_prefix_cache_hit_rate: 0.0, // Placeholder value as specified
+            role: WorkerRole::Unified,
+            kv_block_size: None,
+            max_concurrent: None,
+            oldest_waiting_age_ms: None,
         }
     }
 }